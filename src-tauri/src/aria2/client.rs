@@ -0,0 +1,596 @@
+//! Aria2 JSON-RPC client
+//!
+//! Independent of Tauri: notifications and connection changes are published
+//! on a broadcast channel (see `Aria2Client::subscribe`), so the client can be
+//! used from headless tools and tests.
+
+use super::sync;
+use super::transport::{self, RpcError, RpcRequest};
+use super::{FileServers, GlobalStat, Options, Peer, SessionInfo, Task, TaskFile, TaskUri, UriChange};
+use crate::config::RpcScheme;
+use crate::{Error, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::{broadcast, mpsc, oneshot};
+
+/// Capacity of the event channel; slow subscribers skip older events
+const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// How long a call waits for its response before giving up
+const DEFAULT_REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Aria2 event types
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Aria2Event {
+    pub event_type: Aria2EventType,
+    pub gid: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Aria2EventType {
+    DownloadStart,
+    DownloadPause,
+    DownloadStop,
+    DownloadComplete,
+    DownloadError,
+    BtDownloadComplete,
+}
+
+/// Aria2 notification pushed over WebSocket
+#[derive(Debug, Deserialize)]
+pub(super) struct Aria2Notification {
+    method: String,
+    params: Vec<NotificationParam>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NotificationParam {
+    gid: String,
+}
+
+/// Location and credentials of an aria2 JSON-RPC server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RpcEndpoint {
+    pub scheme: RpcScheme,
+    pub host: String,
+    pub port: u16,
+    pub secret: String,
+}
+
+impl RpcEndpoint {
+    /// Endpoint of the bundled sidecar listening on localhost
+    pub fn local(port: u16, secret: String) -> Self {
+        Self {
+            scheme: RpcScheme::Ws,
+            host: "127.0.0.1".to_string(),
+            port,
+            secret,
+        }
+    }
+
+    /// JSON-RPC URL, e.g. `ws://127.0.0.1:6800/jsonrpc`
+    pub fn url(&self) -> String {
+        // Bracket bare IPv6 literals
+        let host = if self.host.contains(':') && !self.host.starts_with('[') {
+            format!("[{}]", self.host)
+        } else {
+            self.host.clone()
+        };
+        format!("{}://{}:{}/jsonrpc", self.scheme.as_str(), host, self.port)
+    }
+}
+
+/// Aria2 RPC client
+pub struct Aria2Client {
+    secret: String,
+    sender: mpsc::Sender<RpcRequest>,
+    events: broadcast::Sender<ClientEvent>,
+    request_timeout: std::time::Duration,
+}
+
+/// Event published by `Aria2Client` to its subscribers
+#[derive(Debug, Clone)]
+pub enum ClientEvent {
+    /// A task notification, pushed by aria2 or synthesized from polling
+    Task(Aria2Event),
+    /// The connection to aria2 was lost or restored
+    Connection(ConnectionState),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectionState {
+    Connected,
+    Disconnected,
+}
+
+impl Aria2Client {
+    /// Create a new aria2 client
+    ///
+    /// WebSocket endpoints receive pushed notifications and replay the ones
+    /// missed while reconnecting; HTTP endpoints have no push channel, so task
+    /// status changes are polled instead.
+    pub async fn new(endpoint: RpcEndpoint) -> Result<Self> {
+        let url = endpoint.url();
+        let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        match endpoint.scheme {
+            RpcScheme::Ws | RpcScheme::Wss => {
+                let sender = transport::spawn_websocket(url, events.clone()).await?;
+                let client = Self::from_parts(endpoint.secret, sender, events);
+                sync::resync_on_reconnect(client.sender.downgrade(), client.secret.clone(), &client.events);
+                Ok(client)
+            }
+            RpcScheme::Http | RpcScheme::Https => {
+                let client = Self::from_parts(endpoint.secret, transport::spawn_http(url)?, events);
+                // HTTP is connectionless, so verify reachability and the secret up front
+                client.get_version().await?;
+                sync::watch_status(client.sender.downgrade(), client.secret.clone(), client.events.clone());
+                Ok(client)
+            }
+        }
+    }
+
+    /// Assemble a client around an existing request channel
+    pub(super) fn from_parts(
+        secret: String,
+        sender: mpsc::Sender<RpcRequest>,
+        events: broadcast::Sender<ClientEvent>,
+    ) -> Self {
+        Self {
+            secret,
+            sender,
+            events,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+        }
+    }
+
+    /// Override how long calls wait for a response (30 seconds by default)
+    pub fn with_request_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// Subscribe to task notifications and connection changes
+    pub fn subscribe(&self) -> broadcast::Receiver<ClientEvent> {
+        self.events.subscribe()
+    }
+
+    /// Map an aria2 notification to a task event and publish it
+    pub(super) fn handle_notification(events: &broadcast::Sender<ClientEvent>, notification: Aria2Notification) {
+        let event_type = match notification.method.as_str() {
+            "aria2.onDownloadStart" => Aria2EventType::DownloadStart,
+            "aria2.onDownloadPause" => Aria2EventType::DownloadPause,
+            "aria2.onDownloadStop" => Aria2EventType::DownloadStop,
+            "aria2.onDownloadComplete" => Aria2EventType::DownloadComplete,
+            "aria2.onDownloadError" => Aria2EventType::DownloadError,
+            "aria2.onBtDownloadComplete" => Aria2EventType::BtDownloadComplete,
+            _ => {
+                tracing::debug!("Unknown aria2 notification: {}", notification.method);
+                return;
+            }
+        };
+
+        if let Some(param) = notification.params.first() {
+            let event = Aria2Event {
+                event_type,
+                gid: param.gid.clone(),
+            };
+            Self::publish_event(events, event);
+        }
+    }
+
+    /// Publish a task event to subscribers
+    pub(super) fn publish_event(events: &broadcast::Sender<ClientEvent>, event: Aria2Event) {
+        tracing::info!("Aria2 event: {:?} for gid {}", event.event_type, event.gid);
+        // Sending only fails when nobody is subscribed
+        let _ = events.send(ClientEvent::Task(event));
+    }
+
+    /// Publish a connection state change to subscribers
+    pub(super) fn publish_connection(events: &broadcast::Sender<ClientEvent>, state: ConnectionState) {
+        let _ = events.send(ClientEvent::Connection(state));
+    }
+
+    /// Call an aria2 RPC method
+    pub async fn call(&self, method: &str, params: Vec<Value>) -> Result<Value> {
+        self.send(format!("aria2.{}", method), self.with_token(params)).await
+    }
+
+    /// Prepend the secret token to a method's params
+    fn with_token(&self, params: Vec<Value>) -> Vec<Value> {
+        let mut full_params = vec![json!(format!("token:{}", self.secret))];
+        full_params.extend(params);
+        full_params
+    }
+
+    /// Send a raw JSON-RPC request and wait for its response
+    async fn send(&self, method: String, params: Vec<Value>) -> Result<Value> {
+        let (tx, rx) = oneshot::channel();
+
+        let request = RpcRequest {
+            method,
+            params,
+            response_tx: tx,
+        };
+
+        self.sender
+            .send(request)
+            .await
+            .map_err(|_| Error::Disconnected("Aria2 connection is closed".to_string()))?;
+
+        // Add timeout to prevent hanging forever if WebSocket connection is broken
+        match tokio::time::timeout(self.request_timeout, rx).await {
+            Ok(result) => result.map_err(|_| Error::Disconnected("Aria2 connection is closed".to_string()))?,
+            Err(_) => Err(Error::Timeout),
+        }
+    }
+
+    /// Batch several aria2 RPC calls into one `system.multicall` round trip
+    ///
+    /// Returns one result per call, in order. A failing call does not affect
+    /// the others; only transport errors fail the whole batch.
+    pub async fn multicall(&self, calls: Vec<(&str, Vec<Value>)>) -> Result<Vec<Result<Value>>> {
+        if calls.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let expected = calls.len();
+        let methods: Vec<Value> = calls
+            .into_iter()
+            .map(|(method, params)| {
+                json!({
+                    "methodName": format!("aria2.{}", method),
+                    "params": self.with_token(params),
+                })
+            })
+            .collect();
+
+        let result = self.send("system.multicall".to_string(), vec![json!(methods)]).await?;
+        let entries = match result {
+            Value::Array(entries) if entries.len() == expected => entries,
+            _ => return Err(Error::Protocol("system.multicall returned the wrong number of results".to_string())),
+        };
+
+        // Each entry is either a one-element array holding the result, or an error struct
+        Ok(entries
+            .into_iter()
+            .map(|entry| match entry {
+                Value::Array(mut values) if values.len() == 1 => Ok(values.remove(0)),
+                other => match serde_json::from_value::<RpcError>(other) {
+                    Ok(error) => Err(error.into()),
+                    Err(_) => Err(Error::Protocol("unexpected system.multicall entry".to_string())),
+                },
+            })
+            .collect())
+    }
+
+    /// Run the same single-gid method for many gids in one round trip
+    async fn multicall_gids(&self, method: &str, gids: &[String]) -> Result<Vec<GidResult>> {
+        let calls = gids.iter().map(|gid| (method, vec![json!(gid)])).collect();
+        let results = self.multicall(calls).await?;
+        Ok(gids
+            .iter()
+            .zip(results)
+            .map(|(gid, result)| GidResult::new(gid, result))
+            .collect())
+    }
+
+    /// Call an aria2 RPC method and decode its result into a typed model
+    async fn call_as<T: DeserializeOwned>(&self, method: &str, params: Vec<Value>) -> Result<T> {
+        let result = self.call(method, params).await?;
+        decode(method, result)
+    }
+
+    /// Add URI download
+    pub async fn add_uri(&self, uris: Vec<String>, options: Option<Value>) -> Result<String> {
+        if uris.iter().all(|uri| uri.trim().is_empty()) {
+            return Err(Error::Validation("no URI to download".to_string()));
+        }
+        let mut params = vec![json!(uris)];
+        if let Some(opts) = options {
+            params.push(opts);
+        }
+        let result = self.call("addUri", params).await?;
+        result
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| Error::Protocol("expected a gid".to_string()))
+    }
+
+    /// Add torrent download
+    pub async fn add_torrent(&self, torrent: &str, options: Option<Value>) -> Result<String> {
+        let mut params = vec![json!(torrent)];
+        params.push(json!([])); // uris
+        if let Some(opts) = options {
+            params.push(opts);
+        }
+        let result = self.call("addTorrent", params).await?;
+        result
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| Error::Protocol("expected a gid".to_string()))
+    }
+
+    /// Add metalink download
+    pub async fn add_metalink(&self, metalink: &str, options: Option<Value>) -> Result<Value> {
+        let mut params = vec![json!(metalink)];
+        if let Some(opts) = options {
+            params.push(opts);
+        }
+        self.call("addMetalink", params).await
+    }
+
+    /// Pause task
+    pub async fn pause(&self, gid: &str) -> Result<String> {
+        let result = self.call("pause", vec![json!(gid)]).await?;
+        result
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| Error::Protocol("expected a gid".to_string()))
+    }
+
+    /// Resume task
+    pub async fn unpause(&self, gid: &str) -> Result<String> {
+        let result = self.call("unpause", vec![json!(gid)]).await?;
+        result
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| Error::Protocol("expected a gid".to_string()))
+    }
+
+    /// Remove task
+    pub async fn remove(&self, gid: &str) -> Result<String> {
+        let result = self.call("remove", vec![json!(gid)]).await?;
+        result
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| Error::Protocol("expected a gid".to_string()))
+    }
+
+    /// Get task status
+    pub async fn tell_status(&self, gid: &str) -> Result<Task> {
+        self.call_as("tellStatus", vec![json!(gid)]).await
+    }
+
+    /// Get task info limited to `keys`
+    ///
+    /// `gid` and `status` are always included so the result still decodes as a `Task`.
+    pub async fn tell_status_keys(&self, gid: &str, keys: &[String]) -> Result<Task> {
+        let mut keys = keys.to_vec();
+        for required in ["gid", "status"] {
+            if !keys.iter().any(|k| k == required) {
+                keys.push(required.to_string());
+            }
+        }
+        self.call_as("tellStatus", vec![json!(gid), json!(keys)]).await
+    }
+
+    /// Get the URIs of a task
+    pub async fn get_uris(&self, gid: &str) -> Result<Vec<TaskUri>> {
+        self.call_as("getUris", vec![json!(gid)]).await
+    }
+
+    /// Get the files of a task with their progress
+    pub async fn get_files(&self, gid: &str) -> Result<Vec<TaskFile>> {
+        self.call_as("getFiles", vec![json!(gid)]).await
+    }
+
+    /// Get the mirrors each file of an HTTP/FTP task is downloaded from
+    pub async fn get_servers(&self, gid: &str) -> Result<Vec<FileServers>> {
+        self.call_as("getServers", vec![json!(gid)]).await
+    }
+
+    /// Remove and add URIs of a file (`file_index` is 1-based)
+    ///
+    /// New URIs are appended, or inserted at `position` (0-based) if given.
+    pub async fn change_uri(
+        &self,
+        gid: &str,
+        file_index: u32,
+        del_uris: Vec<String>,
+        add_uris: Vec<String>,
+        position: Option<u32>,
+    ) -> Result<UriChange> {
+        if file_index == 0 {
+            return Err(Error::Validation("file index starts at 1".to_string()));
+        }
+        let mut params = vec![json!(gid), json!(file_index), json!(del_uris), json!(add_uris)];
+        if let Some(position) = position {
+            params.push(json!(position));
+        }
+        self.call_as("changeUri", params).await
+    }
+
+    /// Get active tasks
+    pub async fn tell_active(&self) -> Result<Vec<Task>> {
+        self.call_as("tellActive", vec![]).await
+    }
+
+    /// Get waiting tasks
+    pub async fn tell_waiting(&self, offset: i32, num: i32) -> Result<Vec<Task>> {
+        self.call_as("tellWaiting", vec![json!(offset), json!(num)])
+            .await
+    }
+
+    /// Get stopped tasks
+    pub async fn tell_stopped(&self, offset: i32, num: i32) -> Result<Vec<Task>> {
+        self.call_as("tellStopped", vec![json!(offset), json!(num)])
+            .await
+    }
+
+    /// Get global statistics
+    pub async fn get_global_stat(&self) -> Result<GlobalStat> {
+        self.call_as("getGlobalStat", vec![]).await
+    }
+
+    /// Get active and waiting tasks in a single round trip
+    pub async fn tell_active_and_waiting(&self, num_waiting: i32) -> Result<Vec<Task>> {
+        let mut results = self
+            .multicall(vec![
+                ("tellActive", vec![]),
+                ("tellWaiting", vec![json!(0), json!(num_waiting)]),
+            ])
+            .await?
+            .into_iter();
+
+        let mut tasks: Vec<Task> = decode("tellActive", results.next().unwrap_or(Ok(Value::Null))?)?;
+        let waiting: Vec<Task> = decode("tellWaiting", results.next().unwrap_or(Ok(Value::Null))?)?;
+        tasks.extend(waiting);
+        Ok(tasks)
+    }
+
+    /// Pause many tasks at once
+    pub async fn pause_many(&self, gids: &[String], force: bool) -> Result<Vec<GidResult>> {
+        self.multicall_gids(if force { "forcePause" } else { "pause" }, gids).await
+    }
+
+    /// Resume many tasks at once
+    pub async fn unpause_many(&self, gids: &[String]) -> Result<Vec<GidResult>> {
+        self.multicall_gids("unpause", gids).await
+    }
+
+    /// Remove many tasks at once
+    ///
+    /// Stopped tasks cannot be removed; their records are purged with
+    /// `remove_download_results`.
+    pub async fn remove_many(&self, gids: &[String], force: bool) -> Result<Vec<GidResult>> {
+        self.multicall_gids(if force { "forceRemove" } else { "remove" }, gids).await
+    }
+
+    /// Remove the download result records of many stopped tasks at once
+    pub async fn remove_download_results(&self, gids: &[String]) -> Result<Vec<GidResult>> {
+        self.multicall_gids("removeDownloadResult", gids).await
+    }
+
+    /// Change global options
+    pub async fn change_global_option(&self, options: Value) -> Result<Value> {
+        self.call("changeGlobalOption", vec![options]).await
+    }
+
+    /// Pause all active tasks
+    pub async fn pause_all(&self) -> Result<Value> {
+        self.call("pauseAll", vec![]).await
+    }
+
+    /// Resume all paused tasks
+    pub async fn unpause_all(&self) -> Result<Value> {
+        self.call("unpauseAll", vec![]).await
+    }
+
+    /// Remove download result (clear completed/error record)
+    pub async fn remove_download_result(&self, gid: &str) -> Result<Value> {
+        self.call("removeDownloadResult", vec![json!(gid)]).await
+    }
+
+    /// Purge all completed/error/removed download results
+    pub async fn purge_download_result(&self) -> Result<Value> {
+        self.call("purgeDownloadResult", vec![]).await
+    }
+
+    /// Save session to file
+    pub async fn save_session(&self) -> Result<Value> {
+        self.call("saveSession", vec![]).await
+    }
+
+    /// Force pause a task (needed for BT tasks)
+    pub async fn force_pause(&self, gid: &str) -> Result<String> {
+        let result = self.call("forcePause", vec![json!(gid)]).await?;
+        result
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| Error::Protocol("expected a gid".to_string()))
+    }
+
+    /// Force remove a task
+    pub async fn force_remove(&self, gid: &str) -> Result<String> {
+        let result = self.call("forceRemove", vec![json!(gid)]).await?;
+        result
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| Error::Protocol("expected a gid".to_string()))
+    }
+
+    /// Get aria2 version info
+    pub async fn get_version(&self) -> Result<Value> {
+        self.call("getVersion", vec![]).await
+    }
+
+    /// Get peers for a BT task
+    pub async fn get_peers(&self, gid: &str) -> Result<Vec<Peer>> {
+        self.call_as("getPeers", vec![json!(gid)]).await
+    }
+
+    /// Get the effective options of a task
+    pub async fn get_option(&self, gid: &str) -> Result<Options> {
+        self.call_as("getOption", vec![json!(gid)]).await
+    }
+
+    /// Get the global options
+    pub async fn get_global_option(&self) -> Result<Options> {
+        self.call_as("getGlobalOption", vec![]).await
+    }
+
+    /// Get the current session id
+    pub async fn get_session_info(&self) -> Result<SessionInfo> {
+        self.call_as("getSessionInfo", vec![]).await
+    }
+
+    /// List every RPC method the engine supports
+    pub async fn list_methods(&self) -> Result<Vec<String>> {
+        let result = self.send("system.listMethods".to_string(), vec![]).await?;
+        decode("system.listMethods", result)
+    }
+
+    /// List every notification the engine can send
+    pub async fn list_notifications(&self) -> Result<Vec<String>> {
+        let result = self.send("system.listNotifications".to_string(), vec![]).await?;
+        decode("system.listNotifications", result)
+    }
+
+    /// Change task-specific options
+    pub async fn change_option(&self, gid: &str, options: Value) -> Result<Value> {
+        self.call("changeOption", vec![json!(gid), options]).await
+    }
+
+    /// Change task position in the waiting queue
+    pub async fn change_position(&self, gid: &str, pos: i32, how: &str) -> Result<Value> {
+        if !matches!(how, "POS_SET" | "POS_CUR" | "POS_END") {
+            return Err(Error::Validation(format!("unknown position mode {:?}", how)));
+        }
+        self.call("changePosition", vec![json!(gid), json!(pos), json!(how)]).await
+    }
+
+    /// Shutdown aria2
+    pub async fn shutdown(&self) -> Result<Value> {
+        // Save session before shutdown
+        let _ = self.save_session().await;
+        self.call("shutdown", vec![]).await
+    }
+}
+
+/// Outcome of a batched operation for a single gid
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GidResult {
+    pub gid: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<Error>,
+}
+
+impl GidResult {
+    fn new(gid: &str, result: Result<Value>) -> Self {
+        Self {
+            gid: gid.to_string(),
+            error: result.err(),
+        }
+    }
+}
+
+/// Decode an RPC result into a typed model
+pub(super) fn decode<T: DeserializeOwned>(method: &str, value: Value) -> Result<T> {
+    serde_json::from_value(value)
+        .map_err(|e| Error::Protocol(format!("{}: {}", method, e)))
+}
@@ -0,0 +1,442 @@
+//! Backend task poller
+//!
+//! Keeps a cached table of every task and emits only what changed since the
+//! last tick as `task-delta`, so the frontend no longer has to poll full task
+//! lists. The table is shared so the frontend can fetch a full snapshot when it
+//! starts listening. Stopped records are read in full once; afterwards only
+//! the ones that stopped since the last tick are fetched, found at the end of
+//! aria2's oldest-first stopped list from the counters, which keeps ticks cheap
+//! even with thousands of finished records.
+
+use super::client::decode;
+use super::{Aria2Client, ClientEvent, GlobalStat, Task, TaskStatus};
+use crate::Result;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+use tokio::sync::{broadcast, Mutex, Notify};
+
+/// Poll interval while any download is active
+const ACTIVE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Poll interval while nothing is downloading
+const IDLE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Delay between an aria2 notification and the tick it triggers, to batch bursts
+const EVENT_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Most stopped records read when the table is built
+const STOPPED_LIMIT: u64 = 10_000;
+
+/// Fields kept for stopped records; piece maps and speeds no longer matter
+const STOPPED_KEYS: [&str; 15] = [
+    "gid",
+    "status",
+    "totalLength",
+    "completedLength",
+    "uploadLength",
+    "infoHash",
+    "errorCode",
+    "errorMessage",
+    "followedBy",
+    "following",
+    "belongsTo",
+    "dir",
+    "files",
+    "bittorrent",
+    "verifiedLength",
+];
+
+/// Table of the current client, shared with `snapshot`
+static TASK_TABLE: Mutex<Option<TaskTable>> = Mutex::const_new(None);
+
+/// Wakes the poller for an immediate tick
+static REFRESH: Notify = Notify::const_new();
+
+/// Changes to the task table since the previous tick
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskDelta {
+    /// The table was rebuilt from scratch; `added` holds every task and
+    /// anything not listed should be dropped
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub reset: bool,
+    /// Tasks seen for the first time
+    pub added: Vec<Task>,
+    /// Changed fields of known tasks; each entry also carries `gid`
+    pub updated: Vec<Map<String, Value>>,
+    /// Gids of tasks that are gone
+    pub removed: Vec<String>,
+    /// Global statistics, when they changed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stat: Option<GlobalStat>,
+}
+
+impl TaskDelta {
+    pub fn is_empty(&self) -> bool {
+        !self.reset
+            && self.added.is_empty()
+            && self.updated.is_empty()
+            && self.removed.is_empty()
+            && self.stat.is_none()
+    }
+}
+
+/// Cached view of every task, diffed on each refresh
+#[derive(Debug, Default)]
+pub struct TaskTable {
+    tasks: HashMap<String, Map<String, Value>>,
+    /// Gids in aria2's order: active, waiting, then stopped
+    order: Vec<String>,
+    stat: Option<GlobalStat>,
+}
+
+/// What a tick needs from the table to fetch, so aria2 is asked without the table locked
+#[derive(Debug, Default)]
+struct FetchBasis {
+    stat: Option<GlobalStat>,
+    /// Gids of cached tasks that have not stopped
+    live: HashSet<String>,
+}
+
+/// Tasks fetched for one tick, not yet diffed against the table
+#[derive(Debug)]
+struct Fetched {
+    stat: GlobalStat,
+    /// Active and waiting tasks
+    current: Vec<Task>,
+    /// Stopped records fetched this tick, oldest first
+    stopped: Vec<Task>,
+    /// Gids still in aria2's stopped list, when records may have been dropped
+    kept: Option<HashSet<String>>,
+}
+
+impl FetchBasis {
+    async fn fetch(&self, client: &Aria2Client) -> Result<Fetched> {
+        let stat = client.get_global_stat().await?;
+        let keys = json!(STOPPED_KEYS);
+
+        let mut calls = vec![
+            ("tellActive", vec![]),
+            ("tellWaiting", vec![json!(0), json!(stat.num_waiting)]),
+        ];
+        // Records that stopped since the last tick are the newest, at the end of the list
+        let (offset, num, dropped) = match &self.stat {
+            Some(last) => {
+                let new = stat
+                    .num_stopped_total
+                    .saturating_sub(last.num_stopped_total)
+                    .min(stat.num_stopped);
+                let dropped = (last.num_stopped + new).saturating_sub(stat.num_stopped);
+                (stat.num_stopped - new, new, dropped)
+            }
+            None => {
+                let num = stat.num_stopped.min(STOPPED_LIMIT);
+                (stat.num_stopped - num, num, 0)
+            }
+        };
+        if num > 0 {
+            calls.push(("tellStopped", vec![json!(offset), json!(num), keys.clone()]));
+        }
+        // Purged or evicted records: only which ones are left is needed
+        if dropped > 0 && stat.num_stopped > 0 {
+            calls.push(("tellStopped", vec![json!(0), json!(stat.num_stopped), json!(["gid"])]));
+        }
+        let mut results = client.multicall(calls).await?.into_iter();
+
+        let mut current: Vec<Task> = decode("tellActive", results.next().unwrap_or(Ok(Value::Null))?)?;
+        let waiting: Vec<Task> = decode("tellWaiting", results.next().unwrap_or(Ok(Value::Null))?)?;
+        current.extend(waiting);
+        let mut stopped: Vec<Task> = match num {
+            0 => Vec::new(),
+            _ => decode("tellStopped", results.next().unwrap_or(Ok(Value::Null))?)?,
+        };
+        let kept = match dropped {
+            0 => None,
+            _ => {
+                let left: Vec<Value> = match stat.num_stopped {
+                    0 => Vec::new(),
+                    _ => decode("tellStopped", results.next().unwrap_or(Ok(Value::Null))?)?,
+                };
+                Some(
+                    left.iter()
+                        .filter_map(|record| record["gid"].as_str())
+                        .map(str::to_string)
+                        .collect(),
+                )
+            }
+        };
+
+        // A task that left the queue without moving the counters yet has most
+        // likely just stopped; look it up on its own
+        let present: HashSet<&str> = current.iter().chain(&stopped).map(|t| t.gid.as_str()).collect();
+        let vanished: Vec<&String> = self.live.iter().filter(|gid| !present.contains(gid.as_str())).collect();
+        if !vanished.is_empty() {
+            let calls = vanished
+                .iter()
+                .map(|gid| ("tellStatus", vec![json!(gid), keys.clone()]))
+                .collect();
+            for result in client.multicall(calls).await? {
+                // Gone altogether, e.g. removed and purged
+                let Ok(result) = result else {
+                    continue;
+                };
+                let task: Task = decode("tellStatus", result)?;
+                if matches!(
+                    task.status,
+                    TaskStatus::Complete | TaskStatus::Error | TaskStatus::Removed
+                ) {
+                    stopped.push(task);
+                } else {
+                    current.push(task);
+                }
+            }
+        }
+        Ok(Fetched {
+            stat,
+            current,
+            stopped,
+            kept,
+        })
+    }
+}
+
+/// Whether a cached task is a finished record
+fn stopped_entry(fields: &Map<String, Value>) -> bool {
+    matches!(
+        fields.get("status").and_then(Value::as_str),
+        Some("complete" | "error" | "removed")
+    )
+}
+
+impl TaskTable {
+    /// Whether the last refresh saw any active download
+    pub fn is_busy(&self) -> bool {
+        self.stat.as_ref().is_some_and(|stat| stat.num_active > 0)
+    }
+
+    /// Fetch the current tasks and return what changed
+    pub async fn refresh(&mut self, client: &Aria2Client) -> Result<TaskDelta> {
+        let fetched = self.basis().fetch(client).await?;
+        self.apply(fetched)
+    }
+
+    fn basis(&self) -> FetchBasis {
+        FetchBasis {
+            stat: self.stat.clone(),
+            live: self
+                .tasks
+                .iter()
+                .filter(|(_, fields)| !stopped_entry(fields))
+                .map(|(gid, _)| gid.clone())
+                .collect(),
+        }
+    }
+
+    /// Diff fetched tasks against the table and take them in
+    fn apply(&mut self, fetched: Fetched) -> Result<TaskDelta> {
+        let Fetched {
+            stat,
+            current,
+            stopped,
+            kept,
+        } = fetched;
+        let reset = self.stat.is_none();
+        let mut next: HashMap<String, Map<String, Value>> = HashMap::with_capacity(self.tasks.len());
+        let mut added = Vec::new();
+        let mut updated = Vec::new();
+
+        // Stopped records not fetched again are unchanged, unless aria2 dropped them
+        let fetched: HashSet<&str> = current.iter().chain(&stopped).map(|t| t.gid.as_str()).collect();
+        let unchanged: Vec<String> = self
+            .order
+            .iter()
+            .filter(|gid| stopped_entry(&self.tasks[gid.as_str()]) && !fetched.contains(gid.as_str()))
+            .filter(|gid| kept.as_ref().map_or(true, |kept| kept.contains(gid.as_str())))
+            .cloned()
+            .collect();
+
+        // aria2's order: active, waiting, older stopped records, then the ones that just stopped
+        let mut order = Vec::with_capacity(self.order.len());
+        for task in current {
+            order.extend(self.take(task, &mut next, &mut added, &mut updated)?);
+        }
+        for gid in unchanged {
+            next.insert(gid.clone(), self.tasks[&gid].clone());
+            order.push(gid);
+        }
+        for task in stopped {
+            order.extend(self.take(task, &mut next, &mut added, &mut updated)?);
+        }
+
+        let removed = self
+            .order
+            .iter()
+            .filter(|gid| !next.contains_key(*gid))
+            .cloned()
+            .collect();
+        let stat_changed = self.stat.as_ref() != Some(&stat);
+
+        self.tasks = next;
+        self.order = order;
+        self.stat = Some(stat.clone());
+
+        Ok(TaskDelta {
+            reset,
+            added,
+            updated,
+            removed,
+            stat: stat_changed.then_some(stat),
+        })
+    }
+
+    /// Diff one fetched task into `next`, returning its gid unless it was already taken
+    fn take(
+        &self,
+        task: Task,
+        next: &mut HashMap<String, Map<String, Value>>,
+        added: &mut Vec<Task>,
+        updated: &mut Vec<Map<String, Value>>,
+    ) -> Result<Option<String>> {
+        if next.contains_key(&task.gid) {
+            return Ok(None);
+        }
+        let fields = match serde_json::to_value(&task)? {
+            Value::Object(fields) => fields,
+            _ => return Ok(None),
+        };
+        match self.tasks.get(&task.gid) {
+            None => added.push(task.clone()),
+            Some(old) => {
+                if let Some(change) = changed_fields(&task.gid, old, &fields) {
+                    updated.push(change);
+                }
+            }
+        }
+        next.insert(task.gid.clone(), fields);
+        Ok(Some(task.gid))
+    }
+
+    /// Every cached task as a reset delta
+    pub fn snapshot(&self) -> TaskDelta {
+        let added = self
+            .order
+            .iter()
+            .filter_map(|gid| serde_json::from_value(Value::Object(self.tasks[gid].clone())).ok())
+            .collect();
+        TaskDelta {
+            reset: true,
+            added,
+            stat: self.stat.clone(),
+            ..TaskDelta::default()
+        }
+    }
+
+    /// Number of cached tasks
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+}
+
+/// Fields that differ between two serialized versions of a task
+///
+/// Fields that disappeared are reported as `null`.
+fn changed_fields(gid: &str, old: &Map<String, Value>, new: &Map<String, Value>) -> Option<Map<String, Value>> {
+    let mut change = Map::new();
+    for (key, value) in new {
+        if old.get(key) != Some(value) {
+            change.insert(key.clone(), value.clone());
+        }
+    }
+    for key in old.keys() {
+        if !new.contains_key(key) {
+            change.insert(key.clone(), Value::Null);
+        }
+    }
+    if change.is_empty() {
+        return None;
+    }
+    change.insert("gid".to_string(), json!(gid));
+    Some(change)
+}
+
+/// Current contents of the shared task table, if a client is polled
+pub async fn snapshot() -> Option<TaskDelta> {
+    TASK_TABLE.lock().await.as_ref().map(TaskTable::snapshot)
+}
+
+/// Ask the poller to tick now instead of waiting for its interval
+pub fn request_refresh() {
+    REFRESH.notify_one();
+}
+
+/// Poll the client and emit `task-delta` until the client is dropped
+///
+/// Ticks every second while something downloads and every five seconds when
+/// idle; aria2 notifications and `request_refresh` trigger an early tick.
+pub(super) fn spawn(app: AppHandle, client: &Arc<Aria2Client>) {
+    let weak: Weak<Aria2Client> = Arc::downgrade(client);
+    let mut events = client.subscribe();
+
+    tauri::async_runtime::spawn(async move {
+        // A new client starts from an empty table, so its first tick is a reset
+        *TASK_TABLE.lock().await = Some(TaskTable::default());
+
+        loop {
+            let Some(client) = weak.upgrade() else {
+                break;
+            };
+            // Only the diff holds the lock, so `snapshot` never waits on aria2
+            let basis = TASK_TABLE
+                .lock()
+                .await
+                .as_ref()
+                .map(TaskTable::basis)
+                .unwrap_or_default();
+            let tick = match basis.fetch(&client).await {
+                Ok(fetched) => {
+                    let mut guard = TASK_TABLE.lock().await;
+                    let table = guard.get_or_insert_with(TaskTable::default);
+                    table.apply(fetched).map(|delta| (delta, table.is_busy()))
+                }
+                Err(e) => Err(e),
+            };
+            let busy = match tick {
+                Ok((delta, busy)) => {
+                    if !delta.removed.is_empty() {
+                        crate::category::forget(&delta.removed).await;
+                    }
+                    if !delta.is_empty() {
+                        if let Err(e) = app.emit("task-delta", &delta) {
+                            tracing::error!("Failed to emit task delta: {}", e);
+                        }
+                    }
+                    busy
+                }
+                Err(e) => {
+                    tracing::debug!("Task poll failed: {}", e);
+                    false
+                }
+            };
+            drop(client);
+
+            let interval = if busy { ACTIVE_INTERVAL } else { IDLE_INTERVAL };
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => {}
+                _ = REFRESH.notified() => {}
+                event = events.recv() => match event {
+                    Ok(ClientEvent::Task(_)) => tokio::time::sleep(EVENT_DEBOUNCE).await,
+                    Ok(ClientEvent::Connection(_)) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+            }
+        }
+    });
+}
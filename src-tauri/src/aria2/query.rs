@@ -0,0 +1,261 @@
+//! Task list queries
+//!
+//! Filters, sorts and pages task lists next to the engine so list views only
+//! receive the rows they show. The key projection is forwarded to aria2 as
+//! `keys`, so heavy fields such as bittorrent `files` are not transferred
+//! when the caller does not need them.
+
+use super::client::decode;
+use super::{Aria2Client, Task, TaskStatus};
+use crate::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::cmp::Ordering;
+
+/// Order of a query result
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TaskSort {
+    /// aria2's own order: active tasks, the waiting queue, then stopped records
+    /// oldest first. aria2 keeps no timestamps, so this stands in for add time.
+    #[default]
+    Added,
+    Speed,
+    Progress,
+    Size,
+}
+
+/// Filters, order and page of a task list
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TaskQuery {
+    /// Statuses to include; empty means every task
+    pub status: Vec<TaskStatus>,
+    /// Case-insensitive substring of the task name or one of its URIs
+    pub search: Option<String>,
+    pub sort: TaskSort,
+    pub descending: bool,
+    pub offset: usize,
+    /// Page size; `None` returns every match
+    pub limit: Option<usize>,
+    /// Fields to return, forwarded to aria2 as `keys`; `None` returns every field
+    pub keys: Option<Vec<String>>,
+}
+
+/// One page of a task query
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskPage {
+    pub tasks: Vec<Task>,
+    /// Number of matching tasks across all pages
+    pub total: usize,
+}
+
+/// The three lists aria2 keeps tasks in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum List {
+    Active,
+    Waiting,
+    Stopped,
+}
+
+impl List {
+    const ALL: [List; 3] = [List::Active, List::Waiting, List::Stopped];
+
+    fn statuses(self) -> &'static [TaskStatus] {
+        match self {
+            List::Active => &[TaskStatus::Active],
+            List::Waiting => &[TaskStatus::Waiting, TaskStatus::Paused],
+            List::Stopped => &[TaskStatus::Complete, TaskStatus::Error, TaskStatus::Removed],
+        }
+    }
+
+    fn method(self) -> &'static str {
+        match self {
+            List::Active => "tellActive",
+            List::Waiting => "tellWaiting",
+            List::Stopped => "tellStopped",
+        }
+    }
+
+    /// Params reading `num` tasks from `offset`; `tellActive` always returns the whole list
+    fn params(self, offset: usize, num: usize, keys: Option<&[String]>) -> Vec<Value> {
+        let mut params = match self {
+            List::Active => vec![],
+            _ => vec![json!(offset), json!(num)],
+        };
+        if let Some(keys) = keys {
+            params.push(json!(keys));
+        }
+        params
+    }
+}
+
+impl TaskQuery {
+    /// Lists holding at least one of the wanted statuses
+    fn lists(&self) -> Vec<List> {
+        List::ALL
+            .into_iter()
+            .filter(|list| self.status.is_empty() || list.statuses().iter().any(|s| self.status.contains(s)))
+            .collect()
+    }
+
+    /// Whether aria2's own windows can serve the page without reading whole lists
+    fn pages_in_engine(&self) -> bool {
+        self.search.is_none()
+            && self.sort == TaskSort::Added
+            && !self.descending
+            && (self.status.is_empty()
+                || self
+                    .lists()
+                    .iter()
+                    .all(|list| list.statuses().iter().all(|s| self.status.contains(s))))
+    }
+
+    /// Keys to request from aria2, including those the filter and sort read
+    fn fetch_keys(&self) -> Option<Vec<String>> {
+        let mut keys = self.keys.clone()?;
+        let mut needed = vec!["gid", "status"];
+        if self.search.is_some() {
+            needed.extend(["files", "bittorrent"]);
+        }
+        match self.sort {
+            TaskSort::Added => {}
+            TaskSort::Speed => needed.push("downloadSpeed"),
+            TaskSort::Progress => needed.extend(["completedLength", "totalLength"]),
+            TaskSort::Size => needed.push("totalLength"),
+        }
+        for key in needed {
+            if !keys.iter().any(|k| k == key) {
+                keys.push(key.to_string());
+            }
+        }
+        Some(keys)
+    }
+
+    fn matches(&self, task: &Task) -> bool {
+        if !self.status.is_empty() && !self.status.contains(&task.status) {
+            return false;
+        }
+        let Some(search) = &self.search else {
+            return true;
+        };
+        let search = search.to_lowercase();
+        task.name().to_lowercase().contains(&search)
+            || task
+                .files
+                .iter()
+                .flat_map(|file| &file.uris)
+                .any(|uri| uri.uri.to_lowercase().contains(&search))
+    }
+
+    fn compare(&self, a: &Task, b: &Task) -> Ordering {
+        let progress = |task: &Task| {
+            if task.total_length == 0 {
+                0.0
+            } else {
+                task.completed_length as f64 / task.total_length as f64
+            }
+        };
+        match self.sort {
+            TaskSort::Added => Ordering::Equal,
+            TaskSort::Speed => a.download_speed.cmp(&b.download_speed),
+            TaskSort::Progress => progress(a).total_cmp(&progress(b)),
+            TaskSort::Size => a.total_length.cmp(&b.total_length),
+        }
+    }
+
+    /// Drop fields that were only fetched for filtering
+    fn project(&self, task: &mut Task) {
+        let Some(keys) = &self.keys else {
+            return;
+        };
+        if !keys.iter().any(|k| k == "files") {
+            task.files.clear();
+        }
+        if !keys.iter().any(|k| k == "bittorrent") {
+            task.bittorrent = None;
+        }
+    }
+}
+
+impl Aria2Client {
+    /// Read one page of tasks matching `query`
+    ///
+    /// Unfiltered queries in aria2's order are paged by aria2 itself; anything
+    /// else reads the affected lists in one round trip and filters here.
+    pub async fn query_tasks(&self, query: &TaskQuery) -> Result<TaskPage> {
+        let stat = self.get_global_stat().await?;
+        let count = |list: List| -> usize {
+            (match list {
+                List::Active => stat.num_active,
+                List::Waiting => stat.num_waiting,
+                List::Stopped => stat.num_stopped,
+            }) as usize
+        };
+        let keys = query.fetch_keys();
+        let lists = query.lists();
+
+        if query.pages_in_engine() {
+            let total = lists.iter().map(|&list| count(list)).sum();
+            let mut skip = query.offset;
+            let mut remaining = query.limit.unwrap_or(usize::MAX);
+            // (list, offset, num) windows covering the page
+            let mut windows = Vec::new();
+            for &list in &lists {
+                if remaining == 0 {
+                    break;
+                }
+                if skip >= count(list) {
+                    skip -= count(list);
+                    continue;
+                }
+                let num = (count(list) - skip).min(remaining);
+                windows.push((list, skip, num));
+                remaining -= num;
+                skip = 0;
+            }
+
+            let calls = windows
+                .iter()
+                .map(|&(list, offset, num)| (list.method(), list.params(offset, num, keys.as_deref())))
+                .collect();
+            let results = self.multicall(calls).await?;
+            let mut tasks = Vec::new();
+            for ((list, offset, num), result) in windows.into_iter().zip(results) {
+                let page: Vec<Task> = decode(list.method(), result?)?;
+                match list {
+                    List::Active => tasks.extend(page.into_iter().skip(offset).take(num)),
+                    _ => tasks.extend(page),
+                }
+            }
+            return Ok(TaskPage { tasks, total });
+        }
+
+        let calls = lists
+            .iter()
+            .map(|&list| (list.method(), list.params(0, count(list), keys.as_deref())))
+            .collect();
+        let results = self.multicall(calls).await?;
+        let mut tasks = Vec::new();
+        for (&list, result) in lists.iter().zip(results) {
+            let fetched: Vec<Task> = decode(list.method(), result?)?;
+            tasks.extend(fetched.into_iter().filter(|task| query.matches(task)));
+        }
+
+        tasks.sort_by(|a, b| query.compare(a, b));
+        if query.descending {
+            tasks.reverse();
+        }
+        let total = tasks.len();
+        let mut tasks: Vec<Task> = tasks
+            .into_iter()
+            .skip(query.offset)
+            .take(query.limit.unwrap_or(usize::MAX))
+            .collect();
+        for task in &mut tasks {
+            query.project(task);
+        }
+        Ok(TaskPage { tasks, total })
+    }
+}
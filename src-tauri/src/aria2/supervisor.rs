@@ -0,0 +1,141 @@
+//! Supervision of the bundled aria2c process
+//!
+//! Drains the sidecar's output into `tracing` and restarts the engine with
+//! exponential backoff when it exits without being asked to. Lifecycle changes
+//! are emitted to the frontend as `engine-state`.
+
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+use tauri_plugin_shell::process::{CommandEvent, TerminatedPayload};
+use tokio::sync::mpsc;
+
+/// Delay before the first restart; doubled on each consecutive failure
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Upper bound for the restart delay
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Consecutive restarts attempted before giving up
+const MAX_RESTARTS: u32 = 5;
+
+/// An engine that stays up this long is considered healthy again
+const STABLE_UPTIME: Duration = Duration::from_secs(60);
+
+/// Lifecycle of the bundled engine, emitted as `engine-state`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum EngineState {
+    /// The engine is up and the client is connected
+    Running,
+    /// The engine exited unexpectedly
+    Crashed { code: Option<i32>, signal: Option<i32> },
+    /// A restart is scheduled after `delay_ms`
+    Restarting { attempt: u32, delay_ms: u64 },
+    /// Restarting failed too many times; the engine stays down
+    Failed { code: Option<i32> },
+    /// The engine exited on request
+    Stopped,
+}
+
+/// Emit an engine state change to the frontend
+pub(super) fn emit_state(app: &AppHandle, state: EngineState) {
+    if let Err(e) = app.emit("engine-state", &state) {
+        tracing::error!("Failed to emit engine state: {}", e);
+    }
+}
+
+/// Delay before restart `attempt` (1-based)
+fn backoff(attempt: u32) -> Duration {
+    INITIAL_BACKOFF
+        .saturating_mul(1 << attempt.saturating_sub(1).min(16))
+        .min(MAX_BACKOFF)
+}
+
+/// Log the process output until it terminates
+///
+/// Returns `None` if the event channel closed without a termination event.
+async fn wait_for_exit(events: &mut mpsc::Receiver<CommandEvent>) -> Option<TerminatedPayload> {
+    while let Some(event) = events.recv().await {
+        match event {
+            CommandEvent::Stdout(line) => {
+                let line = String::from_utf8_lossy(&line);
+                let line = line.trim_end();
+                if !line.is_empty() {
+                    tracing::debug!(target: "aria2c", "{}", line);
+                }
+            }
+            CommandEvent::Stderr(line) => {
+                let line = String::from_utf8_lossy(&line);
+                let line = line.trim_end();
+                if !line.is_empty() {
+                    tracing::warn!(target: "aria2c", "{}", line);
+                }
+            }
+            CommandEvent::Error(e) => tracing::error!(target: "aria2c", "Process error: {}", e),
+            CommandEvent::Terminated(payload) => return Some(payload),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Watch the bundled engine and restart it whenever it crashes
+pub(super) fn spawn(app: AppHandle, mut events: mpsc::Receiver<CommandEvent>) {
+    tauri::async_runtime::spawn(async move {
+        let mut failures = 0;
+
+        loop {
+            let started = Instant::now();
+            let exit = wait_for_exit(&mut events).await;
+            let (code, signal) = exit.map_or((None, None), |p| (p.code, p.signal));
+
+            if super::exit_expected() {
+                tracing::info!("Aria2 process exited (code: {:?})", code);
+                emit_state(&app, EngineState::Stopped);
+                return;
+            }
+
+            tracing::error!("Aria2 process exited unexpectedly (code: {:?}, signal: {:?})", code, signal);
+            super::clear_process().await;
+            super::set_client(None).await;
+            emit_state(&app, EngineState::Crashed { code, signal });
+
+            if started.elapsed() >= STABLE_UPTIME {
+                failures = 0;
+            }
+
+            // Keep trying until the engine is back up or the retry budget is spent
+            events = loop {
+                failures += 1;
+                if failures > MAX_RESTARTS {
+                    tracing::error!("Aria2 crashed {} times in a row, giving up", MAX_RESTARTS);
+                    emit_state(&app, EngineState::Failed { code });
+                    return;
+                }
+
+                let delay = backoff(failures);
+                tracing::info!("Restarting aria2 in {:?} (attempt {}/{})", delay, failures, MAX_RESTARTS);
+                emit_state(
+                    &app,
+                    EngineState::Restarting {
+                        attempt: failures,
+                        delay_ms: delay.as_millis() as u64,
+                    },
+                );
+                tokio::time::sleep(delay).await;
+                if super::exit_expected() {
+                    return;
+                }
+
+                match super::restart_engine(&app).await {
+                    Ok(events) => break events,
+                    Err(e) => tracing::error!("Failed to restart aria2: {}", e),
+                }
+            };
+
+            tracing::info!("Aria2 engine restarted");
+            emit_state(&app, EngineState::Running);
+        }
+    });
+}
@@ -0,0 +1,222 @@
+//! Task status tracking and resynchronisation
+//!
+//! Compares successive snapshots of task statuses and turns every change into
+//! the `Aria2Event` aria2 would have pushed over WebSocket. HTTP transports are
+//! polled this way; WebSocket transports only take a snapshot after
+//! reconnecting, to recover the notifications lost while disconnected.
+
+use super::client::decode;
+use super::transport::RpcRequest;
+use super::{Aria2Client, Aria2Event, Aria2EventType, ClientEvent, ConnectionState, Task, TaskStatus};
+use crate::Result;
+use serde_json::json;
+use std::collections::HashMap;
+use tokio::sync::{broadcast, mpsc};
+
+/// How often to poll when the transport cannot push notifications
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Number of the most recently stopped tasks included in a snapshot
+const SNAPSHOT_WINDOW: i32 = 1000;
+
+/// Extra tasks asked for beyond those counted, for tasks added between the calls
+const SNAPSHOT_SLACK: i32 = 100;
+
+/// Last known status of each task, keyed by gid
+#[derive(Debug, Default, Clone)]
+pub(super) struct StatusSnapshot(HashMap<String, TaskStatus>);
+
+impl StatusSnapshot {
+    fn from_tasks(tasks: Vec<Task>) -> Self {
+        Self(tasks.into_iter().map(|task| (task.gid, task.status)).collect())
+    }
+
+    /// Record the status implied by a task event
+    fn apply(&mut self, event: &Aria2Event) {
+        if let Some(status) = status_after(&event.event_type) {
+            self.0.insert(event.gid.clone(), status);
+        }
+    }
+
+    /// Events for every task whose status differs in `next`
+    pub fn transitions(&self, next: &StatusSnapshot) -> Vec<Aria2Event> {
+        next.0
+            .iter()
+            .filter(|(gid, status)| self.0.get(*gid) != Some(*status))
+            .filter_map(|(gid, status)| {
+                event_type_for(*status).map(|event_type| Aria2Event {
+                    event_type,
+                    gid: gid.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// The notification aria2 sends when a task enters `status`
+fn event_type_for(status: TaskStatus) -> Option<Aria2EventType> {
+    match status {
+        TaskStatus::Active => Some(Aria2EventType::DownloadStart),
+        TaskStatus::Paused => Some(Aria2EventType::DownloadPause),
+        TaskStatus::Removed => Some(Aria2EventType::DownloadStop),
+        TaskStatus::Complete => Some(Aria2EventType::DownloadComplete),
+        TaskStatus::Error => Some(Aria2EventType::DownloadError),
+        TaskStatus::Waiting => None,
+    }
+}
+
+/// The status a task is in after aria2 sent `event_type`
+fn status_after(event_type: &Aria2EventType) -> Option<TaskStatus> {
+    match event_type {
+        Aria2EventType::DownloadStart => Some(TaskStatus::Active),
+        Aria2EventType::DownloadPause => Some(TaskStatus::Paused),
+        Aria2EventType::DownloadStop => Some(TaskStatus::Removed),
+        Aria2EventType::DownloadComplete => Some(TaskStatus::Complete),
+        Aria2EventType::DownloadError => Some(TaskStatus::Error),
+        // BitTorrent downloads keep seeding after the payload completes
+        Aria2EventType::BtDownloadComplete => None,
+    }
+}
+
+/// Fetch the status of all active and waiting tasks and the recently stopped ones
+///
+/// aria2 lists the least recently stopped task first, so the stopped window is
+/// taken from the end of the list.
+pub(super) async fn fetch_snapshot(client: &Aria2Client) -> Result<StatusSnapshot> {
+    let stat = client.get_global_stat().await?;
+    let waiting = stat.num_waiting as i32 + SNAPSHOT_SLACK;
+    let offset = (stat.num_stopped as i32 - SNAPSHOT_WINDOW).max(0);
+    let keys = json!(["gid", "status"]);
+    let results = client
+        .multicall(vec![
+            ("tellActive", vec![keys.clone()]),
+            ("tellWaiting", vec![json!(0), json!(waiting), keys.clone()]),
+            ("tellStopped", vec![json!(offset), json!(SNAPSHOT_WINDOW + SNAPSHOT_SLACK), keys]),
+        ])
+        .await?;
+
+    let mut tasks = Vec::new();
+    for (method, result) in ["tellActive", "tellWaiting", "tellStopped"].into_iter().zip(results) {
+        let batch: Vec<Task> = decode(method, result?)?;
+        tasks.extend(batch);
+    }
+    Ok(StatusSnapshot::from_tasks(tasks))
+}
+
+/// Poll task statuses and publish the events a push channel would have delivered
+///
+/// Stops once the client that owns the request channel is dropped.
+pub(super) fn watch_status(
+    sender: mpsc::WeakSender<RpcRequest>,
+    secret: String,
+    events: broadcast::Sender<ClientEvent>,
+) {
+    tokio::spawn(async move {
+        let mut last: Option<StatusSnapshot> = None;
+        let mut connected = true;
+
+        loop {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            let Some(sender) = sender.upgrade() else {
+                break;
+            };
+            let client = Aria2Client::from_parts(secret.clone(), sender, events.clone());
+
+            match fetch_snapshot(&client).await {
+                Ok(next) => {
+                    if !connected {
+                        tracing::info!("Aria2 reachable again");
+                        Aria2Client::publish_connection(&events, ConnectionState::Connected);
+                        connected = true;
+                    }
+                    if let Some(prev) = &last {
+                        for event in prev.transitions(&next) {
+                            Aria2Client::publish_event(&events, event);
+                        }
+                    }
+                    last = Some(next);
+                }
+                Err(e) => {
+                    if connected {
+                        tracing::warn!("Aria2 status poll failed: {}", e);
+                        Aria2Client::publish_connection(&events, ConnectionState::Disconnected);
+                        connected = false;
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// Take a snapshot through the client behind `sender`, or `None` once it is gone
+async fn snapshot_via(
+    sender: &mpsc::WeakSender<RpcRequest>,
+    secret: &str,
+    events: &broadcast::WeakSender<ClientEvent>,
+) -> Option<Result<StatusSnapshot>> {
+    let client = Aria2Client::from_parts(secret.to_string(), sender.upgrade()?, events.upgrade()?);
+    Some(fetch_snapshot(&client).await)
+}
+
+/// Follow pushed notifications and replay the ones missed during a disconnect
+///
+/// Keeps the last known status of every task up to date from notifications.
+/// After each reconnect it takes a fresh snapshot and publishes an event for
+/// every transition that happened while the socket was down. Stops once the
+/// client and its connection are dropped.
+pub(super) fn resync_on_reconnect(
+    sender: mpsc::WeakSender<RpcRequest>,
+    secret: String,
+    events: &broadcast::Sender<ClientEvent>,
+) {
+    // Subscribe before spawning so no notification slips past the baseline
+    let mut rx = events.subscribe();
+    // Hold the channel weakly so it still closes when the client goes away
+    let events = events.downgrade();
+
+    tokio::spawn(async move {
+        let mut last = match snapshot_via(&sender, &secret, &events).await {
+            Some(Ok(snapshot)) => Some(snapshot),
+            Some(Err(e)) => {
+                tracing::warn!("Failed to take initial task snapshot: {}", e);
+                None
+            }
+            None => return,
+        };
+
+        loop {
+            match rx.recv().await {
+                Ok(ClientEvent::Task(event)) => {
+                    if let Some(last) = &mut last {
+                        last.apply(&event);
+                    }
+                }
+                Ok(ClientEvent::Connection(ConnectionState::Connected)) => {
+                    let next = match snapshot_via(&sender, &secret, &events).await {
+                        Some(Ok(next)) => next,
+                        Some(Err(e)) => {
+                            tracing::warn!("Failed to resync tasks after reconnect: {}", e);
+                            continue;
+                        }
+                        None => break,
+                    };
+                    if let (Some(prev), Some(events)) = (&last, events.upgrade()) {
+                        let missed = prev.transitions(&next);
+                        if !missed.is_empty() {
+                            tracing::info!("Replaying {} task events missed while disconnected", missed.len());
+                        }
+                        for event in missed {
+                            Aria2Client::publish_event(&events, event);
+                        }
+                    }
+                    last = Some(next);
+                }
+                Ok(ClientEvent::Connection(ConnectionState::Disconnected)) => {}
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!("Task tracker skipped {} events", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+}
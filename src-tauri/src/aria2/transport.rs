@@ -0,0 +1,254 @@
+//! JSON-RPC transports (WebSocket and HTTP) for talking to aria2
+//!
+//! Each transport runs as a background task that drains `RpcRequest`s from a
+//! channel, so `Aria2Client` does not care how requests travel.
+
+use super::client::Aria2Notification;
+use super::{Aria2Client, ClientEvent, ConnectionState};
+use crate::{Error, Result};
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+/// Atomic counter for generating unique RPC request IDs (safe for JSON number precision)
+static RPC_ID_COUNTER: AtomicU64 = AtomicU64::new(1);
+
+/// Delay before the first reconnect attempt; doubled after each failure
+const RECONNECT_INITIAL_DELAY: Duration = Duration::from_secs(1);
+
+/// Upper bound for the delay between reconnect attempts
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+pub(super) struct RpcRequest {
+    pub method: String,
+    pub params: Vec<Value>,
+    pub response_tx: oneshot::Sender<Result<Value>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcResponse {
+    id: u64,
+    result: Option<Value>,
+    error: Option<RpcError>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct RpcError {
+    pub code: i32,
+    pub message: String,
+}
+
+impl From<RpcError> for Error {
+    fn from(error: RpcError) -> Self {
+        Error::Aria2Rpc {
+            code: error.code,
+            message: error.message,
+        }
+    }
+}
+
+impl RpcResponse {
+    fn into_result(self) -> Result<Value> {
+        if let Some(error) = self.error {
+            Err(error.into())
+        } else {
+            Ok(self.result.unwrap_or(Value::Null))
+        }
+    }
+}
+
+fn request_body(id: u64, req: &RpcRequest) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "method": req.method,
+        "params": req.params,
+    })
+}
+
+/// Connect over WebSocket and spawn the request/notification loop
+///
+/// The loop reconnects on its own when the socket drops, retrying with
+/// backoff until it succeeds or every client handle is dropped.
+pub(super) async fn spawn_websocket(
+    url: String,
+    events: broadcast::Sender<ClientEvent>,
+) -> Result<mpsc::Sender<RpcRequest>> {
+    let (ws_stream, _) = connect_async(&url)
+        .await
+        .map_err(|e| Error::WebSocket(e.to_string()))?;
+
+    let (tx, mut rx) = mpsc::channel::<RpcRequest>(100);
+
+    // Spawn message handler with reconnection
+    tokio::spawn(async move {
+        let mut pending: HashMap<u64, oneshot::Sender<Result<Value>>> = HashMap::new();
+        let (mut write, mut read) = ws_stream.split();
+
+        loop {
+            tokio::select! {
+                // Handle outgoing requests
+                req = rx.recv() => {
+                    // Every client handle is gone, so nobody needs this connection anymore
+                    let Some(req) = req else {
+                        break;
+                    };
+                    let id = RPC_ID_COUNTER.fetch_add(1, Ordering::Relaxed);
+                    let msg = request_body(id, &req);
+
+                    pending.insert(id, req.response_tx);
+
+                    if let Err(e) = write.send(Message::Text(msg.to_string())).await {
+                        tracing::error!("Failed to send message: {}", e);
+                        // Remove pending request and send error back so caller doesn't hang
+                        if let Some(tx) = pending.remove(&id) {
+                            let _ = tx.send(Err(Error::Disconnected(format!("WebSocket send failed: {}", e))));
+                        }
+                    }
+                }
+                // Handle incoming responses and notifications
+                msg = read.next() => {
+                    match msg {
+                        Some(Ok(Message::Text(text))) => {
+                            // Try to parse as RPC response first
+                            if let Ok(response) = serde_json::from_str::<RpcResponse>(&text) {
+                                if let Some(tx) = pending.remove(&response.id) {
+                                    let _ = tx.send(response.into_result());
+                                }
+                            }
+                            // Try to parse as notification
+                            else if let Ok(notification) = serde_json::from_str::<Aria2Notification>(&text) {
+                                Aria2Client::handle_notification(&events, notification);
+                            }
+                        }
+                        Some(Ok(_)) => {}
+                        // Errors, close frames and the end of the stream all mean the socket is gone
+                        closed => {
+                            match closed {
+                                Some(Err(e)) => tracing::error!("WebSocket error: {}, attempting reconnect...", e),
+                                _ => tracing::warn!("WebSocket closed by aria2, attempting reconnect..."),
+                            }
+                            Aria2Client::publish_connection(&events, ConnectionState::Disconnected);
+                            // Fail all pending requests
+                            for (_, tx) in pending.drain() {
+                                let _ = tx.send(Err(disconnected()));
+                            }
+
+                            let Some(new_stream) = reconnect(&url, &mut rx).await else {
+                                break;
+                            };
+                            (write, read) = new_stream.split();
+                            Aria2Client::publish_connection(&events, ConnectionState::Connected);
+                        }
+                    }
+                }
+            }
+        }
+    });
+
+    Ok(tx)
+}
+
+fn disconnected() -> Error {
+    Error::Disconnected("WebSocket disconnected".to_string())
+}
+
+/// Reconnect with exponential backoff until it works
+///
+/// Requests that arrive in the meantime fail right away instead of waiting
+/// for the timeout. Returns `None` once every client handle is dropped.
+async fn reconnect(url: &str, rx: &mut mpsc::Receiver<RpcRequest>) -> Option<WsStream> {
+    let mut delay = RECONNECT_INITIAL_DELAY;
+    let mut attempt: u32 = 0;
+
+    loop {
+        let sleep = tokio::time::sleep(delay);
+        tokio::pin!(sleep);
+        loop {
+            tokio::select! {
+                _ = &mut sleep => break,
+                req = rx.recv() => match req {
+                    Some(req) => {
+                        let _ = req.response_tx.send(Err(disconnected()));
+                    }
+                    None => return None,
+                },
+            }
+        }
+
+        attempt += 1;
+        match connect_async(url).await {
+            Ok((stream, _)) => {
+                tracing::info!("WebSocket reconnected on attempt {}", attempt);
+                return Some(stream);
+            }
+            Err(e) => {
+                delay = (delay * 2).min(RECONNECT_MAX_DELAY);
+                tracing::warn!("Reconnect attempt {} failed: {}, retrying in {:?}", attempt, e, delay);
+            }
+        }
+    }
+}
+
+/// Spawn a loop that sends each request as an HTTP POST to `url`
+///
+/// HTTP has no push channel, so notifications must be synthesized by polling
+/// (see `sync::watch_status`).
+pub(super) fn spawn_http(url: String) -> Result<mpsc::Sender<RpcRequest>> {
+    let http = reqwest::Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()
+        .map_err(|e| Error::Custom(format!("Failed to create HTTP client: {}", e)))?;
+    let (tx, mut rx) = mpsc::channel::<RpcRequest>(100);
+
+    tokio::spawn(async move {
+        while let Some(req) = rx.recv().await {
+            let http = http.clone();
+            let url = url.clone();
+            // Requests are independent, so run them concurrently
+            tokio::spawn(async move {
+                let id = RPC_ID_COUNTER.fetch_add(1, Ordering::Relaxed);
+                let body = request_body(id, &req).to_string();
+                let result = post(&http, &url, body).await;
+                let _ = req.response_tx.send(result);
+            });
+        }
+    });
+
+    Ok(tx)
+}
+
+async fn post(http: &reqwest::Client, url: &str, body: String) -> Result<Value> {
+    let response = http
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| {
+            if e.is_timeout() {
+                Error::Timeout
+            } else {
+                Error::Disconnected(format!("HTTP request failed: {}", e))
+            }
+        })?;
+
+    // aria2 reports RPC errors with a non-2xx status but a regular JSON-RPC body
+    let status = response.status();
+    let text = response
+        .text()
+        .await
+        .map_err(|e| Error::Disconnected(format!("Failed to read HTTP response: {}", e)))?;
+    match serde_json::from_str::<RpcResponse>(&text) {
+        Ok(response) => response.into_result(),
+        Err(_) => Err(Error::Protocol(format!("unexpected HTTP response ({})", status))),
+    }
+}
@@ -0,0 +1,381 @@
+//! Typed models for aria2 RPC responses
+//!
+//! aria2 encodes almost every number and boolean as a JSON string ("1024",
+//! "true"). The models below decode those into native types so callers never
+//! have to guess field names or parse strings themselves. Numbers serialize
+//! back out as plain JSON numbers.
+
+use crate::error::{DownloadExit, Error};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Task or global options (`getOption`, `getGlobalOption`); aria2 reports every value as a string
+pub type Options = BTreeMap<String, String>;
+
+/// Download status as reported by `tellStatus`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskStatus {
+    Active,
+    Waiting,
+    Paused,
+    Error,
+    Complete,
+    Removed,
+}
+
+/// A download task (`tellStatus`, `tellActive`, `tellWaiting`, `tellStopped`)
+///
+/// Only `gid` and `status` are required; every other field may be absent when
+/// aria2 omits it or when a key filter was applied.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Task {
+    pub gid: String,
+    pub status: TaskStatus,
+    #[serde(default, with = "num")]
+    pub total_length: u64,
+    #[serde(default, with = "num")]
+    pub completed_length: u64,
+    #[serde(default, with = "num")]
+    pub upload_length: u64,
+    #[serde(default, with = "num")]
+    pub download_speed: u64,
+    #[serde(default, with = "num")]
+    pub upload_speed: u64,
+    #[serde(default, with = "num")]
+    pub connections: u64,
+    #[serde(default, with = "num", skip_serializing_if = "Option::is_none")]
+    pub num_seeders: Option<u64>,
+    #[serde(default, with = "flag", skip_serializing_if = "Option::is_none")]
+    pub seeder: Option<bool>,
+    #[serde(default, with = "num")]
+    pub piece_length: u64,
+    #[serde(default, with = "num")]
+    pub num_pieces: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitfield: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub info_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_message: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub followed_by: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub following: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub belongs_to: Option<String>,
+    #[serde(default)]
+    pub dir: String,
+    #[serde(default)]
+    pub files: Vec<TaskFile>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bittorrent: Option<BittorrentInfo>,
+    #[serde(default, with = "num", skip_serializing_if = "Option::is_none")]
+    pub verified_length: Option<u64>,
+    #[serde(default, with = "flag", skip_serializing_if = "Option::is_none")]
+    pub verify_integrity_pending: Option<bool>,
+}
+
+impl Task {
+    /// Display name: the torrent name, else the first file's name (empty before metadata arrives)
+    pub fn name(&self) -> &str {
+        if let Some(info) = self.bittorrent.as_ref().and_then(|bt| bt.info.as_ref()) {
+            return &info.name;
+        }
+        self.files
+            .first()
+            .and_then(|file| file.path.rsplit(['/', '\\']).next())
+            .unwrap_or_default()
+    }
+
+    /// Why the task failed, if it stopped with a non-zero `errorCode`
+    pub fn error(&self) -> Option<Error> {
+        let code: u8 = self.error_code.as_deref()?.parse().ok()?;
+        if code == 0 {
+            return None;
+        }
+        let message = match &self.error_message {
+            Some(message) if !message.is_empty() => message.clone(),
+            _ => DownloadExit::from_code(code)
+                .map_or("Unknown error", DownloadExit::description)
+                .to_string(),
+        };
+        Some(Error::Download { code, message })
+    }
+}
+
+/// A file belonging to a task
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskFile {
+    #[serde(with = "num")]
+    pub index: u32,
+    #[serde(default)]
+    pub path: String,
+    #[serde(default, with = "num")]
+    pub length: u64,
+    #[serde(default, with = "num")]
+    pub completed_length: u64,
+    #[serde(default, with = "flag")]
+    pub selected: bool,
+    #[serde(default)]
+    pub uris: Vec<TaskUri>,
+}
+
+/// A URI attached to a task file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskUri {
+    pub uri: String,
+    pub status: UriStatus,
+}
+
+/// Whether a URI is in use or still queued
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UriStatus {
+    Used,
+    Waiting,
+}
+
+/// BitTorrent metadata of a task
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BittorrentInfo {
+    #[serde(default)]
+    pub announce_list: Vec<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub creation_date: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<BittorrentMode>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub info: Option<BittorrentName>,
+}
+
+/// File mode of a torrent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BittorrentMode {
+    Single,
+    Multi,
+}
+
+/// The `info` dictionary of a torrent (aria2 only exposes the name)
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct BittorrentName {
+    #[serde(default)]
+    pub name: String,
+}
+
+/// A BitTorrent peer (`getPeers`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Peer {
+    #[serde(default)]
+    pub peer_id: String,
+    pub ip: String,
+    #[serde(with = "num")]
+    pub port: u16,
+    #[serde(default)]
+    pub bitfield: String,
+    #[serde(default, with = "flag")]
+    pub am_choking: bool,
+    #[serde(default, with = "flag")]
+    pub peer_choking: bool,
+    #[serde(default, with = "num")]
+    pub download_speed: u64,
+    #[serde(default, with = "num")]
+    pub upload_speed: u64,
+    #[serde(default, with = "flag")]
+    pub seeder: bool,
+}
+
+/// Global transfer statistics (`getGlobalStat`)
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GlobalStat {
+    #[serde(with = "num")]
+    pub download_speed: u64,
+    #[serde(with = "num")]
+    pub upload_speed: u64,
+    #[serde(with = "num")]
+    pub num_active: u64,
+    #[serde(with = "num")]
+    pub num_waiting: u64,
+    #[serde(with = "num")]
+    pub num_stopped: u64,
+    #[serde(with = "num")]
+    pub num_stopped_total: u64,
+}
+
+/// The servers a file is currently downloaded from (`getServers`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileServers {
+    #[serde(with = "num")]
+    pub index: u32,
+    #[serde(default)]
+    pub servers: Vec<Server>,
+}
+
+/// A mirror in use for a file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Server {
+    /// The URI as added
+    pub uri: String,
+    /// The URI actually being downloaded after redirects
+    pub current_uri: String,
+    #[serde(default, with = "num")]
+    pub download_speed: u64,
+}
+
+/// Number of URIs removed and added by `changeUri`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "(u32, u32)")]
+pub struct UriChange {
+    pub deleted: u32,
+    pub added: u32,
+}
+
+impl From<(u32, u32)> for UriChange {
+    fn from((deleted, added): (u32, u32)) -> Self {
+        Self { deleted, added }
+    }
+}
+
+/// The current aria2 session (`getSessionInfo`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionInfo {
+    pub session_id: String,
+}
+
+/// (De)serialize numbers that aria2 sends as strings; also accepts plain
+/// JSON numbers so values round-trip through our own serialization.
+mod num {
+    use serde::de::{self, Deserializer};
+    use serde::{Deserialize, Serialize, Serializer};
+    use std::fmt::Display;
+    use std::str::FromStr;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        Str(String),
+        Num(serde_json::Number),
+    }
+
+    pub trait Field: Sized {
+        fn parse<E: de::Error>(raw: Option<String>) -> Result<Self, E>;
+        fn emit<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>;
+    }
+
+    fn parse_str<T, E>(s: &str) -> Result<T, E>
+    where
+        T: FromStr,
+        T::Err: Display,
+        E: de::Error,
+    {
+        s.trim()
+            .parse()
+            .map_err(|e| E::custom(format!("invalid number {:?}: {}", s, e)))
+    }
+
+    macro_rules! impl_field {
+        ($($t:ty),*) => {$(
+            impl Field for $t {
+                fn parse<E: de::Error>(raw: Option<String>) -> Result<Self, E> {
+                    match raw {
+                        Some(s) => parse_str(&s),
+                        None => Err(E::custom("expected a number, found null")),
+                    }
+                }
+                fn emit<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    self.serialize(serializer)
+                }
+            }
+
+            impl Field for Option<$t> {
+                fn parse<E: de::Error>(raw: Option<String>) -> Result<Self, E> {
+                    raw.map(|s| parse_str(&s)).transpose()
+                }
+                fn emit<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    self.serialize(serializer)
+                }
+            }
+        )*};
+    }
+
+    impl_field!(u16, u32, u64);
+
+    pub fn serialize<T: Field, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        value.emit(serializer)
+    }
+
+    pub fn deserialize<'de, T: Field, D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+        let raw = Option::<Raw>::deserialize(deserializer)?.map(|raw| match raw {
+            Raw::Str(s) => s,
+            Raw::Num(n) => n.to_string(),
+        });
+        T::parse(raw)
+    }
+}
+
+/// (De)serialize booleans that aria2 sends as "true"/"false"
+mod flag {
+    use serde::de::{self, Deserializer};
+    use serde::{Deserialize, Serialize, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        Str(String),
+        Bool(bool),
+    }
+
+    pub trait Field: Sized {
+        fn parse<E: de::Error>(raw: Option<bool>) -> Result<Self, E>;
+        fn emit<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>;
+    }
+
+    impl Field for bool {
+        fn parse<E: de::Error>(raw: Option<bool>) -> Result<Self, E> {
+            raw.ok_or_else(|| E::custom("expected a boolean, found null"))
+        }
+        fn emit<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            self.serialize(serializer)
+        }
+    }
+
+    impl Field for Option<bool> {
+        fn parse<E: de::Error>(raw: Option<bool>) -> Result<Self, E> {
+            Ok(raw)
+        }
+        fn emit<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            self.serialize(serializer)
+        }
+    }
+
+    pub fn serialize<T: Field, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        value.emit(serializer)
+    }
+
+    pub fn deserialize<'de, T: Field, D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+        let raw = match Option::<Raw>::deserialize(deserializer)? {
+            Some(Raw::Bool(b)) => Some(b),
+            Some(Raw::Str(s)) => match s.as_str() {
+                "true" => Some(true),
+                "false" => Some(false),
+                other => return Err(de::Error::custom(format!("invalid boolean {:?}", other))),
+            },
+            None => None,
+        };
+        T::parse(raw)
+    }
+}
@@ -0,0 +1,40 @@
+//! Command line control of the running app, or of aria2 when it is not running
+//!
+//! ```text
+//! motrix-cli add --dir artifacts https://example.com/build.tar.gz
+//! motrix-cli --json list active
+//! motrix-cli wait 2089b05ecca3d829 --timeout 600
+//! ```
+
+use motrix_lib::control::{self, Target};
+use motrix_lib::ipc;
+use std::process::ExitCode;
+
+async fn run(args: &[String]) -> motrix_lib::Result<()> {
+    let cwd = std::env::current_dir()?;
+    let invocation = control::parse(args, &cwd)?;
+    let target = Target::connect(invocation.connection, &ipc::socket_path(), invocation.config.as_deref()).await?;
+    control::run(
+        &target,
+        invocation.command,
+        invocation.json,
+        &mut std::io::stdout().lock(),
+    )
+    .await
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.is_empty() || args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", control::USAGE);
+        return ExitCode::SUCCESS;
+    }
+    match run(&args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("motrix-cli: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! Native messaging host for the Motrix browser extensions
+//!
+//! Browsers run it with the calling extension as argument and talk to it over
+//! stdio, so nothing but messages may be written to stdout. Run by hand it
+//! writes or removes the host manifests:
+//!
+//! ```text
+//! motrix-native-host --install [--chrome-extension ID]... [--firefox-extension ID]...
+//! motrix-native-host --uninstall
+//! ```
+
+use motrix_lib::ipc;
+use motrix_lib::native_host::{self, ExtensionIds};
+use std::process::ExitCode;
+
+fn install(args: &[String]) -> motrix_lib::Result<()> {
+    let mut ids = ExtensionIds::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let list = match arg.as_str() {
+            "--chrome-extension" => &mut ids.chromium,
+            "--firefox-extension" => &mut ids.firefox,
+            _ => return Err(motrix_lib::Error::Validation(format!("Unknown argument: {}", arg))),
+        };
+        match args.next() {
+            Some(id) => list.push(id.clone()),
+            None => return Err(motrix_lib::Error::Validation(format!("{} needs an extension ID", arg))),
+        }
+    }
+    if ids == ExtensionIds::default() {
+        return Err(motrix_lib::Error::Validation(
+            "Give the extensions to allow with --chrome-extension or --firefox-extension".to_string(),
+        ));
+    }
+
+    let home = dirs::home_dir().ok_or_else(|| motrix_lib::Error::Custom("No home directory".to_string()))?;
+    let host = std::env::current_exe()?.canonicalize()?;
+    let written = native_host::install(&home, &host, &ids)?;
+    if written.is_empty() {
+        println!("No browser profiles found");
+    }
+    for path in written {
+        println!("Wrote {}", path.display());
+    }
+    Ok(())
+}
+
+fn uninstall() -> motrix_lib::Result<()> {
+    let home = dirs::home_dir().ok_or_else(|| motrix_lib::Error::Custom("No home directory".to_string()))?;
+    for path in native_host::uninstall(&home)? {
+        println!("Removed {}", path.display());
+    }
+    Ok(())
+}
+
+/// Answer the browser's messages until it closes stdin
+async fn run() -> std::io::Result<()> {
+    let socket = ipc::socket_path();
+    let mut stdin = std::io::stdin().lock();
+    let mut stdout = std::io::stdout().lock();
+    while let Some(message) = native_host::read_message(&mut stdin)? {
+        let reply = native_host::handle(&message, &socket).await;
+        native_host::write_message(&mut stdout, &reply)?;
+    }
+    Ok(())
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("--install") => install(&args[1..]),
+        Some("--uninstall") => uninstall(),
+        // Started by a browser with the extension origin or the manifest path
+        _ => run().await.map_err(Into::into),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("motrix-native-host: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
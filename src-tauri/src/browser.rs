@@ -0,0 +1,458 @@
+//! Local HTTP endpoint for browser extensions
+//!
+//! The `motrix://` deep link only carries a URL, which is not enough for
+//! downloads behind a logged-in session. Extensions instead POST the captured
+//! download as JSON to `http://127.0.0.1:<port>/download`, authenticated with
+//! `Authorization: Bearer <token>`. Its cookies, referer, user agent and
+//! suggested file name become per-task aria2 options, and the download is
+//! added like any other, so category rules and duplicate detection apply.
+//!
+//! aria2 only downloads with GET. A download the page started with a form
+//! POST is replayed here with the session, and aria2 fetches the file the
+//! server redirects to (Post/Redirect/Get) with the cookies it set.
+
+use crate::commands;
+use crate::config::BrowserIntegration;
+use crate::duplicate::DuplicateAction;
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::async_runtime::JoinHandle;
+use tauri::Url;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+
+/// Largest request head accepted
+const MAX_HEAD: usize = 16 * 1024;
+
+/// Largest request body accepted
+const MAX_BODY: usize = 1024 * 1024;
+
+/// Time a client has to send its whole request
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Schemes aria2 can download
+const SCHEMES: [&str; 5] = ["http", "https", "ftp", "sftp", "magnet"];
+
+/// Time the server of a form POST has to answer
+const POST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Cookies as a `Cookie` header value or as the browser's cookie list
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Cookies {
+    Header(String),
+    List(Vec<Cookie>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+}
+
+impl Cookies {
+    /// Value of the `Cookie` request header
+    fn header(&self) -> String {
+        match self {
+            Cookies::Header(header) => header.trim().to_string(),
+            Cookies::List(cookies) => cookies
+                .iter()
+                .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+                .collect::<Vec<_>>()
+                .join("; "),
+        }
+    }
+}
+
+/// A download captured by a browser extension
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BrowserDownload {
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cookies: Option<Cookies>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub referer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    /// Further request headers, e.g. `Authorization`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// File name the browser suggested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+    /// Body of a download the page started with a form POST
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub post_data: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_duplicate: Option<DuplicateAction>,
+}
+
+/// Reject text that would end a header line early
+fn header_safe<'a>(what: &str, text: &'a str) -> Result<&'a str> {
+    if text.contains(['\r', '\n', '\0']) {
+        return Err(Error::Validation(format!("{} must be a single line", what)));
+    }
+    Ok(text.trim())
+}
+
+/// The last path component of a suggested file name, if it is a usable one
+fn file_name(name: &str) -> Option<String> {
+    let name: String = name
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect();
+    let name = name.trim();
+    (!name.is_empty() && name != "." && name != "..").then(|| name.to_string())
+}
+
+impl BrowserDownload {
+    /// The aria2 options carrying the browser session
+    pub fn options(&self) -> Result<Value> {
+        let url = Url::parse(self.url.trim()).map_err(|e| Error::Validation(format!("Invalid URL: {}", e)))?;
+        if !SCHEMES.contains(&url.scheme()) {
+            return Err(Error::Validation(format!("Unsupported URL scheme: {}", url.scheme())));
+        }
+
+        let mut options = Map::new();
+        let mut headers = Vec::new();
+        if let Some(cookies) = &self.cookies {
+            let cookie = cookies.header();
+            if !header_safe("Cookies", &cookie)?.is_empty() {
+                headers.push(format!("Cookie: {}", cookie));
+            }
+        }
+        for (name, value) in &self.headers {
+            let name = header_safe("Header names", name)?;
+            if name.is_empty() || name.contains(':') {
+                return Err(Error::Validation(format!("Invalid header name: {}", name)));
+            }
+            headers.push(format!("{}: {}", name, header_safe("Header values", value)?));
+        }
+        if !headers.is_empty() {
+            options.insert("header".to_string(), json!(headers));
+        }
+        if let Some(referer) = self.referer.as_deref() {
+            let referer = header_safe("The referer", referer)?;
+            if !referer.is_empty() {
+                options.insert("referer".to_string(), json!(referer));
+            }
+        }
+        if let Some(user_agent) = self.user_agent.as_deref() {
+            let user_agent = header_safe("The user agent", user_agent)?;
+            if !user_agent.is_empty() {
+                options.insert("user-agent".to_string(), json!(user_agent));
+            }
+        }
+        if let Some(out) = self.filename.as_deref().and_then(file_name) {
+            options.insert("out".to_string(), json!(out));
+        }
+        Ok(Value::Object(options))
+    }
+
+    /// Replay a form POST to find the URL aria2 can download with GET
+    ///
+    /// Forms that start a download answer with a redirect to the file; the
+    /// download becomes a GET of that URL carrying the cookies the answer set.
+    /// Downloads without POST data are returned as they are.
+    pub async fn resolve_post(mut self) -> Result<Self> {
+        let Some(data) = self.post_data.take().filter(|data| !data.is_empty()) else {
+            return Ok(self);
+        };
+        let options = self.options()?;
+        let url = Url::parse(self.url.trim()).map_err(|e| Error::Validation(format!("Invalid URL: {}", e)))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(Error::Validation("Only HTTP downloads can be requested with POST".to_string()));
+        }
+
+        let http = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .timeout(POST_TIMEOUT)
+            .build()
+            .map_err(|e| Error::Custom(format!("Failed to create an HTTP client: {}", e)))?;
+        let mut request = http.post(url.clone()).body(data);
+        let headers = options["header"].as_array().cloned().unwrap_or_default();
+        let mut typed = false;
+        for header in headers.iter().filter_map(Value::as_str) {
+            if let Some((name, value)) = header.split_once(": ") {
+                typed |= name.eq_ignore_ascii_case("content-type");
+                request = request.header(name, value);
+            }
+        }
+        if !typed {
+            request = request.header(reqwest::header::CONTENT_TYPE, "application/x-www-form-urlencoded");
+        }
+        if let Some(referer) = options["referer"].as_str() {
+            request = request.header(reqwest::header::REFERER, referer);
+        }
+        if let Some(user_agent) = options["user-agent"].as_str() {
+            request = request.header(reqwest::header::USER_AGENT, user_agent);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| Error::Custom(format!("Failed to send the form to {}: {}", url, e)))?;
+
+        let location = response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .filter(|_| response.status().is_redirection());
+        let Some(location) = location else {
+            return Err(Error::Unsupported(format!(
+                "{} answered the form with {} instead of a link to the file, and aria2 only downloads with GET",
+                url,
+                response.status()
+            )));
+        };
+        let target = url
+            .join(location)
+            .map_err(|e| Error::Validation(format!("Invalid redirect from {}: {}", url, e)))?;
+        self.url = target.to_string();
+
+        // Cookies set by the answer replace the page's cookies of the same name;
+        // the page's cookies only go along to the form's own host
+        let origin = |url: &Url| (url.host_str().map(str::to_string), url.port_or_known_default());
+        let known = self.cookies.as_ref().filter(|_| origin(&target) == origin(&url));
+        let known = known.map(Cookies::header).unwrap_or_default();
+        let set = response.headers().get_all(reqwest::header::SET_COOKIE);
+        let set = set.iter().filter_map(|value| value.to_str().ok()?.split(';').next());
+        let mut cookies: Vec<&str> = Vec::new();
+        for pair in known.split(';').chain(set).map(str::trim).filter(|pair| !pair.is_empty()) {
+            let name = pair.split('=').next().unwrap_or_default();
+            cookies.retain(|cookie| cookie.split('=').next() != Some(name));
+            cookies.push(pair);
+        }
+        self.cookies = (!cookies.is_empty()).then(|| Cookies::Header(cookies.join("; ")));
+        Ok(self)
+    }
+}
+
+/// Add a captured download, answering with its gid
+///
+/// A download started with a form POST is first replayed to find its file.
+pub async fn add(download: BrowserDownload) -> Result<String> {
+    let download = download.resolve_post().await?;
+    let options = download.options()?;
+    commands::add_uri(vec![download.url.trim().to_string()], Some(options), None, download.on_duplicate).await
+}
+
+/// The running endpoint and the settings it was started with
+struct Server {
+    config: BrowserIntegration,
+    task: JoinHandle<()>,
+}
+
+static SERVER: Mutex<Option<Server>> = Mutex::const_new(None);
+
+/// Start, restart or stop the endpoint to match `config`
+pub async fn configure(config: &BrowserIntegration) -> Result<()> {
+    let mut server = SERVER.lock().await;
+    if server.as_ref().is_some_and(|server| &server.config == config) {
+        return Ok(());
+    }
+    if let Some(old) = server.take() {
+        old.task.abort();
+        // Wait for the listener to be dropped so its port can be bound again
+        let _ = old.task.await;
+        tracing::info!("Browser integration endpoint stopped");
+    }
+    if !config.enabled {
+        return Ok(());
+    }
+    if config.token.is_empty() {
+        return Err(Error::Validation("The browser integration token is empty".to_string()));
+    }
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, config.port)).await?;
+    tracing::info!("Browser integration endpoint listening on {}", listener.local_addr()?);
+    let token = config.token.clone();
+    *server = Some(Server {
+        config: config.clone(),
+        task: tauri::async_runtime::spawn(serve(listener, token)),
+    });
+    Ok(())
+}
+
+/// Answer requests on `listener`, accepting those bearing `token`
+pub async fn serve(listener: TcpListener, token: String) {
+    let token: Arc<str> = token.into();
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let token = token.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle_connection(stream, &token).await {
+                        tracing::debug!("Browser integration request failed: {}", e);
+                    }
+                });
+            }
+            Err(e) => {
+                tracing::warn!("Browser integration endpoint failed to accept: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        }
+    }
+}
+
+/// A parsed HTTP request
+struct Request {
+    method: String,
+    path: String,
+    /// Lowercase names
+    headers: BTreeMap<String, String>,
+    body: Vec<u8>,
+}
+
+/// Why a request could not be read
+enum ReadError {
+    Io(std::io::Error),
+    Status(u16, &'static str),
+}
+
+impl From<std::io::Error> for ReadError {
+    fn from(e: std::io::Error) -> Self {
+        ReadError::Io(e)
+    }
+}
+
+async fn read_request(stream: &mut TcpStream) -> std::result::Result<Request, ReadError> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let head_end = loop {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(ReadError::Status(400, "Incomplete request"));
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos;
+        }
+        if buf.len() > MAX_HEAD {
+            return Err(ReadError::Status(431, "Request head too large"));
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..head_end]).into_owned();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let (Some(method), Some(target)) = (request_line.next(), request_line.next()) else {
+        return Err(ReadError::Status(400, "Malformed request line"));
+    };
+    let headers: BTreeMap<String, String> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+    let length = match headers.get("content-length") {
+        Some(length) => length.parse().map_err(|_| ReadError::Status(400, "Invalid Content-Length"))?,
+        None => 0,
+    };
+    if length > MAX_BODY {
+        return Err(ReadError::Status(413, "Request body too large"));
+    }
+
+    let mut body = buf.split_off(head_end + 4);
+    while body.len() < length {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(ReadError::Status(400, "Incomplete request body"));
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+    body.truncate(length);
+    Ok(Request {
+        method: method.to_string(),
+        path: target.split('?').next().unwrap_or_default().to_string(),
+        headers,
+        body,
+    })
+}
+
+/// Compare tokens in time independent of where they differ
+fn token_matches(given: &str, token: &str) -> bool {
+    given.len() == token.len() && given.bytes().zip(token.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// HTTP status for an error from adding a download
+fn status_of(error: &Error) -> u16 {
+    match error {
+        Error::Validation(_) | Error::Unsupported(_) | Error::Json(_) => 400,
+        Error::Duplicate(_) => 409,
+        Error::Disconnected(_) | Error::Timeout => 503,
+        Error::Aria2Rpc { .. } => 502,
+        _ => 500,
+    }
+}
+
+async fn handle(request: Request, token: &str) -> (u16, Value) {
+    let authorized = request
+        .headers
+        .get("authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|given| token_matches(given.trim(), token));
+    if !authorized {
+        let error = Error::Validation("Missing or wrong browser integration token".to_string());
+        return (401, json!(error));
+    }
+
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/ping") => (200, json!({ "app": "motrix", "version": env!("CARGO_PKG_VERSION") })),
+        ("POST", "/download") => {
+            let result = match serde_json::from_slice::<BrowserDownload>(&request.body) {
+                Ok(download) => add(download).await,
+                Err(e) => Err(e.into()),
+            };
+            match result {
+                Ok(gid) => (200, json!({ "gid": gid })),
+                Err(e) => (status_of(&e), json!(e)),
+            }
+        }
+        (_, "/ping" | "/download") => (405, json!(Error::Validation("Method not allowed".to_string()))),
+        _ => (404, json!(Error::Validation("Not found".to_string()))),
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, token: &str) -> std::io::Result<()> {
+    let (status, body) = match tokio::time::timeout(READ_TIMEOUT, read_request(&mut stream)).await {
+        Ok(Ok(request)) => handle(request, token).await,
+        Ok(Err(ReadError::Status(status, message))) => (status, json!(Error::Validation(message.to_string()))),
+        Ok(Err(ReadError::Io(e))) => return Err(e),
+        Err(_) => (408, json!(Error::Validation("Request timed out".to_string()))),
+    };
+    let body = body.to_string();
+    let response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason(status),
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
@@ -0,0 +1,607 @@
+//! Rule-based download categories
+//!
+//! Category rules (see `CategoryRule`) route a download to a folder of its
+//! own, optionally with a per-task speed limit and a tag kept in the history.
+//! They are applied when a task is added, from its URIs and whatever name is
+//! known then, and again once aria2 reports the real file name: a task whose
+//! name only shows up after redirects, `Content-Disposition` or magnet
+//! metadata is paused, moved to its category folder and resumed. Tasks are
+//! looked at again every few seconds until their name is known.
+//!
+//! Tasks added with a folder other than the default download dir keep it, and
+//! so do tasks that have written data already, which a move would restart.
+
+use crate::aria2::{Aria2Client, Aria2EventType, ClientEvent, ConnectionState, Task, TaskStatus};
+use crate::config::{AppConfig, CategoryRule};
+use crate::{Error, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use tauri::Url;
+use tokio::sync::{broadcast, Mutex};
+
+/// Rules of the running app, set by `configure`
+static CATEGORIES: Mutex<Option<Categories>> = Mutex::const_new(None);
+
+/// How often tasks whose name is still unknown are looked at again
+const RECHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How the tasks added this session were routed, by gid, until aria2 drops their records
+static ROUTED: Mutex<BTreeMap<String, Routing>> = Mutex::const_new(BTreeMap::new());
+
+/// MIME types by extension, for the kinds of files commonly downloaded
+const MIME_TYPES: &[(&str, &str)] = &[
+    ("7z", "application/x-7z-compressed"),
+    ("aac", "audio/aac"),
+    ("apk", "application/vnd.android.package-archive"),
+    ("avi", "video/x-msvideo"),
+    ("bmp", "image/bmp"),
+    ("bz2", "application/x-bzip2"),
+    ("deb", "application/vnd.debian.binary-package"),
+    ("dmg", "application/x-apple-diskimage"),
+    ("doc", "application/msword"),
+    ("docx", "application/vnd.openxmlformats-officedocument.wordprocessingml.document"),
+    ("epub", "application/epub+zip"),
+    ("exe", "application/vnd.microsoft.portable-executable"),
+    ("flac", "audio/flac"),
+    ("flv", "video/x-flv"),
+    ("gif", "image/gif"),
+    ("gz", "application/gzip"),
+    ("htm", "text/html"),
+    ("html", "text/html"),
+    ("iso", "application/x-iso9660-image"),
+    ("jpeg", "image/jpeg"),
+    ("jpg", "image/jpeg"),
+    ("json", "application/json"),
+    ("m4a", "audio/mp4"),
+    ("m4v", "video/mp4"),
+    ("mkv", "video/x-matroska"),
+    ("mov", "video/quicktime"),
+    ("mp3", "audio/mpeg"),
+    ("mp4", "video/mp4"),
+    ("msi", "application/x-msi"),
+    ("ogg", "audio/ogg"),
+    ("opus", "audio/opus"),
+    ("pdf", "application/pdf"),
+    ("png", "image/png"),
+    ("ppt", "application/vnd.ms-powerpoint"),
+    ("pptx", "application/vnd.openxmlformats-officedocument.presentationml.presentation"),
+    ("rar", "application/vnd.rar"),
+    ("rpm", "application/x-rpm"),
+    ("srt", "application/x-subrip"),
+    ("svg", "image/svg+xml"),
+    ("tar", "application/x-tar"),
+    ("torrent", "application/x-bittorrent"),
+    ("ts", "video/mp2t"),
+    ("txt", "text/plain"),
+    ("wav", "audio/wav"),
+    ("webm", "video/webm"),
+    ("webp", "image/webp"),
+    ("wmv", "video/x-ms-wmv"),
+    ("xls", "application/vnd.ms-excel"),
+    ("xlsx", "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"),
+    ("xz", "application/x-xz"),
+    ("zip", "application/zip"),
+];
+
+/// MIME type of a file, guessed from its extension
+pub fn mime_type(name: &str) -> Option<&'static str> {
+    let (_, ext) = name.rsplit_once('.')?;
+    let ext = ext.to_ascii_lowercase();
+    MIME_TYPES.iter().find(|(known, _)| *known == ext).map(|(_, mime)| *mime)
+}
+
+/// What is known about a download when matching it against the rules
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Source {
+    pub uris: Vec<String>,
+    /// Names of the file to download, empty while unknown
+    pub names: Vec<String>,
+}
+
+impl Source {
+    /// A download of `uris`, saved as `out` when given
+    pub fn from_uris(uris: &[String], out: Option<&str>) -> Self {
+        let mut names = Vec::new();
+        match out.filter(|out| !out.is_empty()) {
+            Some(out) => names.push(file_name(out).to_string()),
+            None => {
+                for name in uris.iter().filter_map(|uri| name_in_uri(uri)) {
+                    if !names.contains(&name) {
+                        names.push(name);
+                    }
+                }
+            }
+        }
+        Self { uris: uris.to_vec(), names }
+    }
+
+    /// A torrent, named after its largest file
+    pub fn from_torrent(data: &[u8]) -> Self {
+        use serde_bencode::value::Value as Bencode;
+
+        let text = |value: Option<&Bencode>| match value {
+            Some(Bencode::Bytes(bytes)) => Some(String::from_utf8_lossy(bytes).into_owned()),
+            _ => None,
+        };
+        let Ok(Bencode::Dict(torrent)) = serde_bencode::from_bytes::<Bencode>(data) else {
+            return Self::default();
+        };
+        let Some(Bencode::Dict(info)) = torrent.get(b"info".as_slice()) else {
+            return Self::default();
+        };
+        let name = match info.get(b"files".as_slice()) {
+            Some(Bencode::List(files)) => files
+                .iter()
+                .filter_map(|file| {
+                    let Bencode::Dict(file) = file else {
+                        return None;
+                    };
+                    let length = match file.get(b"length".as_slice()) {
+                        Some(Bencode::Int(length)) => *length,
+                        _ => 0,
+                    };
+                    match file.get(b"path".as_slice()) {
+                        Some(Bencode::List(parts)) => Some((length, text(parts.last())?)),
+                        _ => None,
+                    }
+                })
+                .max_by_key(|(length, _)| *length)
+                .map(|(_, name)| name),
+            // A single-file torrent is named after its file
+            _ => text(info.get(b"name".as_slice())),
+        };
+        Self {
+            uris: Vec::new(),
+            names: name.into_iter().collect(),
+        }
+    }
+
+    /// A task as aria2 reports it, named after its largest selected file
+    pub fn from_task(task: &Task) -> Self {
+        let mut uris: Vec<String> = Vec::new();
+        for uri in task.files.iter().flat_map(|file| &file.uris) {
+            if !uris.contains(&uri.uri) {
+                uris.push(uri.uri.clone());
+            }
+        }
+        let name = task
+            .files
+            .iter()
+            // A magnet's placeholder until its metadata arrives
+            .filter(|file| file.selected && !file.path.is_empty() && !file.path.starts_with("[METADATA]"))
+            .max_by_key(|file| file.length)
+            .map(|file| file_name(&file.path).to_string());
+        Self {
+            uris,
+            names: name.into_iter().collect(),
+        }
+    }
+}
+
+/// Last component of a path
+fn file_name(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+/// Name of the file a URI points at: its last path segment, or a magnet's display name
+fn name_in_uri(uri: &str) -> Option<String> {
+    let url = Url::parse(uri).ok()?;
+    if url.scheme() == "magnet" {
+        return url
+            .query_pairs()
+            .find(|(key, _)| key == "dn")
+            .map(|(_, name)| name.into_owned());
+    }
+    let segment = url.path_segments()?.rev().find(|segment| !segment.is_empty())?;
+    Some(segment.to_string())
+}
+
+/// Whether `name` (lowercase) ends in the extension `ext`
+fn has_extension(name: &str, ext: &str) -> bool {
+    let ext = ext.trim().trim_start_matches('.').to_lowercase();
+    !ext.is_empty()
+        && name
+            .strip_suffix(ext.as_str())
+            .is_some_and(|stem| stem.len() > 1 && stem.ends_with('.'))
+}
+
+/// Whether `mime` is `pattern`, or of the type of a `type/*` pattern
+fn mime_matches(pattern: &str, mime: &str) -> bool {
+    let pattern = pattern.trim().to_ascii_lowercase();
+    match pattern.strip_suffix("/*") {
+        Some(kind) => mime.split('/').next() == Some(kind),
+        None => pattern == mime,
+    }
+}
+
+/// Whether `host` is `domain` or one of its subdomains
+fn domain_matches(host: &str, domain: &str) -> bool {
+    let domain = domain.trim().trim_start_matches("*.").trim_start_matches('.').to_ascii_lowercase();
+    !domain.is_empty() && (host == domain || host.ends_with(&format!(".{}", domain)))
+}
+
+/// Whether every criterion set on `rule` matches `source`
+fn matches(rule: &CategoryRule, pattern: Option<&Regex>, source: &Source) -> bool {
+    let names: Vec<String> = source.names.iter().map(|name| name.to_lowercase()).collect();
+    let hosts: Vec<String> = source
+        .uris
+        .iter()
+        .filter_map(|uri| Url::parse(uri).ok()?.host_str().map(str::to_ascii_lowercase))
+        .collect();
+    let criteria = [
+        (!rule.extensions.is_empty())
+            .then(|| rule.extensions.iter().any(|ext| names.iter().any(|name| has_extension(name, ext)))),
+        (!rule.mime_types.is_empty()).then(|| {
+            names
+                .iter()
+                .filter_map(|name| mime_type(name))
+                .any(|mime| rule.mime_types.iter().any(|pattern| mime_matches(pattern, mime)))
+        }),
+        (!rule.domains.is_empty())
+            .then(|| hosts.iter().any(|host| rule.domains.iter().any(|domain| domain_matches(host, domain)))),
+        pattern.map(|pattern| source.uris.iter().any(|uri| pattern.is_match(uri))),
+    ];
+    criteria.iter().any(Option::is_some) && criteria.iter().all(|matched| matched.unwrap_or(true))
+}
+
+/// Category rules compiled for matching
+#[derive(Debug, Clone)]
+pub struct Categories {
+    download_dir: PathBuf,
+    rules: Vec<(CategoryRule, Option<Regex>)>,
+}
+
+impl Categories {
+    /// Compile the rules of `config`, failing on an invalid URL pattern
+    pub fn new(config: &AppConfig) -> Result<Self> {
+        let rules = config
+            .categories
+            .iter()
+            .map(|rule| {
+                let pattern = rule
+                    .url_pattern
+                    .as_deref()
+                    .filter(|pattern| !pattern.is_empty())
+                    .map(Regex::new)
+                    .transpose()
+                    .map_err(|e| Error::Validation(format!("invalid URL pattern in category {}: {}", rule.name, e)))?;
+                Ok((rule.clone(), pattern))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            download_dir: config.download_dir.clone(),
+            rules,
+        })
+    }
+
+    /// First rule matching `source`
+    pub fn classify(&self, source: &Source) -> Option<&CategoryRule> {
+        self.rules
+            .iter()
+            .find(|(rule, pattern)| matches(rule, pattern.as_ref(), source))
+            .map(|(rule, _)| rule)
+    }
+
+    /// Folder for downloads of `rule`; the download dir without a rule or a folder
+    pub fn dir(&self, rule: Option<&CategoryRule>) -> PathBuf {
+        match rule.map(|rule| rule.dir.trim()).filter(|dir| !dir.is_empty()) {
+            Some(dir) => self.download_dir.join(dir),
+            None => self.download_dir.clone(),
+        }
+    }
+
+    /// Whether `options` leave the folder to the rules: none given, or the download dir
+    pub fn is_default_dir(&self, options: &Map<String, Value>) -> bool {
+        match options.get("dir").and_then(Value::as_str) {
+            None | Some("") => true,
+            Some(dir) => Path::new(dir) == self.download_dir,
+        }
+    }
+
+    /// Fill in the task options of a download of `source` from the first matching rule
+    ///
+    /// The folder is only set when `options` leave it to the rules, the speed
+    /// limit only when `options` have none.
+    pub fn apply(&self, source: &Source, options: &mut Map<String, Value>) -> Option<&CategoryRule> {
+        let rule = self.classify(source)?;
+        if self.is_default_dir(options) && !rule.dir.trim().is_empty() {
+            options.insert("dir".to_string(), json!(self.dir(Some(rule)).to_string_lossy()));
+        }
+        if let Some(limit) = &rule.max_download_limit {
+            options
+                .entry("max-download-limit")
+                .or_insert_with(|| json!(limit));
+        }
+        Some(rule)
+    }
+}
+
+/// Category a task was routed to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskCategory {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+}
+
+impl From<&CategoryRule> for TaskCategory {
+    fn from(rule: &CategoryRule) -> Self {
+        Self {
+            name: rule.name.clone(),
+            tag: rule.tag.clone(),
+        }
+    }
+}
+
+/// How a task was routed when added
+#[derive(Debug, Clone)]
+pub struct Routing {
+    source: Source,
+    /// Whether the folder was left to the rules, so they may move the task
+    own_dir: bool,
+    /// Whether the speed limit was left to the rules
+    own_limit: bool,
+    /// Speed limit set by the rules
+    limit: Option<String>,
+    category: Option<TaskCategory>,
+    /// Whether the real file name was matched already
+    settled: bool,
+}
+
+/// Use the category rules of `config` from now on
+pub async fn configure(config: &AppConfig) -> Result<()> {
+    *CATEGORIES.lock().await = Some(Categories::new(config)?);
+    Ok(())
+}
+
+/// Apply the rules to a download about to be added, returning its options
+pub async fn route(source: Source, options: Option<Value>) -> (Option<Value>, Routing) {
+    let mut routing = Routing {
+        source,
+        own_dir: false,
+        own_limit: false,
+        limit: None,
+        category: None,
+        settled: false,
+    };
+    if !matches!(options, None | Some(Value::Object(_))) {
+        return (options, routing);
+    }
+    let guard = CATEGORIES.lock().await;
+    let Some(categories) = guard.as_ref() else {
+        return (options, routing);
+    };
+
+    let mut options = match options {
+        Some(Value::Object(options)) => options,
+        _ => Map::new(),
+    };
+    routing.own_dir = categories.is_default_dir(&options);
+    routing.own_limit = !options.contains_key("max-download-limit");
+    if let Some(rule) = categories.apply(&routing.source, &mut options) {
+        routing.category = Some(TaskCategory::from(rule));
+        routing.limit = rule.max_download_limit.clone().filter(|_| routing.own_limit);
+    }
+    (Some(Value::Object(options)), routing)
+}
+
+/// Remember how a just-added task was routed, to route it again once its name is known
+pub async fn track(gid: String, routing: Routing) {
+    ROUTED.lock().await.insert(gid, routing);
+}
+
+/// Forget how tasks were routed once aria2 no longer lists them
+pub async fn forget(gids: &[String]) {
+    let mut routed = ROUTED.lock().await;
+    for gid in gids {
+        routed.remove(gid);
+    }
+}
+
+/// Category a task added this session was routed to
+pub async fn category_of(gid: &str) -> Option<TaskCategory> {
+    ROUTED.lock().await.get(gid).and_then(|routing| routing.category.clone())
+}
+
+/// Categories of the tasks added this session, by gid
+pub async fn categories() -> HashMap<String, TaskCategory> {
+    ROUTED
+        .lock()
+        .await
+        .iter()
+        .filter_map(|(gid, routing)| Some((gid.clone(), routing.category.clone()?)))
+        .collect()
+}
+
+/// Wait for a force-paused task to stop
+async fn wait_until_paused(client: &Aria2Client, gid: &str) -> Result<()> {
+    for _ in 0..50 {
+        if client.tell_status_keys(gid, &[]).await?.status == TaskStatus::Paused {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    Err(Error::Custom(format!("GID#{} did not pause", gid)))
+}
+
+/// Change the options of a task, pausing it meanwhile if it runs
+///
+/// aria2 only changes the folder of a task that is not active; the task
+/// starts over in the new folder, so only move tasks without data.
+async fn change_stopped(client: &Aria2Client, task: &Task, options: Value) -> Result<()> {
+    let running = task.status == TaskStatus::Active;
+    if running {
+        client.force_pause(&task.gid).await?;
+    }
+    let result = async {
+        if running {
+            wait_until_paused(client, &task.gid).await?;
+        }
+        client.change_option(&task.gid, options).await.map(|_| ())
+    }
+    .await;
+    // Once paused, the task is resumed whatever happened meanwhile
+    if running {
+        let resumed = client.unpause(&task.gid).await.map(|_| ());
+        return result.and(resumed);
+    }
+    result
+}
+
+/// Stop routing a task again
+async fn settle(gid: &str) {
+    if let Some(routing) = ROUTED.lock().await.get_mut(gid) {
+        routing.settled = true;
+    }
+}
+
+/// Match a task against the rules again now that aria2 knows its files
+///
+/// A task whose name is not known yet is left for a later look.
+pub async fn reroute(client: &Aria2Client, gid: &str) -> Result<()> {
+    let task = match client.tell_status(gid).await {
+        Ok(task) => task,
+        // The record is gone, nothing left to route
+        Err(e @ Error::Aria2Rpc { .. }) => {
+            settle(gid).await;
+            return Err(e);
+        }
+        Err(e) => return Err(e),
+    };
+    let resolved = Source::from_task(&task);
+    let queued = matches!(task.status, TaskStatus::Active | TaskStatus::Waiting | TaskStatus::Paused);
+    // Not known yet, e.g. a magnet still fetching its metadata or a server yet to answer
+    if resolved.names.is_empty() {
+        if !queued {
+            settle(gid).await;
+        }
+        return Ok(());
+    }
+
+    let (dir, limit) = {
+        let mut routed = ROUTED.lock().await;
+        // A magnet's torrent carries on as a task of its own
+        if !routed.contains_key(gid) {
+            let Some(metadata) = task.following.as_ref().and_then(|following| routed.get(following)) else {
+                return Ok(());
+            };
+            let routing = Routing {
+                settled: false,
+                ..metadata.clone()
+            };
+            routed.insert(gid.to_string(), routing);
+        }
+        let Some(routing) = routed.get_mut(gid).filter(|routing| !routing.settled) else {
+            return Ok(());
+        };
+        routing.settled = true;
+
+        let guard = CATEGORIES.lock().await;
+        let Some(categories) = guard.as_ref() else {
+            return Ok(());
+        };
+        let mut source = Source {
+            uris: routing.source.uris.clone(),
+            names: resolved.names,
+        };
+        for uri in resolved.uris {
+            if !source.uris.contains(&uri) {
+                source.uris.push(uri);
+            }
+        }
+        let rule = categories.classify(&source);
+        routing.category = rule.map(TaskCategory::from);
+
+        let dir = categories.dir(rule).to_string_lossy().into_owned();
+        let dir = (routing.own_dir && Path::new(&dir) != Path::new(&task.dir)).then_some(dir);
+        // Moving would start over and leave the partial file and its .aria2 file behind
+        let dir = dir.filter(|dir| {
+            let written = task.completed_length > 0;
+            if written {
+                tracing::info!("Keeping {} ({}) out of {}: data was written already", task.name(), gid, dir);
+            }
+            !written
+        });
+        let wanted = rule.and_then(|rule| rule.max_download_limit.clone());
+        let limit = (routing.own_limit && wanted != routing.limit).then(|| wanted.clone().unwrap_or_else(|| "0".to_string()));
+        if limit.is_some() {
+            routing.limit = wanted;
+        }
+        (dir, limit)
+    };
+
+    if !queued {
+        return Ok(());
+    }
+    if let Some(limit) = limit {
+        client.change_option(gid, json!({ "max-download-limit": limit })).await?;
+    }
+    if let Some(dir) = dir {
+        tracing::info!("Moving {} ({}) to {}", task.name(), gid, dir);
+        change_stopped(client, &task, json!({ "dir": dir })).await?;
+    }
+    Ok(())
+}
+
+/// Route again every task whose real name was not matched yet
+async fn reroute_all(client: &Aria2Client) {
+    let gids: Vec<String> = ROUTED
+        .lock()
+        .await
+        .iter()
+        .filter(|(_, routing)| !routing.settled)
+        .map(|(gid, _)| gid.clone())
+        .collect();
+    for gid in gids {
+        if let Err(e) = reroute(client, &gid).await {
+            tracing::debug!("Failed to route {}: {}", gid, e);
+        }
+    }
+}
+
+/// Route the client's tasks again as they start, until the client is dropped
+///
+/// Tasks whose name was not known yet are looked at again on every tick.
+pub fn spawn_router(client: &Arc<Aria2Client>) {
+    let weak: Weak<Aria2Client> = Arc::downgrade(client);
+    let mut events = client.subscribe();
+
+    tauri::async_runtime::spawn(async move {
+        let mut recheck = tokio::time::interval(RECHECK_INTERVAL);
+        recheck.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            let event = tokio::select! {
+                event = events.recv() => Some(event),
+                _ = recheck.tick() => None,
+            };
+            let Some(client) = weak.upgrade() else {
+                break;
+            };
+            let Some(event) = event else {
+                reroute_all(&client).await;
+                continue;
+            };
+            match event {
+                Ok(ClientEvent::Task(event)) => {
+                    if matches!(event.event_type, Aria2EventType::DownloadStart) {
+                        if let Err(e) = reroute(&client, &event.gid).await {
+                            tracing::debug!("Failed to route {}: {}", event.gid, e);
+                        }
+                    }
+                }
+                // Catch up on starts missed while disconnected or lagging
+                Ok(ClientEvent::Connection(ConnectionState::Connected)) | Err(broadcast::error::RecvError::Lagged(_)) => {
+                    reroute_all(&client).await;
+                }
+                Ok(ClientEvent::Connection(ConnectionState::Disconnected)) => {}
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+}
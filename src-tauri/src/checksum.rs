@@ -0,0 +1,547 @@
+//! Checksum verification of finished downloads
+//!
+//! Tasks added with an expected checksum, or with sidecar lookup on, are
+//! verified once aria2 reports them complete. Sidecar lookup fetches the
+//! `.sha256`/`.md5`/`SHA256SUMS` files published next to the download URI.
+//! Files are hashed on a blocking thread; the outcome is emitted as
+//! `task-checksum` and recorded in the download history.
+
+use crate::aria2::{Aria2Client, Aria2EventType, ClientEvent, ConnectionState, Task, TaskStatus};
+use crate::{history, Error, Result};
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock, Weak};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Url};
+use tokio::sync::{broadcast, Mutex};
+
+/// Verifications waiting for their task to complete, loaded by `init`
+static PENDING: Mutex<Option<Pending>> = Mutex::const_new(None);
+
+/// Held while pending verifications are written, so saves land in order
+static SAVING: Mutex<()> = Mutex::const_new(());
+
+/// Pending verifications by gid, backed by a JSON file so they survive restarts
+struct Pending {
+    path: PathBuf,
+    requests: HashMap<String, VerifyRequest>,
+}
+
+/// Largest sidecar file read, `*SUMS` files of big mirrors included
+const MAX_SIDECAR_SIZE: usize = 4 * 1024 * 1024;
+
+/// Hash functions understood in checksums and sidecar files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HashAlgorithm {
+    #[serde(rename = "md5")]
+    Md5,
+    #[serde(rename = "sha-1")]
+    Sha1,
+    #[serde(rename = "sha-256")]
+    Sha256,
+    #[serde(rename = "sha-512")]
+    Sha512,
+}
+
+impl HashAlgorithm {
+    /// Sidecar lookup order, strongest first
+    const ALL: [HashAlgorithm; 4] = [HashAlgorithm::Sha256, HashAlgorithm::Sha512, HashAlgorithm::Sha1, HashAlgorithm::Md5];
+
+    /// Parse aria2's names (`sha-256`), also accepting `sha256` and `SHA256`
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().replace('-', "").as_str() {
+            "md5" => Some(HashAlgorithm::Md5),
+            "sha1" => Some(HashAlgorithm::Sha1),
+            "sha256" => Some(HashAlgorithm::Sha256),
+            "sha512" => Some(HashAlgorithm::Sha512),
+            _ => None,
+        }
+    }
+
+    /// Name as used in aria2's `checksum` option
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Md5 => "md5",
+            HashAlgorithm::Sha1 => "sha-1",
+            HashAlgorithm::Sha256 => "sha-256",
+            HashAlgorithm::Sha512 => "sha-512",
+        }
+    }
+
+    /// Extension of per-file sidecars, e.g. `file.iso.sha256`
+    fn extension(self) -> &'static str {
+        match self {
+            HashAlgorithm::Md5 => "md5",
+            HashAlgorithm::Sha1 => "sha1",
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Sha512 => "sha512",
+        }
+    }
+
+    /// Name of the directory-wide sums file, e.g. `SHA256SUMS`
+    fn sums_file(self) -> &'static str {
+        match self {
+            HashAlgorithm::Md5 => "MD5SUMS",
+            HashAlgorithm::Sha1 => "SHA1SUMS",
+            HashAlgorithm::Sha256 => "SHA256SUMS",
+            HashAlgorithm::Sha512 => "SHA512SUMS",
+        }
+    }
+
+    /// Tag of BSD-style lines, e.g. `SHA256 (file.iso) = ...`
+    fn bsd_tag(self) -> &'static str {
+        match self {
+            HashAlgorithm::Md5 => "MD5",
+            HashAlgorithm::Sha1 => "SHA1",
+            HashAlgorithm::Sha256 => "SHA256",
+            HashAlgorithm::Sha512 => "SHA512",
+        }
+    }
+
+    fn hex_len(self) -> usize {
+        match self {
+            HashAlgorithm::Md5 => 32,
+            HashAlgorithm::Sha1 => 40,
+            HashAlgorithm::Sha256 => 64,
+            HashAlgorithm::Sha512 => 128,
+        }
+    }
+
+    /// Whether `digest` is a hex digest of this algorithm
+    fn is_digest(self, digest: &str) -> bool {
+        digest.len() == self.hex_len() && digest.bytes().all(|b| b.is_ascii_hexdigit())
+    }
+
+    /// Hex digest of the file at `path`
+    pub fn hash_file(self, path: &Path) -> Result<String> {
+        fn hash<D: Digest>(mut file: std::fs::File) -> Result<String> {
+            let mut hasher = D::new();
+            let mut buf = vec![0; 1024 * 1024];
+            loop {
+                let n = file.read(&mut buf)?;
+                if n == 0 {
+                    break;
+                }
+                hasher.update(&buf[..n]);
+            }
+            Ok(hex::encode(hasher.finalize()))
+        }
+
+        let file = std::fs::File::open(path)?;
+        match self {
+            HashAlgorithm::Md5 => hash::<md5::Md5>(file),
+            HashAlgorithm::Sha1 => hash::<sha1::Sha1>(file),
+            HashAlgorithm::Sha256 => hash::<sha2::Sha256>(file),
+            HashAlgorithm::Sha512 => hash::<sha2::Sha512>(file),
+        }
+    }
+}
+
+/// Expected digest of a file, written like aria2's `checksum` option: `sha-256=<hex>`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Checksum {
+    pub algorithm: HashAlgorithm,
+    /// Lowercase hex
+    pub digest: String,
+}
+
+impl std::str::FromStr for Checksum {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::Validation(format!("Invalid checksum (expected TYPE=DIGEST): {}", s));
+        let (name, digest) = s.trim().split_once('=').ok_or_else(invalid)?;
+        let algorithm = HashAlgorithm::parse(name.trim()).ok_or_else(invalid)?;
+        let digest = digest.trim().to_ascii_lowercase();
+        if !algorithm.is_digest(&digest) {
+            return Err(invalid());
+        }
+        Ok(Self { algorithm, digest })
+    }
+}
+
+impl TryFrom<String> for Checksum {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<Checksum> for String {
+    fn from(checksum: Checksum) -> Self {
+        format!("{}={}", checksum.algorithm.name(), checksum.digest)
+    }
+}
+
+/// Verification asked for when adding a task
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct VerifyRequest {
+    /// Checksum of the downloaded file; only single-file downloads take one
+    pub expected_checksum: Option<Checksum>,
+    /// Look for hash files published next to each file's URI
+    pub sidecar: bool,
+}
+
+impl VerifyRequest {
+    pub fn is_empty(&self) -> bool {
+        self.expected_checksum.is_none() && !self.sidecar
+    }
+
+    /// Reject an expected checksum for a download of `uris` that may have several files
+    ///
+    /// Magnet links and other non-HTTP/FTP URIs can turn into multi-file
+    /// torrents, and the checksum would not say which file it is for.
+    pub fn check_uris(&self, uris: &[String]) -> Result<()> {
+        let single_file = |uri: &String| {
+            Url::parse(uri).is_ok_and(|url| matches!(url.scheme(), "http" | "https" | "ftp" | "sftp"))
+        };
+        if self.expected_checksum.is_some() && !uris.iter().all(single_file) {
+            return Err(Error::Validation(
+                "An expected checksum needs a single-file HTTP or FTP download".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of a verification
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumStatus {
+    Verified,
+    Mismatch,
+    /// No expected digest was found, or the file could not be read
+    Unavailable,
+}
+
+/// Verification of one file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileChecksum {
+    pub path: String,
+    pub status: ChecksumStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected: Option<Checksum>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actual: Option<String>,
+    /// Where the expected digest came from, for sidecars
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+/// Payload of the `task-checksum` event
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChecksumReport {
+    pub gid: String,
+    /// `mismatch` if any file mismatched, `verified` if any was verified
+    pub status: ChecksumStatus,
+    pub files: Vec<FileChecksum>,
+}
+
+/// Sidecar URIs that may hold the digest of the file at `uri`, most specific first
+pub fn sidecar_uris(uri: &str) -> Vec<(String, HashAlgorithm)> {
+    let Ok(mut url) = Url::parse(uri) else {
+        return Vec::new();
+    };
+    if !matches!(url.scheme(), "http" | "https") || url.path().ends_with('/') {
+        return Vec::new();
+    }
+    url.set_query(None);
+    url.set_fragment(None);
+
+    let mut uris: Vec<(String, HashAlgorithm)> = HashAlgorithm::ALL
+        .iter()
+        .map(|&algorithm| (format!("{}.{}", url, algorithm.extension()), algorithm))
+        .collect();
+    uris.extend(
+        HashAlgorithm::ALL
+            .iter()
+            .filter_map(|&algorithm| Some((url.join(algorithm.sums_file()).ok()?.to_string(), algorithm))),
+    );
+    uris
+}
+
+/// Find the digest of `file_name` in a sidecar file
+///
+/// Understands GNU coreutils lines (`<hex>  name`, `<hex> *name`), BSD tags
+/// (`SHA256 (name) = <hex>`) and bare digests. A per-file sidecar with a
+/// single digest is taken even when the name in it differs.
+pub fn parse_sidecar(text: &str, file_name: &str, algorithm: HashAlgorithm, per_file: bool) -> Option<String> {
+    let same_name = |name: &str| {
+        let name = name.trim();
+        name == file_name || name.rsplit(['/', '\\']).next() == Some(file_name)
+    };
+    let bsd_prefix = format!("{} (", algorithm.bsd_tag());
+
+    let mut digests = Vec::new();
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        let (digest, name) = if let Some(rest) = line.strip_prefix(&bsd_prefix) {
+            let Some((name, digest)) = rest.rsplit_once(") = ") else {
+                continue;
+            };
+            (digest.trim(), Some(name))
+        } else {
+            match line.split_once(char::is_whitespace) {
+                Some((digest, name)) => (digest, Some(name.trim_start().trim_start_matches('*'))),
+                None => (line, None),
+            }
+        };
+        if !algorithm.is_digest(digest) {
+            continue;
+        }
+        let digest = digest.to_ascii_lowercase();
+        if name.is_some_and(same_name) {
+            return Some(digest);
+        }
+        digests.push(digest);
+    }
+    (per_file && digests.len() == 1).then(|| digests.remove(0))
+}
+
+/// Fetch a sidecar file, if it exists
+async fn fetch_sidecar(http: &reqwest::Client, uri: &str) -> Option<String> {
+    let response = http.get(uri).send().await.ok()?;
+    if !response.status().is_success() || response.content_length().unwrap_or(0) > MAX_SIDECAR_SIZE as u64 {
+        return None;
+    }
+    let text = response.text().await.ok()?;
+    (text.len() <= MAX_SIDECAR_SIZE).then_some(text)
+}
+
+/// Look up the expected digest of a file from the sidecars next to its URIs
+async fn find_sidecar(http: &reqwest::Client, uris: &[String], file_name: &str) -> Option<(Checksum, String)> {
+    for uri in uris {
+        for (sidecar, algorithm) in sidecar_uris(uri) {
+            let per_file = !sidecar.ends_with(algorithm.sums_file());
+            let Some(text) = fetch_sidecar(http, &sidecar).await else {
+                continue;
+            };
+            if let Some(digest) = parse_sidecar(&text, file_name, algorithm, per_file) {
+                return Some((Checksum { algorithm, digest }, sidecar));
+            }
+        }
+    }
+    None
+}
+
+/// Hash one file on a blocking thread and compare it with `expected`
+async fn check_file(path: String, expected: Option<Checksum>, source: Option<String>) -> FileChecksum {
+    let Some(checksum) = expected else {
+        return FileChecksum { path, status: ChecksumStatus::Unavailable, expected: None, actual: None, source };
+    };
+    let (algorithm, file) = (checksum.algorithm, path.clone());
+    let actual = match tokio::task::spawn_blocking(move || algorithm.hash_file(Path::new(&file))).await {
+        Ok(Ok(actual)) => Some(actual),
+        Ok(Err(e)) => {
+            tracing::warn!("Failed to hash {}: {}", path, e);
+            None
+        }
+        Err(e) => {
+            tracing::warn!("Hashing {} panicked: {}", path, e);
+            None
+        }
+    };
+    let status = match &actual {
+        Some(actual) if *actual == checksum.digest => ChecksumStatus::Verified,
+        Some(_) => ChecksumStatus::Mismatch,
+        None => ChecksumStatus::Unavailable,
+    };
+    FileChecksum { path, status, expected: Some(checksum), actual, source }
+}
+
+/// Client fetching sidecar files, shared by every verification
+fn http() -> Option<&'static reqwest::Client> {
+    static HTTP: OnceLock<Option<reqwest::Client>> = OnceLock::new();
+    HTTP.get_or_init(|| reqwest::Client::builder().timeout(Duration::from_secs(15)).build().ok())
+        .as_ref()
+}
+
+/// Verify the finished files of `task` as asked by `request`
+pub async fn verify_task(task: &Task, request: &VerifyRequest) -> ChecksumReport {
+    let files: Vec<_> = task.files.iter().filter(|file| file.selected && !file.path.is_empty()).collect();
+    let http = http();
+
+    // A checksum cannot be matched to one of several files
+    let expected = request.expected_checksum.as_ref().filter(|_| files.len() == 1);
+    if request.expected_checksum.is_some() && expected.is_none() {
+        tracing::warn!("Ignoring the expected checksum of {}: it has {} files", task.gid, files.len());
+    }
+
+    let mut results = Vec::new();
+    for file in &files {
+        let (expected, source) = match (expected, http) {
+            (Some(checksum), _) => (Some(checksum.clone()), None),
+            (_, Some(http)) if request.sidecar => {
+                let uris: Vec<String> = file.uris.iter().map(|uri| uri.uri.clone()).collect();
+                let name = file.path.rsplit(['/', '\\']).next().unwrap_or_default();
+                match find_sidecar(http, &uris, name).await {
+                    Some((checksum, source)) => (Some(checksum), Some(source)),
+                    None => (None, None),
+                }
+            }
+            _ => continue,
+        };
+        results.push(check_file(file.path.clone(), expected, source).await);
+    }
+
+    let status = if results.iter().any(|file| file.status == ChecksumStatus::Mismatch) {
+        ChecksumStatus::Mismatch
+    } else if results.iter().any(|file| file.status == ChecksumStatus::Verified) {
+        ChecksumStatus::Verified
+    } else {
+        ChecksumStatus::Unavailable
+    };
+    ChecksumReport { gid: task.gid.clone(), status, files: results }
+}
+
+/// Load the pending verifications kept at `path`
+pub async fn init(path: PathBuf) -> Result<()> {
+    let requests = match std::fs::read(&path) {
+        Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+            tracing::warn!("Failed to parse pending checksums, dropping them: {}", e);
+            HashMap::new()
+        }),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+        Err(e) => return Err(e.into()),
+    };
+    *PENDING.lock().await = Some(Pending { path, requests });
+    Ok(())
+}
+
+/// Run `f` on the pending verifications, saving them when they changed
+///
+/// The file is written on the blocking pool after the lock is released.
+async fn with_pending<T>(f: impl FnOnce(&mut HashMap<String, VerifyRequest>) -> T) -> Result<T> {
+    let mut guard = PENDING.lock().await;
+    let pending = guard
+        .as_mut()
+        .ok_or_else(|| Error::Custom("Pending checksums not loaded".to_string()))?;
+    let before = pending.requests.clone();
+    let result = f(&mut pending.requests);
+    if pending.requests == before {
+        return Ok(result);
+    }
+    let path = pending.path.clone();
+    let data = serde_json::to_vec(&pending.requests)?;
+    // Queued before the next change can be made, so an older save never lands last
+    let saving = SAVING.lock().await;
+    drop(guard);
+    let saved = tokio::task::spawn_blocking(move || history::write_file(&path, &data)).await;
+    drop(saving);
+    match saved {
+        Ok(saved) => saved.map(|_| result),
+        Err(e) => Err(Error::Custom(format!("Saving pending checksums panicked: {}", e))),
+    }
+}
+
+/// Verify the task `gid` once it completes
+pub async fn watch(gid: String, request: VerifyRequest) -> Result<()> {
+    if request.is_empty() {
+        return Ok(());
+    }
+    with_pending(|pending| pending.insert(gid, request)).await?;
+    Ok(())
+}
+
+/// Verify a completed task, then report and record the outcome
+async fn verify_and_report(app: &AppHandle, task: &Task, request: &VerifyRequest) {
+    let report = verify_task(task, request).await;
+    match report.status {
+        ChecksumStatus::Mismatch => tracing::warn!("Checksum mismatch for {} ({})", task.name(), task.gid),
+        ChecksumStatus::Verified => tracing::info!("Checksum verified for {} ({})", task.name(), task.gid),
+        ChecksumStatus::Unavailable => tracing::info!("No checksum to verify {} ({}) against", task.name(), task.gid),
+    }
+    if report.status != ChecksumStatus::Unavailable {
+        if let Err(e) = history::record_checksum(&task.gid, report.status).await {
+            tracing::warn!("Failed to record checksum in history: {}", e);
+        }
+    }
+    let _ = app.emit("task-checksum", &report);
+}
+
+/// Act on a pending task that may have stopped
+async fn settle(app: &AppHandle, client: &Aria2Client, gid: &str) -> Result<()> {
+    if !with_pending(|pending| pending.contains_key(gid)).await? {
+        return Ok(());
+    }
+    let task = match client.tell_status(gid).await {
+        Ok(task) => task,
+        // The record is gone, nothing left to verify
+        Err(Error::Aria2Rpc { .. }) => {
+            with_pending(|pending| pending.remove(gid)).await?;
+            return Ok(());
+        }
+        Err(e) => return Err(e),
+    };
+    match task.status {
+        TaskStatus::Complete => {
+            // Whoever takes the request verifies, so a task is never hashed twice
+            if let Some(request) = with_pending(|pending| pending.remove(gid)).await? {
+                verify_and_report(app, &task, &request).await;
+            }
+        }
+        TaskStatus::Error | TaskStatus::Removed => {
+            with_pending(|pending| pending.remove(gid)).await?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Verify the client's tasks as they complete, until the client is dropped
+pub fn spawn_verifier(app: AppHandle, client: &Arc<Aria2Client>) {
+    let weak: Weak<Aria2Client> = Arc::downgrade(client);
+    let mut events = client.subscribe();
+
+    tauri::async_runtime::spawn(async move {
+        let settle_all = |client: Arc<Aria2Client>, app: AppHandle| async move {
+            let gids: Vec<String> = with_pending(|pending| pending.keys().cloned().collect())
+                .await
+                .unwrap_or_default();
+            for gid in gids {
+                if let Err(e) = settle(&app, &client, &gid).await {
+                    tracing::debug!("Failed to check pending checksum of {}: {}", gid, e);
+                }
+            }
+        };
+        // Catch up on tasks that completed while the app was not running
+        if let Some(client) = weak.upgrade() {
+            settle_all(client, app.clone()).await;
+        }
+
+        loop {
+            let event = events.recv().await;
+            let Some(client) = weak.upgrade() else {
+                break;
+            };
+            match event {
+                Ok(ClientEvent::Task(event)) => {
+                    if matches!(
+                        event.event_type,
+                        Aria2EventType::DownloadComplete | Aria2EventType::DownloadError | Aria2EventType::DownloadStop
+                    ) {
+                        // Hashing can take a while; keep receiving events meanwhile
+                        let app = app.clone();
+                        tauri::async_runtime::spawn(async move {
+                            if let Err(e) = settle(&app, &client, &event.gid).await {
+                                tracing::debug!("Failed to verify checksum of {}: {}", event.gid, e);
+                            }
+                        });
+                    }
+                }
+                Ok(ClientEvent::Connection(ConnectionState::Connected)) | Err(broadcast::error::RecvError::Lagged(_)) => {
+                    settle_all(client, app.clone()).await;
+                }
+                Ok(ClientEvent::Connection(ConnectionState::Disconnected)) => {}
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+}
@@ -1,46 +1,495 @@
 //! Command line argument parsing utilities
+//!
+//! ```text
+//! motrix [--hidden] [--paused] [--quit-when-done] [--dir DIR] [--out NAME]
+//!        [--header 'Name: value']... [--input-file LIST] [URL | FILE | motrix://LINK]...
+//! ```
+//!
+//! The first instance acts on its own command line; later ones are forwarded
+//! to it by the single-instance plugin together with their working directory.
+//! An argument that cannot be used is reported to the user without dropping
+//! the rest of the command line.
 
+use crate::aria2::{self, GlobalStat};
+use crate::commands;
+use crate::deep_link::{self, DeepLink};
+use crate::{Error, Result};
 use base64::Engine;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{OnceLock, RwLock};
+use tauri::{AppHandle, Emitter};
 
-/// Parse argv for downloadable URLs and torrent file paths
-pub fn parse_args(argv: &[String]) -> Vec<String> {
-    argv.iter()
-        .skip(1) // Skip program name
-        .filter(|arg| is_downloadable_url(arg) || is_torrent_file(arg) || is_metalink_file(arg))
-        .map(|arg| decode_thunder_url(arg))
-        .collect()
-}
-
-/// Decode thunder:// URL to real download URL
-/// thunder:// format: thunder://BASE64(AA<real_url>ZZ)
-fn decode_thunder_url(url: &str) -> String {
-    if !url.to_lowercase().starts_with("thunder://") {
-        return url.to_string();
-    }
-    let encoded = &url[10..]; // Skip "thunder://"
-    if let Ok(decoded) = base64::engine::general_purpose::STANDARD.decode(encoded) {
-        if let Ok(s) = String::from_utf8(decoded) {
-            // thunder wraps URL with "AA" prefix and "ZZ" suffix
-            let trimmed = s.strip_prefix("AA").unwrap_or(&s);
-            let trimmed = trimmed.strip_suffix("ZZ").unwrap_or(trimmed);
-            if !trimmed.is_empty() {
-                return trimmed.to_string();
+/// Whether a `--quit-when-done` watcher is running
+static QUIT_WHEN_DONE: AtomicBool = AtomicBool::new(false);
+
+/// What a launch asks of the app
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LaunchRequest {
+    /// URLs and absolute paths of torrent / metalink files, one download each
+    pub uris: Vec<String>,
+    /// Download directory, absolute
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub out: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub headers: Vec<String>,
+    /// Add the downloads paused
+    pub paused: bool,
+    /// Add the downloads without showing the window
+    pub hidden: bool,
+    /// Quit once nothing is downloading
+    pub quit_when_done: bool,
+    /// `motrix://` links, handled on their own terms
+    #[serde(skip)]
+    pub links: Vec<DeepLink>,
+    /// Why arguments were left out, one message each
+    #[serde(skip)]
+    pub errors: Vec<String>,
+}
+
+impl LaunchRequest {
+    /// aria2 options for the downloads
+    pub fn options(&self) -> Value {
+        let mut options = Map::new();
+        if let Some(dir) = &self.dir {
+            options.insert("dir".to_string(), json!(dir));
+        }
+        if let Some(out) = &self.out {
+            options.insert("out".to_string(), json!(out));
+        }
+        if !self.headers.is_empty() {
+            options.insert("header".to_string(), json!(self.headers));
+        }
+        if self.paused {
+            options.insert("pause".to_string(), json!("true"));
+        }
+        Value::Object(options)
+    }
+}
+
+/// Reject header arguments aria2 would misread
+pub fn check_header(header: &str) -> Result<()> {
+    if !header.contains(':') || header.contains(['\r', '\n']) {
+        return Err(Error::Validation(format!("Invalid header: {}", header)));
+    }
+    Ok(())
+}
+
+/// Parse argv, resolving relative paths against `cwd`, the caller's working directory
+///
+/// Arguments that cannot be used are left out and their errors collected in
+/// `errors`, so one bad argument does not cost the others.
+pub fn parse_args(argv: &[String], cwd: &Path) -> LaunchRequest {
+    let mut request = LaunchRequest::default();
+    let mut args = argv.iter().skip(1); // Skip program name
+    while let Some(arg) = args.next() {
+        if let Err(e) = parse_arg(&mut request, arg, &mut args, cwd) {
+            request.errors.push(e.to_string());
+        }
+    }
+    if request.out.is_some() && request.uris.len() > 1 {
+        request.out = None;
+        request.errors.push("--out needs a single download and was ignored".to_string());
+    }
+    request
+}
+
+/// Apply one argument, taking its value from `args` if it needs one
+fn parse_arg<'a>(
+    request: &mut LaunchRequest,
+    arg: &'a String,
+    args: &mut impl Iterator<Item = &'a String>,
+    cwd: &Path,
+) -> Result<()> {
+    // Both `--dir DIR` and `--dir=DIR`
+    let (flag, inline) = match arg.split_once('=') {
+        Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+        _ => (arg.as_str(), None),
+    };
+    let mut value = || {
+        inline
+            .clone()
+            .or_else(|| args.next().cloned())
+            .ok_or_else(|| Error::Validation(format!("{} needs a value", flag)))
+    };
+    match flag {
+        "--hidden" => request.hidden = true,
+        "--paused" => request.paused = true,
+        "--quit-when-done" => request.quit_when_done = true,
+        "--dir" => request.dir = Some(cwd.join(value()?)),
+        "--out" => request.out = Some(value()?),
+        "--header" => {
+            let header = value()?;
+            check_header(&header)?;
+            request.headers.push(header);
+        }
+        "--input-file" => {
+            let path = cwd.join(value()?);
+            let list = std::fs::read_to_string(&path)
+                .map_err(|e| Error::Validation(format!("Failed to read {}: {}", path.display(), e)))?;
+            for line in list.lines().map(str::trim) {
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                if let Err(e) = push_download(request, line, cwd) {
+                    request.errors.push(e.to_string());
+                }
+            }
+        }
+        _ if flag.starts_with("--") => return Err(Error::Validation(format!("Unknown option: {}", flag))),
+        _ => push_download(request, arg, cwd)?,
+    }
+    Ok(())
+}
+
+/// Add a URL or a torrent / metalink file to the request
+fn push_download(request: &mut LaunchRequest, arg: &str, cwd: &Path) -> Result<()> {
+    if deep_link::is_deep_link(arg) {
+        request.links.push(deep_link::parse(arg)?);
+        return Ok(());
+    }
+    if is_link(arg) {
+        request.uris.push(decode_link(arg)?);
+        return Ok(());
+    }
+    let path = cwd.join(arg).to_string_lossy().into_owned();
+    if is_torrent_file(&path) || is_metalink_file(&path) {
+        request.uris.push(path);
+        return Ok(());
+    }
+    Err(Error::Validation(format!(
+        "Not a URL, torrent or metalink file: {}",
+        arg
+    )))
+}
+
+/// Add the downloads of a request, returning their GIDs
+///
+/// Used when no window is shown to confirm them.
+pub async fn add(request: &LaunchRequest) -> Result<Vec<String>> {
+    let options = request.options();
+    let mut gids = Vec::new();
+    for uri in &request.uris {
+        if is_torrent_file(uri) {
+            gids.push(commands::add_torrent_file(uri.clone(), Some(options.clone()), None).await?);
+        } else if is_metalink_file(uri) {
+            let added = commands::add_metalink_file(uri.clone(), Some(options.clone())).await?;
+            let added: Vec<String> = serde_json::from_value(added)?;
+            gids.extend(added);
+        } else {
+            gids.push(commands::add_uri(vec![uri.clone()], Some(options.clone()), None, None).await?);
+        }
+    }
+    Ok(gids)
+}
+
+/// Act on a launch request in the primary instance
+///
+/// Hidden requests are added straight away; others open the add dialog.
+/// Arguments that were left out are reported in a `launch-errors` event.
+pub fn handle(app: &AppHandle, mut request: LaunchRequest) {
+    if !request.errors.is_empty() {
+        for error in &request.errors {
+            tracing::warn!("Ignoring command line argument: {}", error);
+        }
+        let _ = app.emit("launch-errors", std::mem::take(&mut request.errors));
+    }
+    for link in request.links.drain(..) {
+        deep_link::handle(app, link);
+    }
+    if request.quit_when_done {
+        quit_when_done(app.clone());
+    }
+    if request.uris.is_empty() {
+        return;
+    }
+    if request.hidden {
+        tauri::async_runtime::spawn(async move {
+            match add(&request).await {
+                Ok(gids) => tracing::info!("Added {} download(s) from the command line", gids.len()),
+                Err(e) => tracing::error!("Failed to add downloads from the command line: {}", e),
+            }
+        });
+    } else {
+        let _ = app.emit("open-urls", &request);
+    }
+}
+
+/// Whether everything started since `baseline` stopped downloads finished
+///
+/// `seen` tells whether a download was seen active or waiting; `baseline` is
+/// the `numStoppedTotal` when watching began, which catches downloads that
+/// finished between two checks.
+pub fn is_done(stat: &GlobalStat, baseline: u64, seen: bool) -> bool {
+    stat.num_active == 0 && stat.num_waiting == 0 && (seen || stat.num_stopped_total > baseline)
+}
+
+/// Quit the app once the downloads are done
+fn quit_when_done(app: AppHandle) {
+    if QUIT_WHEN_DONE.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    tauri::async_runtime::spawn(async move {
+        let mut baseline = None;
+        let mut seen = false;
+        loop {
+            tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+            let Ok(client) = aria2::get_client().await else {
+                continue;
+            };
+            let Ok(stat) = client.get_global_stat().await else {
+                continue;
+            };
+            let baseline = *baseline.get_or_insert(stat.num_stopped_total);
+            seen |= stat.num_active + stat.num_waiting > 0;
+            if is_done(&stat, baseline, seen) {
+                tracing::info!("Downloads done, quitting (--quit-when-done)");
+                aria2::shutdown_and_cleanup().await;
+                app.exit(0);
+                return;
+            }
+        }
+    });
+}
+
+/// Turns the links of one scheme into a URL aria2 downloads
+pub trait LinkDecoder: Send + Sync {
+    /// Lowercase scheme, e.g. `thunder`
+    fn scheme(&self) -> &str;
+    /// The URL to download, or why the link cannot be downloaded
+    fn decode(&self, link: &str) -> Result<String>;
+}
+
+/// A scheme aria2 downloads as is
+struct Direct(&'static str);
+
+impl LinkDecoder for Direct {
+    fn scheme(&self) -> &str {
+        self.0
+    }
+
+    fn decode(&self, link: &str) -> Result<String> {
+        Ok(normalize_percent_encoding(link))
+    }
+}
+
+/// A download manager link: a base64 URL between two markers
+struct Wrapped {
+    scheme: &'static str,
+    prefix: &'static str,
+    suffix: &'static str,
+}
+
+impl LinkDecoder for Wrapped {
+    fn scheme(&self) -> &str {
+        self.scheme
+    }
+
+    fn decode(&self, link: &str) -> Result<String> {
+        // flashget://<base64>&<referrer id>
+        let payload = link[self.scheme.len() + 3..].split('&').next().unwrap_or_default();
+        let payload = percent_decode(payload);
+        let invalid = || Error::Validation(format!("Invalid {}:// link: {}", self.scheme, link));
+        let engine = base64::engine::GeneralPurpose::new(
+            &base64::alphabet::STANDARD,
+            base64::engine::GeneralPurposeConfig::new()
+                .with_decode_padding_mode(base64::engine::DecodePaddingMode::Indifferent),
+        );
+        let decode = |payload: &[u8]| String::from_utf8(engine.decode(payload).ok()?).ok();
+        // Browsers may end the link with a slash; `/` is base64 too, so it is
+        // only dropped when the payload does not decode with it
+        let decoded = decode(&payload)
+            .or_else(|| decode(payload.strip_suffix(b"/")?))
+            .ok_or_else(invalid)?;
+        let url = decoded.trim();
+        let url = url.strip_prefix(self.prefix).unwrap_or(url);
+        let url = url.strip_suffix(self.suffix).unwrap_or(url).trim();
+        if url.is_empty() {
+            return Err(invalid());
+        }
+        Ok(url.to_string())
+    }
+}
+
+/// A scheme users paste that aria2 cannot download
+struct Unsupported {
+    scheme: &'static str,
+    reason: &'static str,
+}
+
+impl LinkDecoder for Unsupported {
+    fn scheme(&self) -> &str {
+        self.scheme
+    }
+
+    fn decode(&self, _link: &str) -> Result<String> {
+        Err(Error::Unsupported(format!("{}:// links {}", self.scheme, self.reason)))
+    }
+}
+
+/// Link decoders by scheme
+pub struct LinkDecoders {
+    decoders: Vec<Box<dyn LinkDecoder>>,
+}
+
+impl Default for LinkDecoders {
+    fn default() -> Self {
+        let mut decoders = Self { decoders: Vec::new() };
+        for scheme in ["http", "https", "ftp", "sftp", "magnet"] {
+            decoders.register(Direct(scheme));
+        }
+        // thunder://BASE64(AA<url>ZZ)
+        decoders.register(Wrapped {
+            scheme: "thunder",
+            prefix: "AA",
+            suffix: "ZZ",
+        });
+        // flashget://BASE64([FLASHGET]<url>[FLASHGET])
+        decoders.register(Wrapped {
+            scheme: "flashget",
+            prefix: "[FLASHGET]",
+            suffix: "[FLASHGET]",
+        });
+        // qqdl://BASE64(<url>)
+        decoders.register(Wrapped {
+            scheme: "qqdl",
+            prefix: "",
+            suffix: "",
+        });
+        decoders.register(Unsupported {
+            scheme: "ed2k",
+            reason: "need an eD2k client such as eMule",
+        });
+        decoders
+    }
+}
+
+impl LinkDecoders {
+    /// Add a decoder, replacing the one for the same scheme
+    pub fn register(&mut self, decoder: impl LinkDecoder + 'static) {
+        self.decoders.retain(|known| known.scheme() != decoder.scheme());
+        self.decoders.push(Box::new(decoder));
+    }
+
+    /// The decoder for the scheme of `link`
+    pub fn decoder(&self, link: &str) -> Option<&dyn LinkDecoder> {
+        let scheme = scheme(link)?;
+        self.decoders
+            .iter()
+            .find(|decoder| decoder.scheme() == scheme)
+            .map(|decoder| decoder.as_ref())
+    }
+
+    /// Decode `link`, unwrapping links that wrap other links
+    pub fn decode(&self, link: &str) -> Result<String> {
+        let mut link = link.trim().to_string();
+        // thunder:// wrapping flashget:// and so on, but not forever
+        for _ in 0..4 {
+            let decoder = self
+                .decoder(&link)
+                .ok_or_else(|| Error::Validation(format!("Not a downloadable URL: {}", link)))?;
+            let decoded = decoder.decode(&link)?;
+            if decoded == link {
+                return Ok(decoded);
             }
+            link = decoded;
         }
+        Err(Error::Validation(format!("Too many nested links: {}", link)))
     }
-    // Return original if decode fails
-    url.to_string()
+}
+
+/// The scheme of a link, lowercase; `magnet:` links have no `//`
+fn scheme(link: &str) -> Option<String> {
+    let (scheme, rest) = link.split_once(':')?;
+    let valid = scheme.len() > 1 // not a Windows drive letter
+        && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    let scheme = scheme.to_ascii_lowercase();
+    (valid && (rest.starts_with("//") || scheme == "magnet")).then_some(scheme)
+}
+
+fn decoders() -> &'static RwLock<LinkDecoders> {
+    static DECODERS: OnceLock<RwLock<LinkDecoders>> = OnceLock::new();
+    DECODERS.get_or_init(|| RwLock::new(LinkDecoders::default()))
+}
+
+/// Add a decoder to the registry `decode_link` uses
+pub fn register_decoder(decoder: impl LinkDecoder + 'static) {
+    decoders().write().unwrap_or_else(|e| e.into_inner()).register(decoder);
+}
+
+/// Decode a link into the URL aria2 downloads
+pub fn decode_link(link: &str) -> Result<String> {
+    decoders().read().unwrap_or_else(|e| e.into_inner()).decode(link)
+}
+
+/// Whether `s` has a registered scheme, downloadable or not
+pub fn is_link(s: &str) -> bool {
+    let decoders = decoders().read().unwrap_or_else(|e| e.into_inner());
+    decoders.decoder(s.trim()).is_some()
 }
 
 /// Check if a string is a downloadable URL
 pub fn is_downloadable_url(s: &str) -> bool {
-    let lower = s.to_lowercase();
-    lower.starts_with("http://")
-        || lower.starts_with("https://")
-        || lower.starts_with("ftp://")
-        || lower.starts_with("magnet:")
-        || lower.starts_with("thunder://")
-        || lower.starts_with("motrix://")
+    decode_link(s).is_ok()
+}
+
+/// Percent-encode what may not appear in a URL as is, such as spaces and
+/// non-ASCII characters, keeping valid escapes with upper-case digits
+pub fn normalize_percent_encoding(url: &str) -> String {
+    let bytes = url.as_bytes();
+    let mut normalized = String::with_capacity(url.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let byte = bytes[i];
+        if escape_at(bytes, i).is_some() {
+            normalized.push('%');
+            normalized.push(bytes[i + 1].to_ascii_uppercase() as char);
+            normalized.push(bytes[i + 2].to_ascii_uppercase() as char);
+            i += 3;
+            continue;
+        }
+        if byte.is_ascii_graphic() && byte != b'%' {
+            normalized.push(byte as char);
+        } else {
+            let _ = write!(normalized, "%{:02X}", byte);
+        }
+        i += 1;
+    }
+    normalized
+}
+
+/// The byte a valid `%XX` escape at `i` stands for
+fn escape_at(bytes: &[u8], i: usize) -> Option<u8> {
+    match bytes.get(i..i + 3)? {
+        [b'%', high, low] if high.is_ascii_hexdigit() && low.is_ascii_hexdigit() => {
+            u8::from_str_radix(std::str::from_utf8(&bytes[i + 1..i + 3]).ok()?, 16).ok()
+        }
+        _ => None,
+    }
+}
+
+/// Undo percent-encoding, e.g. of base64 padding in a pasted link
+fn percent_decode(s: &str) -> Vec<u8> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if let Some(byte) = escape_at(bytes, i) {
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    decoded
 }
 
 /// Check if a string is a path to an existing .torrent file
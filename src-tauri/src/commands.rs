@@ -1,68 +1,134 @@
 //! Tauri commands for frontend communication
 
-use crate::aria2;
+use crate::aria2::{
+    self, FileServers, GidResult, GlobalStat, Options, Peer, SessionInfo, Task, TaskDelta, TaskFile,
+    TaskPage, TaskQuery, TaskUri, UriChange,
+};
+use crate::browser;
+use crate::category::{self, Source, TaskCategory};
+use crate::checksum::{self, VerifyRequest};
+use crate::cli;
 use crate::config::AppConfig;
+use crate::duplicate::{self, Candidate, DuplicateAction};
 use crate::error::Error;
+use crate::handoff;
+use crate::history::{self, ExportFormat, HistoryPage, HistoryQuery};
+use crate::schedule::{self, SpeedState, WindowState};
 use crate::tray::TrayLabels;
 use crate::Result;
-use serde_json::Value;
+use serde_json::{json, Value};
+use std::collections::HashMap;
 use tauri_plugin_store::StoreExt;
 
 /// Get application configuration
 #[tauri::command]
 pub async fn get_app_config(app: tauri::AppHandle) -> Result<AppConfig> {
-    let store = app.store("config.json")?;
-
-    // Try to load from store, or use defaults (persist to ensure rpc_secret consistency)
-    let config: AppConfig = if let Some(data) = store.get("config") {
-        serde_json::from_value(data.clone()).unwrap_or_else(|_| {
-            let default_config = AppConfig::default();
-            store.set("config", serde_json::to_value(&default_config).unwrap());
-            let _ = store.save();
-            default_config
-        })
-    } else {
-        let default_config = AppConfig::default();
-        store.set("config", serde_json::to_value(&default_config).unwrap());
-        let _ = store.save();
-        default_config
-    };
-
-    Ok(config)
+    // Persists defaults on first launch so rpc_secret and the browser token stay consistent
+    aria2::load_config(&app)
 }
 
 /// Save application configuration
 #[tauri::command]
 pub async fn save_app_config(app: tauri::AppHandle, config: AppConfig) -> Result<()> {
+    // Rejects invalid category rules, or a browser endpoint port in use, before anything is saved
+    category::configure(&config).await?;
+    browser::configure(&config.browser_integration).await?;
     let store = app.store("config.json")?;
     store.set("config", serde_json::to_value(&config)?);
     store.save()?;
+    // Apply a changed speed schedule right away
+    schedule::reschedule();
     Ok(())
 }
 
-/// Add URI download task
+/// The frontend listens for `open-urls`; deliver the launch requests held for it
 #[tauri::command]
-pub async fn add_uri(uris: Vec<String>, options: Option<Value>) -> Result<String> {
+pub async fn frontend_ready(app: tauri::AppHandle) -> Result<()> {
+    handoff::frontend_ready(&app);
+    Ok(())
+}
+
+/// Add URI download task, optionally verifying its checksum once complete
+///
+/// Links go through the `cli` decoder registry, so `ed2k://` fails as
+/// `unsupported` instead of reaching aria2.
+///
+/// A download duplicating an existing task or file fails with a `duplicate`
+/// error unless `on_duplicate` says what to do about it.
+#[tauri::command]
+pub async fn add_uri(
+    uris: Vec<String>,
+    options: Option<Value>,
+    verify: Option<VerifyRequest>,
+    on_duplicate: Option<DuplicateAction>,
+) -> Result<String> {
+    // thunder:// and similar links become the URLs they wrap
+    let uris = uris.iter().map(|uri| cli::decode_link(uri)).collect::<Result<Vec<_>>>()?;
+    if let Some(verify) = &verify {
+        verify.check_uris(&uris)?;
+    }
     let client = aria2::get_client().await?;
-    client.add_uri(uris, options).await
+    let out = options.as_ref().and_then(|o| o.get("out")).and_then(Value::as_str).map(String::from);
+    let (mut options, routing) = category::route(Source::from_uris(&uris, out.as_deref()), options).await;
+    let candidate = Candidate::from_uris(&uris, options.as_ref());
+    if let Some(gid) = duplicate::resolve(&client, &candidate, on_duplicate.unwrap_or_default(), &mut options).await? {
+        return Ok(gid);
+    }
+    // aria2 checks the file as it downloads; the verifier reports the outcome
+    if let Some(checksum) = verify.as_ref().and_then(|verify| verify.expected_checksum.clone()) {
+        if let Some(options) = options.get_or_insert_with(|| json!({})).as_object_mut() {
+            options.entry("checksum").or_insert_with(|| json!(String::from(checksum)));
+        }
+    }
+    let gid = client.add_uri(uris, options).await?;
+    category::track(gid.clone(), routing).await;
+    if let Some(verify) = verify {
+        // The task is added either way, so its gid is still the answer
+        if let Err(e) = checksum::watch(gid.clone(), verify).await {
+            tracing::warn!("Failed to watch {} for checksum verification: {}", gid, e);
+        }
+    }
+    Ok(gid)
 }
 
-/// Add torrent download task
+/// Add torrent download task; duplicates are handled as in `add_uri`
 #[tauri::command]
-pub async fn add_torrent(torrent: String, options: Option<Value>) -> Result<String> {
-    let client = aria2::get_client().await?;
-    client.add_torrent(&torrent, options).await
+pub async fn add_torrent(torrent: String, options: Option<Value>, on_duplicate: Option<DuplicateAction>) -> Result<String> {
+    use base64::Engine;
+    let data = base64::engine::general_purpose::STANDARD.decode(&torrent).unwrap_or_default();
+    add_torrent_data(&data, &torrent, options, on_duplicate).await
 }
 
 /// Add torrent download task from file path (reads and base64-encodes on backend)
 #[tauri::command]
-pub async fn add_torrent_file(file_path: String, options: Option<Value>) -> Result<String> {
+pub async fn add_torrent_file(
+    file_path: String,
+    options: Option<Value>,
+    on_duplicate: Option<DuplicateAction>,
+) -> Result<String> {
     use base64::Engine;
     let data = std::fs::read(&file_path)
         .map_err(|e| Error::Custom(format!("Failed to read torrent file: {}", e)))?;
     let b64 = base64::engine::general_purpose::STANDARD.encode(&data);
+    add_torrent_data(&data, &b64, options, on_duplicate).await
+}
+
+/// Add a torrent given both raw and base64-encoded
+async fn add_torrent_data(
+    data: &[u8],
+    torrent: &str,
+    options: Option<Value>,
+    on_duplicate: Option<DuplicateAction>,
+) -> Result<String> {
     let client = aria2::get_client().await?;
-    client.add_torrent(&b64, options).await
+    let (mut options, routing) = category::route(Source::from_torrent(data), options).await;
+    let candidate = Candidate::from_torrent(data, options.as_ref());
+    if let Some(gid) = duplicate::resolve(&client, &candidate, on_duplicate.unwrap_or_default(), &mut options).await? {
+        return Ok(gid);
+    }
+    let gid = client.add_torrent(torrent, options).await?;
+    category::track(gid.clone(), routing).await;
+    Ok(gid)
 }
 
 /// Add metalink download task from file path
@@ -72,15 +138,25 @@ pub async fn add_metalink_file(file_path: String, options: Option<Value>) -> Res
     let data = std::fs::read(&file_path)
         .map_err(|e| Error::Custom(format!("Failed to read metalink file: {}", e)))?;
     let b64 = base64::engine::general_purpose::STANDARD.encode(&data);
-    let client = aria2::get_client().await?;
-    client.add_metalink(&b64, options).await
+    add_metalink(&b64, options).await
 }
 
 /// Add metalink download task from base64 data (for drag-drop)
 #[tauri::command]
 pub async fn add_metalink_file_base64(metalink: String, options: Option<Value>) -> Result<Value> {
+    add_metalink(&metalink, options).await
+}
+
+/// Add a metalink, routing its tasks once their names are known
+async fn add_metalink(metalink: &str, options: Option<Value>) -> Result<Value> {
     let client = aria2::get_client().await?;
-    client.add_metalink(&metalink, options).await
+    // File names are only known once aria2 has read the metalink
+    let (options, routing) = category::route(Source::default(), options).await;
+    let gids = client.add_metalink(metalink, options).await?;
+    for gid in gids.as_array().into_iter().flatten().filter_map(Value::as_str) {
+        category::track(gid.to_string(), routing.clone()).await;
+    }
+    Ok(gids)
 }
 
 /// Pause a task
@@ -104,42 +180,117 @@ pub async fn remove_task(gid: String) -> Result<String> {
     client.remove(&gid).await
 }
 
+/// Pause many tasks in one batch
+#[tauri::command]
+pub async fn pause_tasks(gids: Vec<String>, force: Option<bool>) -> Result<Vec<GidResult>> {
+    let client = aria2::get_client().await?;
+    client.pause_many(&gids, force.unwrap_or(false)).await
+}
+
+/// Resume many tasks in one batch
+#[tauri::command]
+pub async fn resume_tasks(gids: Vec<String>) -> Result<Vec<GidResult>> {
+    let client = aria2::get_client().await?;
+    client.unpause_many(&gids).await
+}
+
+/// Remove many tasks in one batch
+#[tauri::command]
+pub async fn remove_tasks(gids: Vec<String>, force: Option<bool>) -> Result<Vec<GidResult>> {
+    let client = aria2::get_client().await?;
+    client.remove_many(&gids, force.unwrap_or(false)).await
+}
+
 /// Get task list by type
 #[tauri::command]
-pub async fn get_task_list(task_type: String) -> Result<Value> {
+pub async fn get_task_list(task_type: String) -> Result<Vec<Task>> {
     let client = aria2::get_client().await?;
 
     match task_type.as_str() {
-        "active" => {
-            let active = client.tell_active().await?;
-            let waiting = client.tell_waiting(0, 1000).await?;
-
-            // Merge active and waiting
-            let mut tasks = Vec::new();
-            if let Some(arr) = active.as_array() {
-                tasks.extend(arr.clone());
-            }
-            if let Some(arr) = waiting.as_array() {
-                tasks.extend(arr.clone());
-            }
-            Ok(serde_json::to_value(tasks)?)
-        }
+        // Merge active and waiting
+        "active" => client.tell_active_and_waiting(1000).await,
         "waiting" => client.tell_waiting(0, 1000).await,
         "stopped" => client.tell_stopped(0, 10000).await,
         _ => client.tell_active().await,
     }
 }
 
-/// Get single task info
+/// Get one page of tasks, filtered and sorted by the backend
+#[tauri::command]
+pub async fn query_tasks(query: TaskQuery) -> Result<TaskPage> {
+    let client = aria2::get_client().await?;
+    client.query_tasks(&query).await
+}
+
+/// Get every cached task, to seed the table kept current by `task-delta`
+#[tauri::command]
+pub async fn get_task_snapshot() -> Result<TaskDelta> {
+    aria2::task_snapshot()
+        .await
+        .ok_or_else(|| Error::Disconnected("Aria2 client not initialized".to_string()))
+}
+
+/// Ask the task poller to tick now, e.g. right after a user action
+#[tauri::command]
+pub async fn refresh_tasks() -> Result<()> {
+    aria2::request_refresh();
+    Ok(())
+}
+
+/// Get single task info, optionally limited to some keys
+#[tauri::command]
+pub async fn get_task_info(gid: String, keys: Option<Vec<String>>) -> Result<Task> {
+    let client = aria2::get_client().await?;
+    match keys {
+        Some(keys) => client.tell_status_keys(&gid, &keys).await,
+        None => client.tell_status(&gid).await,
+    }
+}
+
+/// Get the URIs of a task
+#[tauri::command]
+pub async fn get_task_uris(gid: String) -> Result<Vec<TaskUri>> {
+    let client = aria2::get_client().await?;
+    client.get_uris(&gid).await
+}
+
+/// Get the files of a task with per-file progress
+#[tauri::command]
+pub async fn get_task_files(gid: String) -> Result<Vec<TaskFile>> {
+    let client = aria2::get_client().await?;
+    client.get_files(&gid).await
+}
+
+/// Get the mirrors a task is downloading from, with their speeds
+#[tauri::command]
+pub async fn get_task_servers(gid: String) -> Result<Vec<FileServers>> {
+    let client = aria2::get_client().await?;
+    client.get_servers(&gid).await
+}
+
+/// Edit the mirrors of a file in a running task
 #[tauri::command]
-pub async fn get_task_info(gid: String) -> Result<Value> {
+pub async fn change_task_uri(
+    gid: String,
+    file_index: u32,
+    del_uris: Vec<String>,
+    add_uris: Vec<String>,
+    position: Option<u32>,
+) -> Result<UriChange> {
     let client = aria2::get_client().await?;
-    client.tell_status(&gid).await
+    client.change_uri(&gid, file_index, del_uris, add_uris, position).await
+}
+
+/// Get the effective options of a task
+#[tauri::command]
+pub async fn get_task_option(gid: String) -> Result<Options> {
+    let client = aria2::get_client().await?;
+    client.get_option(&gid).await
 }
 
 /// Get global statistics
 #[tauri::command]
-pub async fn get_global_stat() -> Result<Value> {
+pub async fn get_global_stat() -> Result<GlobalStat> {
     let client = aria2::get_client().await?;
     client.get_global_stat().await
 }
@@ -151,10 +302,18 @@ pub async fn change_global_option(options: Value) -> Result<Value> {
     client.change_global_option(options).await
 }
 
+/// Get the engine's global options
+#[tauri::command]
+pub async fn get_global_option() -> Result<Options> {
+    let client = aria2::get_client().await?;
+    client.get_global_option().await
+}
+
 /// Shutdown aria2 engine
 #[tauri::command]
 pub async fn shutdown_engine() -> Result<Value> {
     let client = aria2::get_client().await?;
+    aria2::expect_exit();
     client.shutdown().await
 }
 
@@ -179,6 +338,13 @@ pub async fn remove_task_record(gid: String) -> Result<Value> {
     client.remove_download_result(&gid).await
 }
 
+/// Remove many download result records in one batch
+#[tauri::command]
+pub async fn remove_task_records(gids: Vec<String>) -> Result<Vec<GidResult>> {
+    let client = aria2::get_client().await?;
+    client.remove_download_results(&gids).await
+}
+
 /// Purge all completed/error/removed download results
 #[tauri::command]
 pub async fn purge_task_records() -> Result<Value> {
@@ -253,9 +419,30 @@ pub async fn get_engine_version() -> Result<Value> {
     client.get_version().await
 }
 
+/// Get the current aria2 session id
+#[tauri::command]
+pub async fn get_session_info() -> Result<SessionInfo> {
+    let client = aria2::get_client().await?;
+    client.get_session_info().await
+}
+
+/// List the RPC methods supported by the engine
+#[tauri::command]
+pub async fn list_engine_methods() -> Result<Vec<String>> {
+    let client = aria2::get_client().await?;
+    client.list_methods().await
+}
+
+/// List the notifications the engine can send
+#[tauri::command]
+pub async fn list_engine_notifications() -> Result<Vec<String>> {
+    let client = aria2::get_client().await?;
+    client.list_notifications().await
+}
+
 /// Get peers for a BT task
 #[tauri::command]
-pub async fn get_task_peers(gid: String) -> Result<Value> {
+pub async fn get_task_peers(gid: String) -> Result<Vec<Peer>> {
     let client = aria2::get_client().await?;
     client.get_peers(&gid).await
 }
@@ -477,3 +664,61 @@ pub async fn change_task_position(gid: String, pos: i32, how: String) -> Result<
     let client = aria2::get_client().await?;
     client.change_position(&gid, pos, &how).await
 }
+
+/// Search the download history, newest first
+#[tauri::command]
+pub async fn search_history(query: HistoryQuery) -> Result<HistoryPage> {
+    history::search(&query).await
+}
+
+/// Export the whole download history as JSON (default) or CSV
+#[tauri::command]
+pub async fn export_history(path: String, format: Option<ExportFormat>) -> Result<()> {
+    history::export(std::path::Path::new(&path), format.unwrap_or_default()).await
+}
+
+/// Download a history entry again with its recorded options, returning the new gid
+#[tauri::command]
+pub async fn readd_from_history(gid: String) -> Result<String> {
+    let entry = history::entry(&gid).await?;
+    let client = aria2::get_client().await?;
+    history::readd(&client, &entry).await
+}
+
+/// Speed profile and overall limits in effect
+#[tauri::command]
+pub async fn get_speed_profile(app: tauri::AppHandle) -> Result<SpeedState> {
+    schedule::state(&app).await
+}
+
+/// Use a speed profile regardless of the schedule, for `minutes` or until the
+/// scheduled profile changes
+#[tauri::command]
+pub async fn override_speed_profile(app: tauri::AppHandle, profile: String, minutes: Option<u64>) -> Result<SpeedState> {
+    schedule::set_override(&app, profile, minutes).await
+}
+
+/// Drop a speed profile override and return to the schedule
+#[tauri::command]
+pub async fn clear_speed_override() -> Result<()> {
+    schedule::clear_override().await;
+    Ok(())
+}
+
+/// Tasks held by the download windows or flagged "ignore schedule"
+#[tauri::command]
+pub async fn get_download_window_state(app: tauri::AppHandle) -> Result<WindowState> {
+    schedule::window_state(&app).await
+}
+
+/// Let a task run outside the download windows, or put it back under them
+#[tauri::command]
+pub async fn set_task_ignore_schedule(app: tauri::AppHandle, gid: String, ignore: bool) -> Result<WindowState> {
+    schedule::set_ignore_schedule(&app, gid, ignore).await
+}
+
+/// Categories the tasks added this session were routed to, by gid
+#[tauri::command]
+pub async fn get_task_categories() -> Result<HashMap<String, TaskCategory>> {
+    Ok(category::categories().await)
+}
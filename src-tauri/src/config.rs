@@ -48,11 +48,26 @@ pub struct AppConfig {
     pub rpc_secret: String,
     pub max_overall_download_limit: String,
     pub max_overall_upload_limit: String,
+    /// Weekly speed profiles that override the overall limits above
+    pub speed_schedule: SpeedSchedule,
+    /// Hours the download queue may run in
+    pub download_windows: DownloadWindows,
+    /// Rules routing downloads to category folders
+    pub categories: Vec<CategoryRule>,
+    /// Local endpoint browser extensions send captured downloads to
+    pub browser_integration: BrowserIntegration,
     pub allow_overwrite: bool,
     pub auto_file_renaming: bool,
     pub continue_download: bool,
     pub follow_metalink: String,
 
+    // Engine settings
+    pub engine_mode: EngineMode,
+    pub external_rpc_scheme: RpcScheme,
+    pub external_rpc_host: String,
+    pub external_rpc_port: u16,
+    pub external_rpc_secret: String,
+
     // Proxy settings
     pub proxy_enabled: bool,
     pub proxy_type: ProxyType,
@@ -84,6 +99,209 @@ pub enum ProxyType {
     Socks5,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum EngineMode {
+    /// Spawn and manage the bundled aria2c sidecar
+    #[default]
+    Builtin,
+    /// Connect to an aria2 daemon that is managed elsewhere
+    External,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RpcScheme {
+    #[default]
+    Ws,
+    Wss,
+    Http,
+    Https,
+}
+
+impl RpcScheme {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RpcScheme::Ws => "ws",
+            RpcScheme::Wss => "wss",
+            RpcScheme::Http => "http",
+            RpcScheme::Https => "https",
+        }
+    }
+}
+
+/// Day of the week in local time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Weekday {
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+    Sun,
+}
+
+impl Weekday {
+    /// The day before
+    pub fn pred(self) -> Self {
+        match self {
+            Weekday::Mon => Weekday::Sun,
+            Weekday::Tue => Weekday::Mon,
+            Weekday::Wed => Weekday::Tue,
+            Weekday::Thu => Weekday::Wed,
+            Weekday::Fri => Weekday::Thu,
+            Weekday::Sat => Weekday::Fri,
+            Weekday::Sun => Weekday::Sat,
+        }
+    }
+}
+
+/// A recurring stretch of local time, e.g. weekdays 07:00–22:00
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeWindow {
+    /// Days the window starts on; empty means every day
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    /// Start time as `HH:MM`
+    pub start: String,
+    /// End time as `HH:MM`; an end before the start runs past midnight, an
+    /// end equal to the start covers the whole day
+    pub end: String,
+}
+
+impl TimeWindow {
+    /// Whether the window covers `minute` (minutes since midnight) on `day`
+    pub fn contains(&self, day: Weekday, minute: u32) -> bool {
+        let (Some(start), Some(end)) = (parse_time(&self.start), parse_time(&self.end)) else {
+            return false;
+        };
+        let on = |day: Weekday| self.days.is_empty() || self.days.contains(&day);
+        match start.cmp(&end) {
+            std::cmp::Ordering::Less => on(day) && (start..end).contains(&minute),
+            std::cmp::Ordering::Greater => (on(day) && minute >= start) || (on(day.pred()) && minute < end),
+            std::cmp::Ordering::Equal => on(day),
+        }
+    }
+}
+
+/// Parse `HH:MM` into minutes since midnight
+pub fn parse_time(time: &str) -> Option<u32> {
+    let (hours, minutes) = time.trim().split_once(':')?;
+    let (hours, minutes): (u32, u32) = (hours.parse().ok()?, minutes.parse().ok()?);
+    (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+}
+
+/// Named pair of overall speed limits, in aria2 size syntax ("0" is unlimited)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpeedProfile {
+    pub name: String,
+    pub download_limit: String,
+    pub upload_limit: String,
+}
+
+/// Use `profile` during `window`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpeedRule {
+    #[serde(flatten)]
+    pub window: TimeWindow,
+    pub profile: String,
+}
+
+/// Weekly speed schedule; outside every rule the overall limits apply
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SpeedSchedule {
+    pub enabled: bool,
+    pub profiles: Vec<SpeedProfile>,
+    /// Checked in order; the first matching rule wins
+    pub rules: Vec<SpeedRule>,
+}
+
+impl SpeedSchedule {
+    pub fn profile(&self, name: &str) -> Option<&SpeedProfile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+
+    /// Profile scheduled at `minute` on `day`, if any rule covers it
+    pub fn profile_at(&self, day: Weekday, minute: u32) -> Option<&SpeedProfile> {
+        if !self.enabled {
+            return None;
+        }
+        self.rules
+            .iter()
+            .find(|rule| rule.window.contains(day, minute))
+            .and_then(|rule| self.profile(&rule.profile))
+    }
+}
+
+/// Hours the download queue may run in; outside them tasks are paused
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DownloadWindows {
+    pub enabled: bool,
+    pub windows: Vec<TimeWindow>,
+}
+
+impl DownloadWindows {
+    /// Whether tasks may run at `minute` on `day`; always true when disabled
+    pub fn is_open(&self, day: Weekday, minute: u32) -> bool {
+        !self.enabled || self.windows.iter().any(|window| window.contains(day, minute))
+    }
+}
+
+/// Routes matching downloads to a folder of their own
+///
+/// Every criterion that is set must match, any entry of a list will do. A rule
+/// without criteria never matches. Rules are checked in order, the first
+/// matching one wins.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CategoryRule {
+    pub name: String,
+    /// File extensions without the dot, e.g. `mkv` or `tar.gz`
+    pub extensions: Vec<String>,
+    /// MIME types guessed from the file name; `video/*` matches a whole type
+    pub mime_types: Vec<String>,
+    /// Source domains, each matching its subdomains too
+    pub domains: Vec<String>,
+    /// Regular expression searched for in the download URIs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url_pattern: Option<String>,
+    /// Target folder; a relative one sits in the download dir
+    pub dir: String,
+    /// Per-task download limit in aria2 size syntax
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_download_limit: Option<String>,
+    /// Label recorded with the task in the history
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+}
+
+/// Local HTTP endpoint for browser extensions, bound to 127.0.0.1
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BrowserIntegration {
+    pub enabled: bool,
+    pub port: u16,
+    /// Bearer token extensions authenticate with; generated on first load when empty
+    pub token: String,
+}
+
+impl Default for BrowserIntegration {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 16801,
+            token: String::new(),
+        }
+    }
+}
+
 impl Default for AppConfig {
     fn default() -> Self {
         let download_dir = dirs::download_dir().unwrap_or_else(|| PathBuf::from("."));
@@ -131,11 +349,21 @@ impl Default for AppConfig {
             rpc_secret: uuid::Uuid::new_v4().to_string(),
             max_overall_download_limit: "0".to_string(),
             max_overall_upload_limit: "0".to_string(),
+            speed_schedule: SpeedSchedule::default(),
+            download_windows: DownloadWindows::default(),
+            categories: Vec::new(),
+            browser_integration: BrowserIntegration::default(),
             allow_overwrite: false,
             auto_file_renaming: true,
             continue_download: true,
             follow_metalink: "true".to_string(),
 
+            engine_mode: EngineMode::Builtin,
+            external_rpc_scheme: RpcScheme::Ws,
+            external_rpc_host: "127.0.0.1".to_string(),
+            external_rpc_port: 6800,
+            external_rpc_secret: String::new(),
+
             proxy_enabled: false,
             proxy_type: ProxyType::Http,
             proxy_host: String::new(),
@@ -150,6 +378,19 @@ impl Default for AppConfig {
 }
 
 impl AppConfig {
+    /// RPC endpoint of the engine the app should talk to
+    pub fn rpc_endpoint(&self) -> crate::aria2::RpcEndpoint {
+        match self.engine_mode {
+            EngineMode::Builtin => crate::aria2::RpcEndpoint::local(self.rpc_port, self.rpc_secret.clone()),
+            EngineMode::External => crate::aria2::RpcEndpoint {
+                scheme: self.external_rpc_scheme,
+                host: self.external_rpc_host.clone(),
+                port: self.external_rpc_port,
+                secret: self.external_rpc_secret.clone(),
+            },
+        }
+    }
+
     /// Convert to aria2 command line arguments
     pub fn to_aria2_args(&self) -> Vec<String> {
         let mut args = vec![
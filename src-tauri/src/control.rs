@@ -0,0 +1,438 @@
+//! Scripting Motrix from the command line (`motrix-cli`)
+//!
+//! Commands go to the running app over IPC, so they behave as if made in the
+//! app: category rules and duplicate detection apply to added downloads. When
+//! the app is not running, they go straight to aria2 at the endpoint of the
+//! stored config (`rpcPort`/`rpcSecret`, or the external engine), going
+//! through the same commands in-process.
+
+use crate::aria2::{self, Aria2Client, GlobalStat, Task, TaskStatus};
+use crate::category;
+use crate::cli;
+use crate::config::{AppConfig, EngineMode};
+use crate::duplicate::DuplicateAction;
+use crate::ipc::{self, IpcReply, IpcRequest};
+use crate::{Error, Result};
+use serde_json::{json, Map, Value};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Tauri identifier, naming the app data dir the config is stored in
+pub const APP_IDENTIFIER: &str = "app.motrix.native";
+
+/// Time between status checks while waiting for a task
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+pub const USAGE: &str = "\
+Usage: motrix-cli [--json] [--app | --engine] [--config FILE] <command>
+
+Commands:
+  add [--dir DIR] [--out NAME] [--header 'Name: value']... [--on-duplicate ask|skip|restart|keep-both] URI...
+  list [active|waiting|stopped]
+  pause GID
+  resume GID
+  remove GID
+  stat
+  limit [--download LIMIT] [--upload LIMIT]    e.g. 2M, 0 for unlimited
+  wait GID [--timeout SECONDS]                 exits non-zero if the download fails
+
+Options:
+  --json           print JSON instead of tables
+  --app            only talk to the running app
+  --engine         talk to aria2 directly, bypassing the app
+  --config FILE    stored config to read the aria2 endpoint from";
+
+/// What to do
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Add {
+        uris: Vec<String>,
+        options: Map<String, Value>,
+        on_duplicate: Option<DuplicateAction>,
+    },
+    List {
+        status: Option<String>,
+    },
+    Pause {
+        gid: String,
+    },
+    Resume {
+        gid: String,
+    },
+    Remove {
+        gid: String,
+    },
+    Stat,
+    Limit {
+        download: Option<String>,
+        upload: Option<String>,
+    },
+    /// Wait for a download, and the downloads following it, to complete
+    Wait {
+        gid: String,
+        timeout: Option<Duration>,
+    },
+}
+
+/// Whom to send commands to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Connection {
+    /// The app if it is running, aria2 otherwise
+    #[default]
+    Auto,
+    App,
+    Engine,
+}
+
+/// A parsed command line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invocation {
+    pub command: Command,
+    pub json: bool,
+    pub connection: Connection,
+    /// Stored config to read the aria2 endpoint from
+    pub config: Option<PathBuf>,
+}
+
+fn invalid(message: impl Into<String>) -> Error {
+    Error::Validation(message.into())
+}
+
+/// Parse the arguments after the program name, resolving paths against `cwd`
+pub fn parse(args: &[String], cwd: &Path) -> Result<Invocation> {
+    let mut json = false;
+    let mut connection = Connection::Auto;
+    let mut config = None;
+    let mut words = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--app" => connection = Connection::App,
+            "--engine" => connection = Connection::Engine,
+            "--config" => {
+                let path = args.next().ok_or_else(|| invalid("--config needs a file"))?;
+                config = Some(cwd.join(path));
+            }
+            _ => words.push(arg.clone()),
+        }
+    }
+
+    let (name, rest) = words.split_first().ok_or_else(|| invalid("No command given"))?;
+    let mut rest = rest.iter();
+    let mut value = |flag: &str| {
+        rest.next()
+            .cloned()
+            .ok_or_else(|| invalid(format!("{} needs a value", flag)))
+    };
+    let command = match name.as_str() {
+        "add" => {
+            let (mut uris, mut options, mut headers, mut on_duplicate) = (Vec::new(), Map::new(), Vec::new(), None);
+            while let Ok(arg) = value("add") {
+                match arg.as_str() {
+                    // The app may run elsewhere than the caller
+                    "--dir" => {
+                        options.insert("dir".to_string(), json!(cwd.join(value("--dir")?)));
+                    }
+                    "--out" => {
+                        options.insert("out".to_string(), json!(value("--out")?));
+                    }
+                    "--header" => {
+                        let header = value("--header")?;
+                        cli::check_header(&header)?;
+                        headers.push(header);
+                    }
+                    "--on-duplicate" => {
+                        let action = value("--on-duplicate")?.replace("keep-both", "keepBoth");
+                        on_duplicate = Some(
+                            serde_json::from_value(json!(action))
+                                .map_err(|_| invalid(format!("Unknown duplicate action: {}", action)))?,
+                        );
+                    }
+                    flag if flag.starts_with("--") => return Err(invalid(format!("Unknown option: {}", flag))),
+                    _ => uris.push(arg),
+                }
+            }
+            if uris.is_empty() {
+                return Err(invalid("add needs at least one URI"));
+            }
+            if !headers.is_empty() {
+                options.insert("header".to_string(), json!(headers));
+            }
+            Command::Add {
+                uris,
+                options,
+                on_duplicate,
+            }
+        }
+        "list" => {
+            let status = value("list").ok();
+            if let Some(status) = &status {
+                if !["active", "waiting", "stopped"].contains(&status.as_str()) {
+                    return Err(invalid(format!("Unknown task status: {}", status)));
+                }
+            }
+            Command::List { status }
+        }
+        "pause" => Command::Pause { gid: value("pause")? },
+        "resume" => Command::Resume { gid: value("resume")? },
+        "remove" => Command::Remove { gid: value("remove")? },
+        "stat" => Command::Stat,
+        "limit" => {
+            let (mut download, mut upload) = (None, None);
+            while let Ok(arg) = value("limit") {
+                match arg.as_str() {
+                    "--download" => download = Some(value("--download")?),
+                    "--upload" => upload = Some(value("--upload")?),
+                    _ => return Err(invalid(format!("Unknown option: {}", arg))),
+                }
+            }
+            if download.is_none() && upload.is_none() {
+                return Err(invalid("limit needs --download or --upload"));
+            }
+            Command::Limit { download, upload }
+        }
+        "wait" => {
+            let gid = value("wait")?;
+            let timeout = match value("wait") {
+                Ok(flag) if flag == "--timeout" => {
+                    let seconds = value("--timeout")?;
+                    let seconds: u64 = seconds
+                        .parse()
+                        .map_err(|_| invalid(format!("Invalid timeout: {}", seconds)))?;
+                    Some(Duration::from_secs(seconds))
+                }
+                Ok(arg) => return Err(invalid(format!("Unknown option: {}", arg))),
+                Err(_) => None,
+            };
+            Command::Wait { gid, timeout }
+        }
+        _ => return Err(invalid(format!("Unknown command: {}", name))),
+    };
+    if let Ok(extra) = value("") {
+        return Err(invalid(format!("Unexpected argument: {}", extra)));
+    }
+    Ok(Invocation {
+        command,
+        json,
+        connection,
+        config,
+    })
+}
+
+/// Where the app stores its config
+pub fn config_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join(APP_IDENTIFIER).join("config.json"))
+}
+
+/// Read the config the app stored at `path`
+pub fn stored_config(path: &Path) -> Result<AppConfig> {
+    let store: Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    let config = store
+        .get("config")
+        .cloned()
+        .ok_or_else(|| Error::Config(format!("No config in {}", path.display())))?;
+    Ok(serde_json::from_value(config)?)
+}
+
+/// Where commands are sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// The app listening on this socket
+    App(PathBuf),
+    /// aria2, through the global client
+    Engine,
+}
+
+impl Target {
+    /// Connect as `connection` says, trying the app on `socket` first
+    pub async fn connect(connection: Connection, socket: &Path, config: Option<&Path>) -> Result<Self> {
+        if connection != Connection::Engine {
+            match ipc::request_at(socket, &IpcRequest::Ping).await {
+                Ok(_) => return Ok(Target::App(socket.to_path_buf())),
+                Err(e) if connection == Connection::App => return Err(e),
+                Err(_) => {}
+            }
+        }
+
+        let path = match config {
+            Some(path) => path.to_path_buf(),
+            None => config_path().ok_or_else(|| Error::Config("No data directory".to_string()))?,
+        };
+        let config = stored_config(&path)?;
+        let client = Aria2Client::new(config.rpc_endpoint()).await?;
+        aria2::set_client(Some(Arc::new(client))).await;
+        aria2::set_external_engine(config.engine_mode == EngineMode::External);
+        if let Err(e) = category::configure(&config).await {
+            tracing::warn!("Failed to load category rules: {}", e);
+        }
+        Ok(Target::Engine)
+    }
+
+    /// Send one request, turning an error reply into an error
+    pub async fn send(&self, request: IpcRequest) -> Result<IpcReply> {
+        let reply = match self {
+            Target::App(socket) => ipc::request_at(socket, &request).await?,
+            Target::Engine => ipc::dispatch(request).await,
+        };
+        match reply.error {
+            Some(error) => {
+                let message = error.get("message").and_then(Value::as_str).unwrap_or("Unknown error");
+                Err(Error::Custom(message.to_string()))
+            }
+            None => Ok(reply),
+        }
+    }
+
+    async fn status(&self, gid: &str) -> Result<Task> {
+        let reply = self.send(IpcRequest::Status { gid: gid.to_string() }).await?;
+        Ok(serde_json::from_value(reply.result.unwrap_or_default())?)
+    }
+}
+
+/// Sizes as the app shows them
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+fn status_name(status: TaskStatus) -> String {
+    json!(status).as_str().unwrap_or_default().to_string()
+}
+
+/// Name of a task, its first URI until the files are known
+fn task_name(task: &Task) -> &str {
+    match task.name() {
+        "" => task
+            .files
+            .first()
+            .and_then(|file| file.uris.first())
+            .map_or("", |uri| uri.uri.as_str()),
+        name => name,
+    }
+}
+
+fn write_tasks(out: &mut impl Write, tasks: &[Task]) -> std::io::Result<()> {
+    writeln!(
+        out,
+        "{:<16}  {:<8}  {:>8}  {:>11}  NAME",
+        "GID", "STATUS", "PROGRESS", "SPEED"
+    )?;
+    for task in tasks {
+        let progress = match task.total_length {
+            0 => "-".to_string(),
+            total => format!("{:.1}%", task.completed_length as f64 * 100.0 / total as f64),
+        };
+        let speed = format!("{}/s", format_bytes(task.download_speed));
+        writeln!(
+            out,
+            "{:<16}  {:<8}  {:>8}  {:>11}  {}",
+            task.gid,
+            status_name(task.status),
+            progress,
+            speed,
+            task_name(task)
+        )?;
+    }
+    Ok(())
+}
+
+fn write_json(out: &mut impl Write, value: &Value) -> Result<()> {
+    writeln!(out, "{}", serde_json::to_string_pretty(value)?)?;
+    Ok(())
+}
+
+/// Run `command` against `target`, printing the outcome to `out`
+pub async fn run(target: &Target, command: Command, json: bool, out: &mut impl Write) -> Result<()> {
+    let request = match command {
+        Command::Wait { gid, timeout } => return wait(target, gid, timeout, json, out).await,
+        Command::Add {
+            uris,
+            options,
+            on_duplicate,
+        } => IpcRequest::Add {
+            uris,
+            options: Some(Value::Object(options)),
+            on_duplicate,
+        },
+        Command::List { status } => IpcRequest::List { status },
+        Command::Pause { gid } => IpcRequest::Pause { gid },
+        Command::Resume { gid } => IpcRequest::Resume { gid },
+        Command::Remove { gid } => IpcRequest::Remove { gid },
+        Command::Stat => IpcRequest::Stat,
+        Command::Limit { download, upload } => IpcRequest::Limit { download, upload },
+    };
+    let listing = matches!(request, IpcRequest::List { .. });
+    let reply = target.send(request).await?;
+
+    match (reply.gid, reply.result) {
+        (Some(gid), _) if json => write_json(out, &json!({ "gid": gid })),
+        (Some(gid), _) => Ok(writeln!(out, "{}", gid)?),
+        (None, Some(result)) if json => write_json(out, &result),
+        (None, Some(result)) if listing => {
+            let tasks: Vec<Task> = serde_json::from_value(result)?;
+            Ok(write_tasks(out, &tasks)?)
+        }
+        (None, Some(result)) => {
+            let stat: GlobalStat = serde_json::from_value(result)?;
+            writeln!(out, "Download speed: {}/s", format_bytes(stat.download_speed))?;
+            writeln!(out, "Upload speed:   {}/s", format_bytes(stat.upload_speed))?;
+            writeln!(out, "Active:  {}", stat.num_active)?;
+            writeln!(out, "Waiting: {}", stat.num_waiting)?;
+            writeln!(out, "Stopped: {}", stat.num_stopped)?;
+            Ok(())
+        }
+        (None, None) if json => write_json(out, &json!({})),
+        (None, None) => Ok(()),
+    }
+}
+
+/// Poll a task until it and the tasks following it (a magnet's download) complete
+async fn wait(target: &Target, gid: String, timeout: Option<Duration>, json: bool, out: &mut impl Write) -> Result<()> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let mut pending = vec![gid];
+    let mut done = Vec::new();
+    while let Some(gid) = pending.pop() {
+        loop {
+            let task = target.status(&gid).await?;
+            match task.status {
+                TaskStatus::Complete => {
+                    pending.extend(task.followed_by.iter().cloned());
+                    done.push(task);
+                    break;
+                }
+                TaskStatus::Error => {
+                    return Err(task
+                        .error()
+                        .unwrap_or_else(|| Error::Custom(format!("GID#{} failed", gid))));
+                }
+                TaskStatus::Removed => return Err(Error::Custom(format!("GID#{} was removed", gid))),
+                _ => {}
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Err(Error::Custom(format!("Timed out waiting for GID#{}", gid)));
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    if json {
+        return write_json(out, &serde_json::to_value(&done)?);
+    }
+    for task in &done {
+        writeln!(out, "{}  complete  {}", task.gid, task_name(task))?;
+    }
+    Ok(())
+}
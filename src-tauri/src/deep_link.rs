@@ -0,0 +1,210 @@
+//! `motrix://` deep links
+//!
+//! ```text
+//! motrix://add?uri=URL[&uri=URL...][&dir=ABSOLUTE_DIR][&out=NAME]
+//! motrix://pause-all
+//! motrix://open?gid=GID
+//! ```
+//!
+//! Any web page can open these links, so none of them changes anything
+//! without the user agreeing: `add` only fills in the add dialog and
+//! `pause-all` asks first; `open`, which just shows a task, acts directly.
+//! Links carry no request headers, which the add dialog would send unseen.
+//! The older `motrix://<URL>` form is read as `add`.
+
+use crate::cli::{self, LaunchRequest};
+use crate::commands;
+use crate::handoff;
+use crate::{Error, Result};
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter, Manager, Url};
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+
+pub const SCHEME: &str = "motrix";
+
+/// Most downloads one link may add
+const MAX_URIS: usize = 100;
+
+/// What a link asks for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeepLink {
+    Add {
+        uris: Vec<String>,
+        dir: Option<PathBuf>,
+        out: Option<String>,
+    },
+    PauseAll,
+    /// Show a task's details
+    Open {
+        gid: String,
+    },
+}
+
+/// How the user agrees to a link before it acts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Confirmation {
+    /// Harmless, acted on straight away
+    None,
+    /// Filled into the add dialog, which the user submits
+    Review,
+    /// Asked in a message box
+    Ask,
+}
+
+impl DeepLink {
+    pub fn confirmation(&self) -> Confirmation {
+        match self {
+            DeepLink::Add { .. } => Confirmation::Review,
+            DeepLink::PauseAll => Confirmation::Ask,
+            DeepLink::Open { .. } => Confirmation::None,
+        }
+    }
+}
+
+/// Whether `s` is a `motrix://` link
+pub fn is_deep_link(s: &str) -> bool {
+    s.get(..SCHEME.len() + 3)
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case(&format!("{}://", SCHEME)))
+}
+
+fn invalid(link: &str, reason: impl std::fmt::Display) -> Error {
+    Error::Validation(format!("Invalid link {}: {}", link, reason))
+}
+
+/// Parse and validate a link
+pub fn parse(link: &str) -> Result<DeepLink> {
+    if !is_deep_link(link) {
+        return Err(invalid(link, "not a motrix:// link"));
+    }
+    // motrix://https://example.com/a.zip
+    let wrapped = &link[SCHEME.len() + 3..];
+    if let Ok(uri) = cli::decode_link(wrapped) {
+        return Ok(DeepLink::Add {
+            uris: vec![uri],
+            dir: None,
+            out: None,
+        });
+    }
+
+    let url = Url::parse(link).map_err(|e| invalid(link, e))?;
+    if !matches!(url.path(), "" | "/") {
+        return Err(invalid(link, format!("unexpected path {}", url.path())));
+    }
+    let action = url.host_str().unwrap_or_default().to_ascii_lowercase();
+    let params: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+    let parsed = match action.as_str() {
+        "add" => {
+            let (mut uris, mut dir, mut out) = (Vec::new(), None, None);
+            for (key, value) in params {
+                match key.as_str() {
+                    "uri" => uris.push(cli::decode_link(&value).map_err(|e| invalid(link, e))?),
+                    // Relative to what? The page does not know our download directory
+                    "dir" if dir.is_none() && Path::new(&value).is_absolute() => dir = Some(PathBuf::from(value)),
+                    "out" if out.is_none() && is_file_name(&value) => out = Some(value),
+                    _ => return Err(invalid(link, format!("bad or repeated parameter {}", key))),
+                }
+            }
+            if uris.is_empty() || uris.len() > MAX_URIS {
+                return Err(invalid(link, format!("needs 1 to {} uri parameters", MAX_URIS)));
+            }
+            if out.is_some() && uris.len() > 1 {
+                return Err(invalid(link, "out needs a single uri"));
+            }
+            DeepLink::Add { uris, dir, out }
+        }
+        "pause-all" if !params.is_empty() => return Err(invalid(link, "pause-all takes no parameters")),
+        "pause-all" => DeepLink::PauseAll,
+        "open" => match params.as_slice() {
+            [(key, gid)] if key == "gid" && gid.len() == 16 && gid.bytes().all(|b| b.is_ascii_hexdigit()) => {
+                DeepLink::Open { gid: gid.clone() }
+            }
+            _ => return Err(invalid(link, "needs a single gid parameter")),
+        },
+        _ => return Err(invalid(link, format!("unknown action {}", action))),
+    };
+    Ok(parsed)
+}
+
+/// A plain file name, which cannot point outside the download directory
+fn is_file_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\'])
+}
+
+fn show_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.show();
+        let _ = window.set_focus();
+        let _ = window.unminimize();
+    }
+}
+
+/// Act on a link, asking the user first where its confirmation says so
+pub fn handle(app: &AppHandle, link: DeepLink) {
+    if link.confirmation() != Confirmation::Ask {
+        act(app, link);
+        return;
+    }
+    let message = match &link {
+        DeepLink::PauseAll => "A web page asks Motrix to pause all downloads.",
+        _ => "A web page asks Motrix to act.",
+    };
+    let app_handle = app.clone();
+    app.dialog()
+        .message(message)
+        .title("Motrix")
+        .kind(MessageDialogKind::Warning)
+        .buttons(MessageDialogButtons::OkCancelCustom(
+            "Allow".to_string(),
+            "Cancel".to_string(),
+        ))
+        .show(move |allowed| {
+            if allowed {
+                act(&app_handle, link);
+            } else {
+                tracing::info!("Deep link declined: {:?}", link);
+            }
+        });
+}
+
+fn act(app: &AppHandle, link: DeepLink) {
+    match link {
+        DeepLink::Add { uris, dir, out } => {
+            show_window(app);
+            // Never hidden: the dialog is where the user reviews the download
+            cli::handle(
+                app,
+                LaunchRequest {
+                    uris,
+                    dir,
+                    out,
+                    ..LaunchRequest::default()
+                },
+            );
+        }
+        DeepLink::PauseAll => {
+            tauri::async_runtime::spawn(async {
+                if let Err(e) = commands::pause_all_tasks().await {
+                    tracing::error!("Failed to pause all: {}", e);
+                }
+            });
+        }
+        DeepLink::Open { gid } => {
+            show_window(app);
+            let _ = app.emit("open-task", &gid);
+        }
+    }
+}
+
+/// Parse a link opened through the deep-link plugin, acting on it once the app is ready
+pub fn open(app: &AppHandle, link: &str) {
+    match parse(link) {
+        Ok(link) => handoff::push(
+            app,
+            LaunchRequest {
+                links: vec![link],
+                ..LaunchRequest::default()
+            },
+        ),
+        Err(e) => tracing::warn!("Ignoring deep link: {}", e),
+    }
+}
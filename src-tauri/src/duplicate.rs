@@ -0,0 +1,341 @@
+//! Duplicate detection when adding downloads
+//!
+//! Before a download is added, its URIs are compared with those of the
+//! active, waiting and most recently stopped tasks, its info hash (from a
+//! torrent or a magnet link) with the BT tasks, and its target path with the
+//! files already in the download folder when the engine is the bundled one
+//! (an external daemon's folders may be on another host). A duplicate fails
+//! the add with `Error::Duplicate` unless the caller chose what to do about
+//! it: skip, restart or keep both.
+
+use crate::aria2::{self, Aria2Client, Task, TaskStatus};
+use crate::category::Source;
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha1::{Digest, Sha1};
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+use tauri::Url;
+
+/// Stopped tasks compared against, newest first
+const RECENT_STOPPED: i32 = 100;
+
+/// Deepest bencode nesting read from a torrent
+const MAX_DEPTH: usize = 64;
+
+/// What the new download has in common with an existing one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DuplicateReason {
+    Uri,
+    InfoHash,
+    File,
+}
+
+/// An existing task or file a new download duplicates
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Duplicate {
+    pub reason: DuplicateReason,
+    /// The existing task; `None` when only the file exists
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<TaskStatus>,
+    pub name: String,
+    /// The file already on disk
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+impl fmt::Display for Duplicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.gid, &self.path) {
+            (Some(gid), _) => write!(f, "{} is already a download (GID#{})", self.name, gid),
+            (None, Some(path)) => write!(f, "{} already exists", path),
+            (None, None) => write!(f, "{} is already downloaded", self.name),
+        }
+    }
+}
+
+/// What to do when a download duplicates an existing one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DuplicateAction {
+    /// Fail with `Error::Duplicate`, so the user can choose
+    #[default]
+    Ask,
+    /// Add nothing and answer with the existing task's gid
+    Skip,
+    /// Remove the existing task and download again, overwriting the file
+    Restart,
+    /// Download again next to it under a new name
+    KeepBoth,
+}
+
+/// What is known about a download about to be added
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Candidate {
+    pub uris: Vec<String>,
+    /// Lowercase hex info hash of a torrent or magnet link
+    pub info_hash: Option<String>,
+    /// Name the download will be saved as
+    pub name: Option<String>,
+    /// Folder from the task options; aria2's global `dir` without one
+    pub dir: Option<String>,
+}
+
+impl Candidate {
+    /// A download of `uris` with task `options`
+    pub fn from_uris(uris: &[String], options: Option<&Value>) -> Self {
+        let option = |key: &str| options.and_then(|options| options.get(key)).and_then(Value::as_str);
+        Self {
+            uris: uris.to_vec(),
+            info_hash: uris.iter().find_map(|uri| magnet_info_hash(uri)),
+            name: Source::from_uris(uris, option("out")).names.into_iter().next(),
+            dir: option("dir").map(String::from),
+        }
+    }
+
+    /// A torrent added with task `options`
+    pub fn from_torrent(data: &[u8], options: Option<&Value>) -> Self {
+        use serde_bencode::value::Value as Bencode;
+
+        let info = info_bytes(data);
+        let name = info
+            .and_then(|info| match serde_bencode::from_bytes::<Bencode>(info) {
+                Ok(Bencode::Dict(info)) => match info.get(b"name".as_slice()) {
+                    Some(Bencode::Bytes(name)) => Some(String::from_utf8_lossy(name).into_owned()),
+                    _ => None,
+                },
+                _ => None,
+            });
+        Self {
+            uris: Vec::new(),
+            info_hash: info.map(|info| hex::encode(Sha1::digest(info))),
+            name,
+            dir: options
+                .and_then(|options| options.get("dir"))
+                .and_then(Value::as_str)
+                .map(String::from),
+        }
+    }
+}
+
+/// Length of the bencoded value at the start of `data`
+fn value_len(data: &[u8], depth: usize) -> Option<usize> {
+    match *data.first()? {
+        b'i' => Some(data.iter().position(|&b| b == b'e')? + 1),
+        b'l' | b'd' if depth < MAX_DEPTH => {
+            let mut pos = 1;
+            while *data.get(pos)? != b'e' {
+                pos += value_len(&data[pos..], depth + 1)?;
+            }
+            Some(pos + 1)
+        }
+        b'0'..=b'9' => {
+            let colon = data.iter().position(|&b| b == b':')?;
+            let len: usize = std::str::from_utf8(&data[..colon]).ok()?.parse().ok()?;
+            let end = (colon + 1).checked_add(len)?;
+            (end <= data.len()).then_some(end)
+        }
+        _ => None,
+    }
+}
+
+/// The `info` dictionary of a torrent exactly as encoded, which is what its info hash is taken of
+pub fn info_bytes(data: &[u8]) -> Option<&[u8]> {
+    if data.first() != Some(&b'd') {
+        return None;
+    }
+    let mut pos = 1;
+    while *data.get(pos)? != b'e' {
+        let key_len = value_len(&data[pos..], 1)?;
+        let key = &data[pos..pos + key_len];
+        pos += key_len;
+        let len = value_len(&data[pos..], 1)?;
+        if key == b"4:info" {
+            return Some(&data[pos..pos + len]);
+        }
+        pos += len;
+    }
+    None
+}
+
+/// Decode RFC 4648 base32, as used by older magnet links
+fn base32_decode(text: &str) -> Option<Vec<u8>> {
+    let (mut bits, mut count, mut bytes) = (0u32, 0, Vec::new());
+    for c in text.bytes().map(|c| c.to_ascii_uppercase()) {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'2'..=b'7' => c - b'2' + 26,
+            _ => return None,
+        };
+        bits = (bits << 5) | u32::from(value);
+        count += 5;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+            bits &= (1 << count) - 1;
+        }
+    }
+    Some(bytes)
+}
+
+/// Info hash of a magnet link, in lowercase hex
+pub fn magnet_info_hash(uri: &str) -> Option<String> {
+    let url = Url::parse(uri).ok().filter(|url| url.scheme() == "magnet")?;
+    let pairs: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+    pairs.iter().find_map(|(key, value)| {
+        let hash = value.strip_prefix("urn:btih:").filter(|_| key == "xt")?;
+        match hash.len() {
+            40 if hash.bytes().all(|b| b.is_ascii_hexdigit()) => Some(hash.to_ascii_lowercase()),
+            32 => base32_decode(hash).map(hex::encode),
+            _ => None,
+        }
+    })
+}
+
+/// A URI as compared: parsed and without its fragment
+fn normalize(uri: &str) -> String {
+    match Url::parse(uri.trim()) {
+        Ok(mut url) => {
+            url.set_fragment(None);
+            url.to_string()
+        }
+        Err(_) => uri.trim().to_string(),
+    }
+}
+
+/// Why `task` duplicates a download of `uris` or `info_hash`, if it does
+fn task_match(task: &Task, uris: &HashSet<String>, info_hash: Option<&str>) -> Option<DuplicateReason> {
+    if info_hash.is_some() && task.info_hash.as_deref().map(str::to_ascii_lowercase).as_deref() == info_hash {
+        return Some(DuplicateReason::InfoHash);
+    }
+    task.files
+        .iter()
+        .flat_map(|file| &file.uris)
+        .any(|uri| uris.contains(&normalize(&uri.uri)))
+        .then_some(DuplicateReason::Uri)
+}
+
+/// The first task or file `candidate` duplicates
+pub async fn find(client: &Aria2Client, candidate: &Candidate) -> Result<Option<Duplicate>> {
+    let uris: HashSet<String> = candidate.uris.iter().map(|uri| normalize(uri)).collect();
+    if !uris.is_empty() || candidate.info_hash.is_some() {
+        let stat = client.get_global_stat().await?;
+        let mut tasks = client.tell_active_and_waiting(stat.num_waiting as i32).await?;
+        let stopped = stat.num_stopped as i32;
+        let mut recent = client.tell_stopped((stopped - RECENT_STOPPED).max(0), RECENT_STOPPED).await?;
+        recent.reverse();
+        // A removed task was dropped on purpose
+        tasks.extend(recent.into_iter().filter(|task| task.status != TaskStatus::Removed));
+
+        for task in &tasks {
+            if let Some(reason) = task_match(task, &uris, candidate.info_hash.as_deref()) {
+                let name = match task.name() {
+                    "" => candidate.name.clone().unwrap_or_default(),
+                    name => name.to_string(),
+                };
+                return Ok(Some(Duplicate {
+                    reason,
+                    gid: Some(task.gid.clone()),
+                    status: Some(task.status),
+                    name,
+                    path: None,
+                }));
+            }
+        }
+    }
+
+    // The folders of an external daemon are not ours to look into
+    if aria2::is_external_engine() {
+        return Ok(None);
+    }
+    let Some(name) = candidate.name.as_deref().filter(|name| !name.is_empty()) else {
+        return Ok(None);
+    };
+    let dir = match &candidate.dir {
+        Some(dir) => dir.clone(),
+        None => match client.get_global_option().await?.get("dir") {
+            Some(dir) => dir.clone(),
+            None => return Ok(None),
+        },
+    };
+    let path = Path::new(&dir).join(name);
+    Ok(path.exists().then(|| Duplicate {
+        reason: DuplicateReason::File,
+        gid: None,
+        status: None,
+        name: name.to_string(),
+        path: Some(path.to_string_lossy().into_owned()),
+    }))
+}
+
+/// Set task options, creating them if there are none
+fn set_options(options: &mut Option<Value>, pairs: &[(&str, &str)]) {
+    if let Some(options) = options.get_or_insert_with(|| json!({})).as_object_mut() {
+        for (key, value) in pairs {
+            options.insert(key.to_string(), json!(value));
+        }
+    }
+}
+
+/// Remove a task and its record, waiting for a running one to stop
+async fn remove_task(client: &Aria2Client, gid: &str) -> Result<()> {
+    match client.tell_status_keys(gid, &[]).await?.status {
+        TaskStatus::Active | TaskStatus::Waiting | TaskStatus::Paused => {
+            client.force_remove(gid).await?;
+            for _ in 0..50 {
+                if client.tell_status_keys(gid, &[]).await?.status == TaskStatus::Removed {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        }
+        _ => {}
+    }
+    // The record only matters for the stopped task list
+    if let Err(e) = client.remove_download_result(gid).await {
+        tracing::debug!("Failed to remove the record of {}: {}", gid, e);
+    }
+    Ok(())
+}
+
+/// Look for a duplicate of `candidate` and act on it as `action` says
+///
+/// Returns the gid to answer with instead of adding the download, if any.
+/// Otherwise the download should be added with `options`, which may have
+/// been adjusted for the chosen action.
+pub async fn resolve(
+    client: &Aria2Client,
+    candidate: &Candidate,
+    action: DuplicateAction,
+    options: &mut Option<Value>,
+) -> Result<Option<String>> {
+    let Some(duplicate) = find(client, candidate).await? else {
+        return Ok(None);
+    };
+    match action {
+        DuplicateAction::Ask => Err(Error::Duplicate(Box::new(duplicate))),
+        // Without a task there is nothing to answer with
+        DuplicateAction::Skip => match duplicate.gid.clone() {
+            Some(gid) => Ok(Some(gid)),
+            None => Err(Error::Duplicate(Box::new(duplicate))),
+        },
+        DuplicateAction::Restart => {
+            if let Some(gid) = &duplicate.gid {
+                remove_task(client, gid).await?;
+            }
+            set_options(options, &[("allow-overwrite", "true"), ("auto-file-renaming", "false")]);
+            Ok(None)
+        }
+        DuplicateAction::KeepBoth => {
+            set_options(options, &[("auto-file-renaming", "true")]);
+            Ok(None)
+        }
+    }
+}
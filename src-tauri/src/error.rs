@@ -1,3 +1,5 @@
+use crate::duplicate::Duplicate;
+use serde::ser::SerializeStruct;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -8,8 +10,36 @@ pub enum Error {
     #[error("JSON error: {0}")]
     Json(#[from] serde_json::Error),
 
-    #[error("Aria2 RPC error: {0}")]
-    Aria2Rpc(String),
+    /// aria2 rejected an RPC call
+    #[error("Aria2 RPC error: {message}")]
+    Aria2Rpc { code: i32, message: String },
+
+    /// A download stopped with an aria2 exit code (tellStatus `errorCode`)
+    #[error("Download failed: {message}")]
+    Download { code: u8, message: String },
+
+    #[error("Aria2 RPC call timed out")]
+    Timeout,
+
+    /// The engine is unreachable or the connection dropped
+    #[error("{0}")]
+    Disconnected(String),
+
+    /// aria2 answered with something we could not decode
+    #[error("Malformed aria2 response: {0}")]
+    Protocol(String),
+
+    /// Input was rejected before reaching aria2
+    #[error("Invalid input: {0}")]
+    Validation(String),
+
+    /// A recognised link aria2 cannot download, e.g. `ed2k://`
+    #[error("Unsupported link: {0}")]
+    Unsupported(String),
+
+    /// The download duplicates an existing task or file
+    #[error("{0}")]
+    Duplicate(Box<Duplicate>),
 
     #[error("WebSocket error: {0}")]
     WebSocket(String),
@@ -29,12 +59,184 @@ pub enum Error {
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-// Implement serialization for Tauri commands
+impl Error {
+    /// Machine-readable category of the error, sent to the frontend as `kind`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Io(_) => "io",
+            Error::Json(_) => "json",
+            Error::Aria2Rpc { .. } => "rpc",
+            Error::Download { .. } => "download",
+            Error::Timeout => "timeout",
+            Error::Disconnected(_) => "disconnected",
+            Error::Protocol(_) => "protocol",
+            Error::Validation(_) => "validation",
+            Error::Unsupported(_) => "unsupported",
+            Error::Duplicate(_) => "duplicate",
+            Error::WebSocket(_) => "websocket",
+            Error::Config(_) => "config",
+            Error::Tauri(_) => "tauri",
+            Error::Store(_) => "store",
+            Error::Custom(_) => "custom",
+        }
+    }
+
+    /// The aria2 RPC error code or download exit code, if any
+    pub fn code(&self) -> Option<i64> {
+        match self {
+            Error::Aria2Rpc { code, .. } => Some(i64::from(*code)),
+            Error::Download { code, .. } => Some(i64::from(*code)),
+            _ => None,
+        }
+    }
+
+    /// The exit code of a failed download, decoded
+    pub fn download_exit(&self) -> Option<DownloadExit> {
+        match self {
+            Error::Download { code, .. } => DownloadExit::from_code(*code),
+            _ => None,
+        }
+    }
+}
+
+/// aria2 exit codes, also reported per download as `errorCode`
+///
+/// See the "EXIT STATUS" section of the aria2c manual.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloadExit {
+    Success = 0,
+    Unknown = 1,
+    Timeout = 2,
+    ResourceNotFound = 3,
+    TooManyNotFound = 4,
+    TooSlow = 5,
+    Network = 6,
+    Unfinished = 7,
+    ResumeUnsupported = 8,
+    DiskFull = 9,
+    PieceLengthMismatch = 10,
+    DuplicateDownload = 11,
+    DuplicateInfoHash = 12,
+    FileExists = 13,
+    RenameFailed = 14,
+    OpenFailed = 15,
+    CreateFailed = 16,
+    FileIo = 17,
+    CreateDirFailed = 18,
+    NameResolution = 19,
+    MetalinkParse = 20,
+    FtpCommand = 21,
+    BadHttpResponse = 22,
+    TooManyRedirects = 23,
+    HttpAuth = 24,
+    BencodeParse = 25,
+    TorrentCorrupted = 26,
+    BadMagnet = 27,
+    BadOption = 28,
+    ServerOverloaded = 29,
+    RpcParse = 30,
+    Reserved = 31,
+    ChecksumMismatch = 32,
+}
+
+impl DownloadExit {
+    const ALL: [DownloadExit; 33] = [
+        Self::Success,
+        Self::Unknown,
+        Self::Timeout,
+        Self::ResourceNotFound,
+        Self::TooManyNotFound,
+        Self::TooSlow,
+        Self::Network,
+        Self::Unfinished,
+        Self::ResumeUnsupported,
+        Self::DiskFull,
+        Self::PieceLengthMismatch,
+        Self::DuplicateDownload,
+        Self::DuplicateInfoHash,
+        Self::FileExists,
+        Self::RenameFailed,
+        Self::OpenFailed,
+        Self::CreateFailed,
+        Self::FileIo,
+        Self::CreateDirFailed,
+        Self::NameResolution,
+        Self::MetalinkParse,
+        Self::FtpCommand,
+        Self::BadHttpResponse,
+        Self::TooManyRedirects,
+        Self::HttpAuth,
+        Self::BencodeParse,
+        Self::TorrentCorrupted,
+        Self::BadMagnet,
+        Self::BadOption,
+        Self::ServerOverloaded,
+        Self::RpcParse,
+        Self::Reserved,
+        Self::ChecksumMismatch,
+    ];
+
+    pub fn from_code(code: u8) -> Option<Self> {
+        Self::ALL.get(usize::from(code)).copied()
+    }
+
+    /// English description, used when aria2 sends no error message
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::Success => "All downloads were successful",
+            Self::Unknown => "An unknown error occurred",
+            Self::Timeout => "Timed out",
+            Self::ResourceNotFound => "Resource was not found",
+            Self::TooManyNotFound => "Too many \"resource not found\" errors",
+            Self::TooSlow => "Download speed was too slow",
+            Self::Network => "Network problem occurred",
+            Self::Unfinished => "There were unfinished downloads",
+            Self::ResumeUnsupported => "Remote server did not support resume",
+            Self::DiskFull => "Not enough disk space available",
+            Self::PieceLengthMismatch => "Piece length differs from the control file",
+            Self::DuplicateDownload => "The same file was being downloaded",
+            Self::DuplicateInfoHash => "The same info hash was being downloaded",
+            Self::FileExists => "File already existed",
+            Self::RenameFailed => "Renaming file failed",
+            Self::OpenFailed => "Could not open existing file",
+            Self::CreateFailed => "Could not create new file or truncate existing file",
+            Self::FileIo => "File I/O error occurred",
+            Self::CreateDirFailed => "Could not create directory",
+            Self::NameResolution => "Name resolution failed",
+            Self::MetalinkParse => "Could not parse Metalink document",
+            Self::FtpCommand => "FTP command failed",
+            Self::BadHttpResponse => "HTTP response header was bad or unexpected",
+            Self::TooManyRedirects => "Too many redirects occurred",
+            Self::HttpAuth => "HTTP authorization failed",
+            Self::BencodeParse => "Could not parse bencoded file",
+            Self::TorrentCorrupted => "Torrent file was corrupted or missing information",
+            Self::BadMagnet => "Magnet URI was bad",
+            Self::BadOption => "Bad or unrecognized option",
+            Self::ServerOverloaded => "Remote server was unable to handle the request",
+            Self::RpcParse => "Could not parse JSON-RPC request",
+            Self::Reserved => "Reserved",
+            Self::ChecksumMismatch => "Checksum validation failed",
+        }
+    }
+}
+
+// Serialize as `{kind, code, message}` for Tauri commands, plus `duplicate` for duplicates
 impl serde::Serialize for Error {
     fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        serializer.serialize_str(&self.to_string())
+        let duplicate = match self {
+            Error::Duplicate(duplicate) => Some(duplicate),
+            _ => None,
+        };
+        let mut state = serializer.serialize_struct("Error", if duplicate.is_some() { 4 } else { 3 })?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("code", &self.code())?;
+        state.serialize_field("message", &self.to_string())?;
+        if let Some(duplicate) = duplicate {
+            state.serialize_field("duplicate", duplicate)?;
+        }
+        state.end()
     }
 }
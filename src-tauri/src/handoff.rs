@@ -0,0 +1,84 @@
+//! Handing launch requests to the app once it can act on them
+//!
+//! Requests from the command line, second instances and deep links can arrive
+//! before the frontend listens for them or before the engine takes downloads.
+//! They wait here until the frontend calls `frontend_ready` and `init_engine`
+//! has finished, and are then delivered exactly once, in arrival order.
+
+use crate::cli::{self, LaunchRequest};
+use std::sync::Mutex;
+use tauri::AppHandle;
+
+/// Locked from the plugins' synchronous callbacks, hence not a tokio mutex
+static PENDING: Mutex<Pending> = Mutex::new(Pending::new());
+
+/// Requests waiting for the app to be ready
+#[derive(Debug, Default)]
+pub struct Pending {
+    frontend_ready: bool,
+    engine_ready: bool,
+    queue: Vec<LaunchRequest>,
+}
+
+impl Pending {
+    pub const fn new() -> Self {
+        Self {
+            frontend_ready: false,
+            engine_ready: false,
+            queue: Vec::new(),
+        }
+    }
+
+    /// Queue a request, returning what can be delivered now
+    pub fn push(&mut self, request: LaunchRequest) -> Vec<LaunchRequest> {
+        self.queue.push(request);
+        self.take()
+    }
+
+    pub fn set_frontend_ready(&mut self) -> Vec<LaunchRequest> {
+        self.frontend_ready = true;
+        self.take()
+    }
+
+    pub fn set_engine_ready(&mut self) -> Vec<LaunchRequest> {
+        self.engine_ready = true;
+        self.take()
+    }
+
+    fn take(&mut self) -> Vec<LaunchRequest> {
+        if self.frontend_ready && self.engine_ready {
+            std::mem::take(&mut self.queue)
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+fn deliver(app: &AppHandle, requests: Vec<LaunchRequest>) {
+    for request in requests {
+        cli::handle(app, request);
+    }
+}
+
+fn lock() -> std::sync::MutexGuard<'static, Pending> {
+    // A panic while queueing leaves the queue itself intact
+    PENDING.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Act on a request once the app is ready
+pub fn push(app: &AppHandle, request: LaunchRequest) {
+    let ready = lock().push(request);
+    deliver(app, ready);
+}
+
+/// The frontend listens for `open-urls` and `open-task`
+pub fn frontend_ready(app: &AppHandle) {
+    let ready = lock().set_frontend_ready();
+    deliver(app, ready);
+}
+
+/// `init_engine` finished, whether or not the engine started
+pub fn engine_ready(app: &AppHandle) {
+    let ready = lock().set_engine_ready();
+    deliver(app, ready);
+}
@@ -0,0 +1,649 @@
+//! Persistent download history
+//!
+//! aria2 forgets stopped tasks on purge, on restart and when completed records
+//! are auto-cleared. Every task the engine reports is therefore recorded in
+//! `history.json` in the app data dir, so it can be searched, exported and
+//! re-added later. Changes are saved in batches off the runtime threads, and
+//! only the newest `MAX_ENTRIES` finished tasks are kept.
+
+use crate::aria2::{Aria2Client, Aria2EventType, ClientEvent, ConnectionState, Options, Task, TaskStatus};
+use crate::category::{self, TaskCategory};
+use crate::checksum::ChecksumStatus;
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{broadcast, Mutex, Notify};
+
+/// Entries kept by default; the oldest finished ones go first
+pub const MAX_ENTRIES: usize = 10_000;
+
+/// Delay between a change and the save it triggers, so bursts are written once
+const SAVE_DELAY: Duration = Duration::from_secs(2);
+
+/// History of the running app, loaded by `init`
+static HISTORY: Mutex<Option<History>> = Mutex::const_new(None);
+
+/// Wakes the saver after the loaded history changed
+static SAVE: Notify = Notify::const_new();
+
+/// The loaded history has changes not written yet
+static DIRTY: AtomicBool = AtomicBool::new(false);
+
+/// The saver was started by `init`
+static SAVER: AtomicBool = AtomicBool::new(false);
+
+/// What is remembered about a task
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub gid: String,
+    pub name: String,
+    #[serde(default)]
+    pub uris: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub info_hash: Option<String>,
+    pub dir: String,
+    /// Paths of the task's files
+    #[serde(default)]
+    pub files: Vec<String>,
+    pub total_length: u64,
+    pub completed_length: u64,
+    /// Task options that differed from the global ones when first recorded
+    #[serde(default)]
+    pub options: Options,
+    pub status: TaskStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_message: Option<String>,
+    /// Unix time in milliseconds when the task was first seen
+    pub added_at: u64,
+    /// Unix time in milliseconds when the task was first seen stopped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<u64>,
+    /// Outcome of checksum verification after completion
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<ChecksumStatus>,
+    /// Category the task was routed to, with its tag
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<TaskCategory>,
+}
+
+/// Whether a task option carries credentials, such as cookies in `header`, which stay out of the history
+fn is_secret_option(key: &str) -> bool {
+    matches!(key, "header" | "rpc-secret") || key.ends_with("-passwd") || key.ends_with("-user")
+}
+
+impl HistoryEntry {
+    fn new(task: &Task, mut options: Options, now: u64) -> Self {
+        options.retain(|key, _| !is_secret_option(key));
+        let mut entry = Self {
+            gid: task.gid.clone(),
+            name: String::new(),
+            uris: Vec::new(),
+            info_hash: None,
+            dir: String::new(),
+            files: Vec::new(),
+            total_length: 0,
+            completed_length: 0,
+            options,
+            status: task.status,
+            error_code: None,
+            error_message: None,
+            added_at: now,
+            finished_at: None,
+            checksum: None,
+            category: None,
+        };
+        entry.update(task, now);
+        entry
+    }
+
+    /// Take the latest state of the task, keeping what aria2 no longer reports
+    fn update(&mut self, task: &Task, now: u64) {
+        let uris: Vec<String> = task
+            .files
+            .iter()
+            .flat_map(|file| &file.uris)
+            .map(|uri| uri.uri.clone())
+            .collect();
+        if !uris.is_empty() {
+            let mut seen = HashSet::new();
+            self.uris = uris.into_iter().filter(|uri| seen.insert(uri.clone())).collect();
+        }
+        let files: Vec<String> = task
+            .files
+            .iter()
+            .filter(|file| !file.path.is_empty())
+            .map(|file| file.path.clone())
+            .collect();
+        if !files.is_empty() {
+            self.files = files;
+        }
+        if !task.name().is_empty() {
+            self.name = task.name().to_string();
+        } else if self.name.is_empty() {
+            self.name = self
+                .uris
+                .first()
+                .and_then(|uri| uri.rsplit('/').find(|part| !part.is_empty()))
+                .unwrap_or_default()
+                .to_string();
+        }
+        if task.info_hash.is_some() {
+            self.info_hash = task.info_hash.clone();
+        }
+        if !task.dir.is_empty() {
+            self.dir = task.dir.clone();
+        }
+        if task.total_length > 0 {
+            self.total_length = task.total_length;
+        }
+        self.completed_length = task.completed_length;
+        self.status = task.status;
+        self.error_code = task.error_code.clone().filter(|code| code != "0");
+        self.error_message = task.error_message.clone().filter(|message| !message.is_empty());
+        if is_stopped(self.status) {
+            self.finished_at.get_or_insert(now);
+        } else {
+            self.finished_at = None;
+        }
+    }
+
+    fn matches(&self, query: &HistoryQuery) -> bool {
+        if !query.status.is_empty() && !query.status.contains(&self.status) {
+            return false;
+        }
+        let Some(search) = &query.search else {
+            return true;
+        };
+        let search = search.to_lowercase();
+        self.name.to_lowercase().contains(&search)
+            || self.uris.iter().any(|uri| uri.to_lowercase().contains(&search))
+            || self.files.iter().any(|path| path.to_lowercase().contains(&search))
+            || self.category.as_ref().is_some_and(|category| {
+                category.name.to_lowercase().contains(&search)
+                    || category.tag.as_ref().is_some_and(|tag| tag.to_lowercase().contains(&search))
+            })
+    }
+
+    /// URIs to download the task again; torrents without a URI fall back to a magnet link
+    fn download_uris(&self) -> Vec<String> {
+        if !self.uris.is_empty() {
+            return self.uris.clone();
+        }
+        self.info_hash
+            .iter()
+            .map(|hash| format!("magnet:?xt=urn:btih:{}", hash))
+            .collect()
+    }
+}
+
+fn is_stopped(status: TaskStatus) -> bool {
+    matches!(status, TaskStatus::Complete | TaskStatus::Error | TaskStatus::Removed)
+}
+
+/// Filters and page of a history search
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HistoryQuery {
+    /// Case-insensitive substring of the name, a URI or a file path
+    pub search: Option<String>,
+    /// Final statuses to include; empty means every entry
+    pub status: Vec<TaskStatus>,
+    pub offset: usize,
+    /// Page size; `None` returns every match
+    pub limit: Option<usize>,
+}
+
+/// One page of history entries, newest first
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryPage {
+    pub entries: Vec<HistoryEntry>,
+    /// Number of matching entries across all pages
+    pub total: usize,
+}
+
+/// File format of `export_history`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+/// Download history backed by a JSON file
+#[derive(Debug)]
+pub struct History {
+    path: PathBuf,
+    /// Oldest first
+    entries: Vec<HistoryEntry>,
+    /// Position of each gid in `entries`
+    index: HashMap<String, usize>,
+    /// Most entries kept
+    limit: usize,
+}
+
+impl History {
+    /// Load the history at `path`, starting empty if the file does not exist
+    ///
+    /// An unreadable file is kept aside as `<name>.bak` rather than overwritten.
+    pub fn open(path: PathBuf) -> Result<Self> {
+        let entries: Vec<HistoryEntry> = match std::fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+                tracing::warn!("Failed to parse download history, starting over: {}", e);
+                let _ = std::fs::rename(&path, path.with_extension("json.bak"));
+                Vec::new()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let mut history = Self {
+            path,
+            entries,
+            index: HashMap::new(),
+            limit: MAX_ENTRIES,
+        };
+        history.prune();
+        Ok(history)
+    }
+
+    /// Keep at most `limit` entries, dropping the oldest finished ones first
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        self.prune();
+    }
+
+    /// Drop the oldest finished entries beyond the limit and rebuild the index
+    fn prune(&mut self) {
+        let mut excess = self.entries.len().saturating_sub(self.limit);
+        if excess > 0 {
+            // Tasks still in aria2 stay, however old
+            self.entries.retain(|entry| {
+                let drop = excess > 0 && is_stopped(entry.status);
+                excess -= drop as usize;
+                !drop
+            });
+        }
+        self.index = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| (entry.gid.clone(), i))
+            .collect();
+    }
+
+    /// Every entry, oldest first
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    pub fn get(&self, gid: &str) -> Option<&HistoryEntry> {
+        self.index.get(gid).map(|&i| &self.entries[i])
+    }
+
+    /// Record the current state of a task
+    ///
+    /// `options` are only used when the task is seen for the first time.
+    pub fn record(&mut self, task: &Task, options: Options, now: u64) {
+        match self.index.get(&task.gid) {
+            Some(&i) => self.entries[i].update(task, now),
+            None => {
+                self.index.insert(task.gid.clone(), self.entries.len());
+                self.entries.push(HistoryEntry::new(task, options, now));
+                if self.entries.len() > self.limit {
+                    self.prune();
+                }
+            }
+        }
+    }
+
+    /// Mark a task as removed when aria2 no longer knows it
+    pub fn mark_removed(&mut self, gid: &str, now: u64) {
+        if let Some(entry) = self.index.get(gid).map(|&i| &mut self.entries[i]) {
+            if !is_stopped(entry.status) {
+                entry.status = TaskStatus::Removed;
+                entry.finished_at = Some(now);
+            }
+        }
+    }
+
+    /// Record the checksum verification of a task, returning whether it is known
+    pub fn set_checksum(&mut self, gid: &str, status: ChecksumStatus) -> bool {
+        match self.index.get(gid).map(|&i| &mut self.entries[i]) {
+            Some(entry) => {
+                entry.checksum = Some(status);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Record the category a task was routed to, returning whether it is known
+    pub fn set_category(&mut self, gid: &str, category: TaskCategory) -> bool {
+        match self.index.get(gid).map(|&i| &mut self.entries[i]) {
+            Some(entry) => {
+                entry.category = Some(category);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Entries matching `query`, newest first
+    pub fn search(&self, query: &HistoryQuery) -> HistoryPage {
+        let matches: Vec<&HistoryEntry> = self.entries.iter().rev().filter(|entry| entry.matches(query)).collect();
+        HistoryPage {
+            total: matches.len(),
+            entries: matches
+                .into_iter()
+                .skip(query.offset)
+                .take(query.limit.unwrap_or(usize::MAX))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Write the history back to its file
+    pub fn save(&self) -> Result<()> {
+        write_file(&self.path, &serde_json::to_vec(&self.entries)?)
+    }
+
+    /// Write every entry to `path`
+    pub fn export(&self, path: &Path, format: ExportFormat) -> Result<()> {
+        export_entries(&self.entries, path, format)
+    }
+}
+
+/// Write `entries` to `path` in `format`
+fn export_entries(entries: &[HistoryEntry], path: &Path, format: ExportFormat) -> Result<()> {
+    let data = match format {
+        ExportFormat::Json => serde_json::to_vec_pretty(entries)?,
+        ExportFormat::Csv => to_csv(entries).into_bytes(),
+    };
+    std::fs::write(path, data)?;
+    Ok(())
+}
+
+/// Replace `path` with `data`
+pub(crate) fn write_file(path: &Path, data: &[u8]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    // Write to a temporary file first so a crash never leaves a truncated history
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, data)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Render entries as CSV, one row per entry
+fn to_csv(entries: &[HistoryEntry]) -> String {
+    fn field(value: &str) -> String {
+        if value.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    }
+
+    let mut csv = String::from(
+        "gid,name,status,totalLength,completedLength,dir,uris,infoHash,errorCode,errorMessage,addedAt,finishedAt\n",
+    );
+    for entry in entries {
+        let status = serde_json::to_value(entry.status)
+            .ok()
+            .and_then(|v| v.as_str().map(String::from))
+            .unwrap_or_default();
+        let row = [
+            entry.gid.clone(),
+            entry.name.clone(),
+            status,
+            entry.total_length.to_string(),
+            entry.completed_length.to_string(),
+            entry.dir.clone(),
+            entry.uris.join(" "),
+            entry.info_hash.clone().unwrap_or_default(),
+            entry.error_code.clone().unwrap_or_default(),
+            entry.error_message.clone().unwrap_or_default(),
+            entry.added_at.to_string(),
+            entry.finished_at.map(|t| t.to_string()).unwrap_or_default(),
+        ];
+        let row: Vec<String> = row.iter().map(|value| field(value)).collect();
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Current Unix time in milliseconds
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Load the history file used by the app
+pub async fn init(path: PathBuf) -> Result<()> {
+    let history = History::open(path)?;
+    tracing::info!("Loaded {} download history entries", history.entries().len());
+    *HISTORY.lock().await = Some(history);
+    if !SAVER.swap(true, Ordering::SeqCst) {
+        tauri::async_runtime::spawn(async {
+            loop {
+                SAVE.notified().await;
+                tokio::time::sleep(SAVE_DELAY).await;
+                if let Err(e) = flush().await {
+                    tracing::warn!("Failed to save download history: {}", e);
+                }
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Save the loaded history soon; later changes are written with it
+fn schedule_save() {
+    DIRTY.store(true, Ordering::SeqCst);
+    SAVE.notify_one();
+}
+
+/// Write unsaved changes of the loaded history now, e.g. before exiting
+pub async fn flush() -> Result<()> {
+    if !DIRTY.swap(false, Ordering::SeqCst) {
+        return Ok(());
+    }
+    // Serialize under the lock, write without it
+    let (path, data) = with_history(|history| (history.path.clone(), serde_json::to_vec(&history.entries))).await?;
+    let data = data?;
+    match tokio::task::spawn_blocking(move || write_file(&path, &data)).await {
+        Ok(result) => result,
+        Err(e) => Err(Error::Custom(format!("Saving the download history panicked: {}", e))),
+    }
+}
+
+/// Run `f` on the loaded history
+async fn with_history<T>(f: impl FnOnce(&mut History) -> T) -> Result<T> {
+    let mut guard = HISTORY.lock().await;
+    let history = guard
+        .as_mut()
+        .ok_or_else(|| Error::Custom("Download history not loaded".to_string()))?;
+    Ok(f(history))
+}
+
+/// Search the loaded history
+pub async fn search(query: &HistoryQuery) -> Result<HistoryPage> {
+    with_history(|history| history.search(query)).await
+}
+
+/// Export the loaded history to `path`
+///
+/// Only the copy of the entries is taken under the lock; rendering and
+/// writing happen on the blocking pool, like saves.
+pub async fn export(path: &Path, format: ExportFormat) -> Result<()> {
+    let entries = with_history(|history| history.entries().to_vec()).await?;
+    let path = path.to_path_buf();
+    match tokio::task::spawn_blocking(move || export_entries(&entries, &path, format)).await {
+        Ok(result) => result,
+        Err(e) => Err(Error::Custom(format!("Exporting the download history panicked: {}", e))),
+    }
+}
+
+/// Look up one entry of the loaded history
+pub async fn entry(gid: &str) -> Result<HistoryEntry> {
+    with_history(|history| history.get(gid).cloned())
+        .await?
+        .ok_or_else(|| Error::Validation(format!("no history entry for GID#{}", gid)))
+}
+
+/// Record the checksum verification of a task in the loaded history
+pub async fn record_checksum(gid: &str, status: ChecksumStatus) -> Result<()> {
+    with_history(|history| {
+        if !history.set_checksum(gid, status) {
+            return Err(Error::Validation(format!("no history entry for GID#{}", gid)));
+        }
+        Ok(())
+    })
+    .await??;
+    schedule_save();
+    Ok(())
+}
+
+/// Download a history entry again with its recorded options, returning the new gid
+pub async fn readd(client: &Aria2Client, entry: &HistoryEntry) -> Result<String> {
+    let uris = entry.download_uris();
+    if uris.is_empty() {
+        return Err(Error::Validation(format!("no URI recorded for {}", entry.name)));
+    }
+    let options = entry
+        .options
+        .iter()
+        .map(|(key, value)| (key.clone(), json!(value)))
+        .collect::<serde_json::Map<String, Value>>();
+    client.add_uri(uris, Some(Value::Object(options))).await
+}
+
+/// Task options that differ from the global ones, for every gid, in one round trip
+async fn custom_options(client: &Aria2Client, gids: &[&str]) -> Result<Vec<Options>> {
+    let mut calls = vec![("getGlobalOption", vec![])];
+    calls.extend(gids.iter().map(|gid| ("getOption", vec![json!(gid)])));
+    let mut results = client.multicall(calls).await?.into_iter();
+
+    let global: Options = match results.next() {
+        Some(Ok(value)) => serde_json::from_value(value).unwrap_or_default(),
+        _ => Options::new(),
+    };
+    Ok(results
+        .map(|result| {
+            let options: Options = result
+                .ok()
+                .and_then(|value| serde_json::from_value(value).ok())
+                .unwrap_or_default();
+            options
+                .into_iter()
+                .filter(|(key, value)| global.get(key) != Some(value))
+                .collect()
+        })
+        .collect())
+}
+
+/// Record tasks, fetching options for the ones not seen before, and schedule a save
+async fn record_tasks(client: &Aria2Client, tasks: Vec<Task>) -> Result<()> {
+    let unknown: Vec<String> = with_history(|history| {
+        tasks
+            .iter()
+            .filter(|task| history.get(&task.gid).is_none())
+            .map(|task| task.gid.clone())
+            .collect()
+    })
+    .await?;
+    let mut options: HashMap<&str, Options> = HashMap::new();
+    if !unknown.is_empty() {
+        let gids: Vec<&str> = unknown.iter().map(String::as_str).collect();
+        let fetched = custom_options(client, &gids).await.unwrap_or_default();
+        options.extend(gids.into_iter().zip(fetched));
+    }
+
+    let mut categories = HashMap::new();
+    for task in &tasks {
+        if let Some(category) = category::category_of(&task.gid).await {
+            categories.insert(task.gid.as_str(), category);
+        }
+    }
+
+    let now = now_millis();
+    with_history(|history| {
+        for task in &tasks {
+            history.record(task, options.remove(task.gid.as_str()).unwrap_or_default(), now);
+            if let Some(category) = categories.remove(task.gid.as_str()) {
+                history.set_category(&task.gid, category);
+            }
+        }
+    })
+    .await?;
+    schedule_save();
+    Ok(())
+}
+
+/// Record every task aria2 currently knows
+async fn record_all(client: &Aria2Client) -> Result<()> {
+    let stat = client.get_global_stat().await?;
+    let mut tasks = client.tell_active_and_waiting(stat.num_waiting as i32).await?;
+    tasks.extend(client.tell_stopped(0, stat.num_stopped as i32).await?);
+    record_tasks(client, tasks).await
+}
+
+/// Record a task after one of its notifications
+async fn record_event(client: &Aria2Client, gid: &str, event_type: &Aria2EventType) -> Result<()> {
+    match client.tell_status(gid).await {
+        Ok(task) => record_tasks(client, vec![task]).await,
+        // The record was purged right after stopping
+        Err(Error::Aria2Rpc { .. }) if matches!(event_type, Aria2EventType::DownloadStop) => {
+            with_history(|history| history.mark_removed(gid, now_millis())).await?;
+            schedule_save();
+            Ok(())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Record the client's tasks now and whenever they change, until the client is dropped
+pub fn spawn_recorder(client: &Arc<Aria2Client>) {
+    let weak: Weak<Aria2Client> = Arc::downgrade(client);
+    let mut events = client.subscribe();
+
+    tauri::async_runtime::spawn(async move {
+        if let Some(client) = weak.upgrade() {
+            if let Err(e) = record_all(&client).await {
+                tracing::warn!("Failed to record download history: {}", e);
+            }
+        }
+
+        loop {
+            let event = events.recv().await;
+            let Some(client) = weak.upgrade() else {
+                break;
+            };
+            let result = match event {
+                Ok(ClientEvent::Task(event)) => record_event(&client, &event.gid, &event.event_type).await,
+                // Catch up on anything missed while disconnected or lagging
+                Ok(ClientEvent::Connection(ConnectionState::Connected)) | Err(broadcast::error::RecvError::Lagged(_)) => {
+                    record_all(&client).await
+                }
+                Ok(ClientEvent::Connection(ConnectionState::Disconnected)) => Ok(()),
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+            if let Err(e) = result {
+                tracing::debug!("Failed to record download history: {}", e);
+            }
+        }
+    });
+}
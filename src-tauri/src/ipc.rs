@@ -0,0 +1,415 @@
+//! Local IPC with the running instance
+//!
+//! Companion binaries such as the native messaging host and `motrix-cli` talk
+//! to the running app over a Unix domain socket (a named pipe on Windows) that
+//! only the current user can reach. Each request is one line of JSON answered
+//! by one line of JSON.
+
+use crate::browser::{self, BrowserDownload};
+use crate::commands;
+use crate::duplicate::DuplicateAction;
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+
+/// Overrides the socket path, e.g. to run a second profile
+pub const SOCKET_ENV: &str = "MOTRIX_IPC_SOCKET";
+
+/// Longest request line accepted
+const MAX_LINE: usize = 4 * 1024 * 1024;
+
+/// A request to the running instance
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum IpcRequest {
+    /// Check the app is running
+    Ping,
+    /// Add a download captured by a browser
+    Download(BrowserDownload),
+    /// Add a download with task options, as `add_uri` does
+    #[serde(rename_all = "camelCase")]
+    Add {
+        uris: Vec<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        options: Option<Value>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        on_duplicate: Option<DuplicateAction>,
+    },
+    /// Tasks of a `get_task_list` type, `active` or `stopped`; all without one
+    List {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        status: Option<String>,
+    },
+    Status {
+        gid: String,
+    },
+    Pause {
+        gid: String,
+    },
+    Resume {
+        gid: String,
+    },
+    Remove {
+        gid: String,
+    },
+    Stat,
+    /// Overall speed limits in aria2 size syntax; unset ones are kept
+    Limit {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        download: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        upload: Option<String>,
+    },
+}
+
+/// The answer to an `IpcRequest`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IpcReply {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gid: Option<String>,
+    /// App version, answering `Ping`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Tasks, a task or stats, answering `List`, `Status` and `Stat`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    /// The failure as the commands serialize it, `{kind, code, message}`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<Value>,
+}
+
+impl From<Error> for IpcReply {
+    fn from(error: Error) -> Self {
+        Self {
+            error: Some(json!(error)),
+            ..Self::default()
+        }
+    }
+}
+
+impl IpcReply {
+    fn gid(gid: String) -> Self {
+        Self {
+            gid: Some(gid),
+            ..Self::default()
+        }
+    }
+
+    fn result(result: impl Serialize) -> Result<Self> {
+        Ok(Self {
+            result: Some(serde_json::to_value(result)?),
+            ..Self::default()
+        })
+    }
+}
+
+/// Where the running instance listens
+pub fn socket_path() -> PathBuf {
+    if let Some(path) = std::env::var_os(SOCKET_ENV) {
+        return PathBuf::from(path);
+    }
+    #[cfg(windows)]
+    {
+        // Pipe names are machine-wide, so each user gets their own
+        let user = pipe::user_sid().unwrap_or_else(|e| {
+            tracing::warn!("Failed to read the user SID: {}", e);
+            std::env::var("USERNAME").unwrap_or_default()
+        });
+        PathBuf::from(format!(r"\\.\pipe\motrix-{}", user))
+    }
+    #[cfg(unix)]
+    {
+        // The runtime dir is private to the user; without one (e.g. on macOS)
+        // the socket goes in a directory of the user's own
+        dirs::runtime_dir().unwrap_or_else(private_dir).join("motrix.sock")
+    }
+}
+
+/// The user's own directory in the shared temp dir
+#[cfg(unix)]
+fn private_dir() -> PathBuf {
+    // SAFETY: getuid cannot fail
+    let uid = unsafe { libc::getuid() };
+    std::env::temp_dir().join(format!("motrix-{}", uid))
+}
+
+/// Create `private_dir`, refusing one another user could reach
+#[cfg(unix)]
+fn create_private_dir(dir: &Path) -> Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+
+    if let Err(e) = std::fs::DirBuilder::new().mode(0o700).create(dir) {
+        if e.kind() != std::io::ErrorKind::AlreadyExists {
+            return Err(e.into());
+        }
+    }
+    // Anyone can create it first in the shared temp dir
+    let metadata = std::fs::symlink_metadata(dir)?;
+    // SAFETY: getuid cannot fail
+    if !metadata.is_dir() || metadata.uid() != unsafe { libc::getuid() } {
+        return Err(Error::Custom(format!("{} belongs to another user", dir.display())));
+    }
+    if metadata.mode() & 0o077 != 0 {
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(())
+}
+
+/// Named pipes only the current user can open
+#[cfg(windows)]
+mod pipe {
+    use std::ffi::c_void;
+    use std::io;
+    use std::path::Path;
+    use std::ptr::null_mut;
+    use tokio::net::windows::named_pipe::{NamedPipeServer, ServerOptions};
+    use windows_sys::Win32::Foundation::{CloseHandle, LocalFree, HANDLE};
+    use windows_sys::Win32::Security::Authorization::{
+        ConvertSidToStringSidW, ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1,
+    };
+    use windows_sys::Win32::Security::{
+        GetTokenInformation, TokenUser, PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES, TOKEN_QUERY, TOKEN_USER,
+    };
+    use windows_sys::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+    /// The SID of the user running the app, e.g. `S-1-5-21-…`
+    pub fn user_sid() -> io::Result<String> {
+        // SAFETY: the token is closed and the SID string freed before returning,
+        // and the buffer is sized and aligned for the TOKEN_USER written to it
+        unsafe {
+            let mut token: HANDLE = null_mut();
+            if OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut size = 0;
+            GetTokenInformation(token, TokenUser, null_mut(), 0, &mut size);
+            let mut buf = vec![0usize; size as usize / std::mem::size_of::<usize>() + 1];
+            let read = GetTokenInformation(token, TokenUser, buf.as_mut_ptr().cast(), size, &mut size);
+            let error = io::Error::last_os_error();
+            CloseHandle(token);
+            if read == 0 {
+                return Err(error);
+            }
+            let user = &*(buf.as_ptr() as *const TOKEN_USER);
+            let mut text = null_mut();
+            if ConvertSidToStringSidW(user.User.Sid, &mut text) == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let len = (0..).take_while(|&i| *text.add(i) != 0).count();
+            let sid = String::from_utf16_lossy(std::slice::from_raw_parts(text, len));
+            LocalFree(text.cast());
+            Ok(sid)
+        }
+    }
+
+    /// Create an instance of the pipe that only the user `sid` can open
+    pub fn create(path: &Path, sid: &str, first: bool) -> io::Result<NamedPipeServer> {
+        // A protected DACL with a single entry, so nothing is inherited
+        let sddl: Vec<u16> = format!("D:P(A;;GA;;;{})", sid).encode_utf16().chain(Some(0)).collect();
+        // SAFETY: the descriptor outlives the call that copies it to the pipe
+        unsafe {
+            let mut descriptor: PSECURITY_DESCRIPTOR = null_mut();
+            if ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                sddl.as_ptr(),
+                SDDL_REVISION_1,
+                &mut descriptor,
+                null_mut(),
+            ) == 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+            let mut attributes = SECURITY_ATTRIBUTES {
+                nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+                lpSecurityDescriptor: descriptor,
+                bInheritHandle: 0,
+            };
+            let server = ServerOptions::new()
+                .first_pipe_instance(first)
+                .create_with_security_attributes_raw(path, &mut attributes as *mut SECURITY_ATTRIBUTES as *mut c_void);
+            LocalFree(descriptor);
+            server
+        }
+    }
+}
+
+/// Act on one request through the commands, so it behaves as in the app
+async fn run(request: IpcRequest) -> Result<IpcReply> {
+    match request {
+        IpcRequest::Ping => Ok(IpcReply {
+            version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            ..IpcReply::default()
+        }),
+        IpcRequest::Download(download) => browser::add(download).await.map(IpcReply::gid),
+        IpcRequest::Add {
+            uris,
+            options,
+            on_duplicate,
+        } => commands::add_uri(uris, options, None, on_duplicate).await.map(IpcReply::gid),
+        IpcRequest::List { status } => {
+            let tasks = match status {
+                Some(status) => commands::get_task_list(status).await?,
+                None => {
+                    let mut tasks = commands::get_task_list("active".to_string()).await?;
+                    tasks.extend(commands::get_task_list("stopped".to_string()).await?);
+                    tasks
+                }
+            };
+            IpcReply::result(tasks)
+        }
+        IpcRequest::Status { gid } => IpcReply::result(commands::get_task_info(gid, None).await?),
+        IpcRequest::Pause { gid } => commands::pause_task(gid).await.map(IpcReply::gid),
+        IpcRequest::Resume { gid } => commands::resume_task(gid).await.map(IpcReply::gid),
+        IpcRequest::Remove { gid } => commands::remove_task(gid).await.map(IpcReply::gid),
+        IpcRequest::Stat => IpcReply::result(commands::get_global_stat().await?),
+        IpcRequest::Limit { download, upload } => {
+            let mut options = serde_json::Map::new();
+            if let Some(limit) = download {
+                options.insert("max-overall-download-limit".to_string(), json!(limit));
+            }
+            if let Some(limit) = upload {
+                options.insert("max-overall-upload-limit".to_string(), json!(limit));
+            }
+            if options.is_empty() {
+                return Err(Error::Validation("No limit given".to_string()));
+            }
+            commands::change_global_option(Value::Object(options)).await?;
+            Ok(IpcReply::default())
+        }
+    }
+}
+
+/// Act on one request
+pub async fn dispatch(request: IpcRequest) -> IpcReply {
+    run(request).await.unwrap_or_else(IpcReply::from)
+}
+
+/// Answer the requests of one connection until it closes
+async fn handle_stream<S: AsyncRead + AsyncWrite + Unpin>(stream: S) -> std::io::Result<()> {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut reader = BufReader::new(reader);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        // Reading stops past the limit, so a client cannot make the line grow without end
+        let read = (&mut reader).take(MAX_LINE as u64 + 1).read_line(&mut line).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        let too_large = read > MAX_LINE;
+        let reply = if too_large {
+            Error::Validation("Request too large".to_string()).into()
+        } else {
+            match serde_json::from_str::<IpcRequest>(&line) {
+                Ok(request) => dispatch(request).await,
+                Err(e) => Error::from(e).into(),
+            }
+        };
+        let mut reply = serde_json::to_string(&reply)?;
+        reply.push('\n');
+        writer.write_all(reply.as_bytes()).await?;
+        writer.flush().await?;
+        // The rest of the oversized line would be read as requests of its own
+        if too_large {
+            return Ok(());
+        }
+    }
+}
+
+fn spawn_handler<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(stream: S) {
+    tokio::spawn(async move {
+        if let Err(e) = handle_stream(stream).await {
+            tracing::debug!("IPC connection failed: {}", e);
+        }
+    });
+}
+
+/// Listen on `path` until the app exits
+#[cfg(unix)]
+pub async fn serve(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    use tokio::net::{UnixListener, UnixStream};
+
+    if path.exists() {
+        if UnixStream::connect(path).await.is_ok() {
+            return Err(Error::Custom(format!("Another instance is listening on {}", path.display())));
+        }
+        // Left behind by an instance that did not exit cleanly
+        std::fs::remove_file(path)?;
+    }
+    match path.parent() {
+        Some(dir) if dir == private_dir() => create_private_dir(dir)?,
+        Some(dir) => std::fs::create_dir_all(dir)?,
+        None => {}
+    }
+    let listener = UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    tracing::info!("IPC listening on {}", path.display());
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => spawn_handler(stream),
+            Err(e) => {
+                tracing::warn!("IPC failed to accept: {}", e);
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            }
+        }
+    }
+}
+
+/// Listen on the pipe named `path` until the app exits
+#[cfg(windows)]
+pub async fn serve(path: &Path) -> Result<()> {
+    let sid = pipe::user_sid()?;
+    let mut server = pipe::create(path, &sid, true)?;
+    tracing::info!("IPC listening on {}", path.display());
+    loop {
+        let connected = server.connect().await;
+        // A fresh instance takes the next client while this one is served
+        let next = loop {
+            match pipe::create(path, &sid, false) {
+                Ok(next) => break next,
+                Err(e) => {
+                    tracing::warn!("IPC failed to create a pipe instance: {}", e);
+                    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                }
+            }
+        };
+        let served = std::mem::replace(&mut server, next);
+        match connected {
+            Ok(()) => spawn_handler(served),
+            // Only this client is lost; the broken instance is dropped
+            Err(e) => tracing::warn!("IPC failed to accept: {}", e),
+        }
+    }
+}
+
+/// Send one request to the instance listening on `path`
+pub async fn request_at(path: &Path, request: &IpcRequest) -> Result<IpcReply> {
+    let not_running = |e: std::io::Error| Error::Disconnected(format!("Motrix is not running ({})", e));
+    #[cfg(unix)]
+    let stream = tokio::net::UnixStream::connect(path).await.map_err(not_running)?;
+    #[cfg(windows)]
+    let stream = tokio::net::windows::named_pipe::ClientOptions::new()
+        .open(path)
+        .map_err(not_running)?;
+
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut line = serde_json::to_string(request)?;
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await?;
+    writer.flush().await?;
+
+    let mut reply = String::new();
+    BufReader::new(reader).read_line(&mut reply).await?;
+    if reply.is_empty() {
+        return Err(Error::Disconnected("Motrix closed the connection".to_string()));
+    }
+    Ok(serde_json::from_str(&reply)?)
+}
+
+/// Send one request to the running instance
+pub async fn request(request: &IpcRequest) -> Result<IpcReply> {
+    request_at(&socket_path(), request).await
+}
@@ -2,11 +2,22 @@
 // Built with Tauri + Vue 3
 
 pub mod aria2;
+pub mod browser;
+pub mod category;
+pub mod checksum;
 pub mod cli;
 pub mod commands;
 pub mod config;
+pub mod control;
+pub mod deep_link;
+pub mod duplicate;
 pub mod error;
+pub mod handoff;
+pub mod history;
+pub mod ipc;
+pub mod native_host;
 pub mod power;
+pub mod schedule;
 pub mod tray;
 
 pub use error::{Error, Result};
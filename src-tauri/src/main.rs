@@ -3,8 +3,8 @@
 
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use motrix_lib::{aria2, cli, commands, tray};
-use tauri::{Emitter, Manager};
+use motrix_lib::{aria2, checksum, cli, commands, deep_link, handoff, history, ipc, schedule, tray};
+use tauri::Manager;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 fn main() {
@@ -20,21 +20,22 @@ fn main() {
     tracing::info!("Starting Motrix...");
 
     tauri::Builder::default()
-        .plugin(tauri_plugin_single_instance::init(|app, argv, _cwd| {
+        .plugin(tauri_plugin_single_instance::init(|app, argv, cwd| {
             tracing::info!("Second instance detected with argv: {:?}", argv);
 
+            // Relative paths are the second instance's, not ours
+            let request = cli::parse_args(&argv, std::path::Path::new(&cwd));
+
             // Focus existing window
-            if let Some(window) = app.get_webview_window("main") {
-                let _ = window.show();
-                let _ = window.set_focus();
-                let _ = window.unminimize();
+            if !request.hidden {
+                if let Some(window) = app.get_webview_window("main") {
+                    let _ = window.show();
+                    let _ = window.set_focus();
+                    let _ = window.unminimize();
+                }
             }
 
-            // Pass argv URLs/files to frontend
-            let urls = cli::parse_args(&argv);
-            if !urls.is_empty() {
-                let _ = app.emit("open-urls", &urls);
-            }
+            handoff::push(app, request);
         }))
         .plugin(tauri_plugin_deep_link::init())
         .plugin(tauri_plugin_autostart::init(tauri_plugin_autostart::MacosLauncher::LaunchAgent, None))
@@ -52,6 +53,11 @@ fn main() {
             // Initialize tray
             tray::create_tray(app)?;
 
+            // Parse command line arguments from first launch
+            let args: Vec<String> = std::env::args().collect();
+            let cwd = std::env::current_dir().unwrap_or_default();
+            let request = cli::parse_args(&args, &cwd);
+
             // Hide window on startup if configured
             {
                 use tauri_plugin_store::StoreExt;
@@ -62,7 +68,7 @@ fn main() {
                     .and_then(|v| v.get("startHidden").and_then(|v| v.as_bool()))
                     .unwrap_or(false);
 
-                if start_hidden {
+                if start_hidden || request.hidden {
                     if let Some(window) = app.get_webview_window("main") {
                         let _ = window.hide();
                         tracing::info!("Window hidden on startup (startHidden=true)");
@@ -70,23 +76,53 @@ fn main() {
                 }
             }
 
-            // Parse command line arguments from first launch
-            let args: Vec<String> = std::env::args().collect();
-            let urls = cli::parse_args(&args);
-            if !urls.is_empty() {
-                let app_handle_cli = app.handle().clone();
-                let urls_clone = urls.clone();
-                tauri::async_runtime::spawn(async move {
-                    // Delay to wait for frontend to be ready
-                    tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
-                    let _ = app_handle_cli.emit("open-urls", &urls_clone);
+            // Held until the frontend and the engine are ready
+            if request != cli::LaunchRequest::default() {
+                handoff::push(app.handle(), request);
+            }
+
+            // motrix:// links opened while running; on Linux and Windows they
+            // arrive as the command line of a second instance instead
+            {
+                use tauri_plugin_deep_link::DeepLinkExt;
+                let app_handle_link = app.handle().clone();
+                app.deep_link().on_open_url(move |event| {
+                    for url in event.urls() {
+                        deep_link::open(&app_handle_link, url.as_str());
+                    }
                 });
             }
 
+            // Accept downloads from the native messaging host
+            tauri::async_runtime::spawn(async {
+                if let Err(e) = ipc::serve(&ipc::socket_path()).await {
+                    tracing::error!("Failed to start IPC: {}", e);
+                }
+            });
+
+            // Apply the speed schedule once the engine is up
+            schedule::spawn(app.handle().clone());
+
             // Initialize aria2 engine
             let app_handle = app.handle().clone();
             tauri::async_runtime::spawn(async move {
-                if let Err(e) = aria2::init_engine(&app_handle).await {
+                // Load the download history before the engine reports tasks
+                match app_handle.path().app_data_dir() {
+                    Ok(dir) => {
+                        if let Err(e) = history::init(dir.join("history.json")).await {
+                            tracing::error!("Failed to load download history: {}", e);
+                        }
+                        if let Err(e) = checksum::init(dir.join("checksums.json")).await {
+                            tracing::error!("Failed to load pending checksums: {}", e);
+                        }
+                    }
+                    Err(e) => tracing::error!("Failed to get app data dir: {}", e),
+                }
+
+                let engine = aria2::init_engine(&app_handle).await;
+                // Held requests go out either way; adding reports a missing engine itself
+                handoff::engine_ready(&app_handle);
+                if let Err(e) = engine {
                     tracing::error!("Failed to initialize aria2 engine: {}", e);
                     return;
                 }
@@ -103,7 +139,8 @@ fn main() {
 
                     if should_resume {
                         if let Ok(client) = aria2::get_client().await {
-                            match client.unpause_all().await {
+                            // Tasks outside the download window are held until it opens
+                            match schedule::resume_on_launch(&app_handle, &client).await {
                                 Ok(_) => tracing::info!("Auto-resumed all tasks on launch"),
                                 Err(e) => tracing::warn!("Failed to auto-resume tasks: {}", e),
                             }
@@ -117,6 +154,7 @@ fn main() {
         .invoke_handler(tauri::generate_handler![
             commands::get_app_config,
             commands::save_app_config,
+            commands::frontend_ready,
             commands::add_uri,
             commands::add_torrent,
             commands::add_torrent_file,
@@ -125,14 +163,27 @@ fn main() {
             commands::pause_task,
             commands::resume_task,
             commands::remove_task,
+            commands::pause_tasks,
+            commands::resume_tasks,
+            commands::remove_tasks,
             commands::get_task_list,
+            commands::query_tasks,
+            commands::get_task_snapshot,
+            commands::refresh_tasks,
             commands::get_task_info,
+            commands::get_task_uris,
+            commands::get_task_files,
+            commands::get_task_servers,
+            commands::change_task_uri,
+            commands::get_task_option,
             commands::get_global_stat,
             commands::change_global_option,
+            commands::get_global_option,
             commands::shutdown_engine,
             commands::pause_all_tasks,
             commands::resume_all_tasks,
             commands::remove_task_record,
+            commands::remove_task_records,
             commands::purge_task_records,
             commands::open_file,
             commands::show_in_folder,
@@ -140,6 +191,9 @@ fn main() {
             commands::force_pause_task,
             commands::force_remove_task,
             commands::get_engine_version,
+            commands::get_session_info,
+            commands::list_engine_methods,
+            commands::list_engine_notifications,
             commands::get_task_peers,
             commands::change_task_option,
             commands::fetch_tracker_list,
@@ -149,6 +203,15 @@ fn main() {
             commands::prevent_sleep,
             commands::allow_sleep,
             commands::change_task_position,
+            commands::search_history,
+            commands::export_history,
+            commands::readd_from_history,
+            commands::get_speed_profile,
+            commands::override_speed_profile,
+            commands::clear_speed_override,
+            commands::get_download_window_state,
+            commands::set_task_ignore_schedule,
+            commands::get_task_categories,
         ])
         .on_window_event(|window, event| {
             if let tauri::WindowEvent::CloseRequested { api, .. } = event {
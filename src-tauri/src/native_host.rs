@@ -0,0 +1,184 @@
+//! Native messaging host for browser extensions
+//!
+//! Chrome and Firefox start the `motrix-native-host` binary and exchange
+//! messages with it over stdio: each one is JSON preceded by its length as a
+//! native-endian `u32`. Requests are `IpcRequest`s, e.g.
+//! `{"type": "download", "url": ..., "cookies": ...}`, and are forwarded to
+//! the running app over IPC; the `IpcReply` goes back to the extension.
+//!
+//! Browsers find the host through a manifest in their profile directories,
+//! which `install` writes for the browsers found on Linux.
+
+use crate::cli;
+use crate::ipc::{self, IpcReply, IpcRequest};
+use crate::{Error, Result};
+use serde_json::{json, Value};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Name extensions connect to with `runtime.connectNative`
+pub const HOST_NAME: &str = "app.motrix.native_host";
+
+/// Largest message accepted from the browser
+const MAX_MESSAGE: usize = 4 * 1024 * 1024;
+
+/// Browsers a host manifest is written for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Browser {
+    Chrome,
+    Chromium,
+    Brave,
+    Edge,
+    Vivaldi,
+    Firefox,
+}
+
+impl Browser {
+    pub const ALL: [Browser; 6] = [
+        Browser::Chrome,
+        Browser::Chromium,
+        Browser::Brave,
+        Browser::Edge,
+        Browser::Vivaldi,
+        Browser::Firefox,
+    ];
+
+    /// Profile root relative to the home directory on Linux
+    fn profile_root(self) -> &'static str {
+        match self {
+            Browser::Chrome => ".config/google-chrome",
+            Browser::Chromium => ".config/chromium",
+            Browser::Brave => ".config/BraveSoftware/Brave-Browser",
+            Browser::Edge => ".config/microsoft-edge",
+            Browser::Vivaldi => ".config/vivaldi",
+            Browser::Firefox => ".mozilla",
+        }
+    }
+
+    /// Directory the host manifest goes in, if the browser has a profile under `home`
+    pub fn manifest_dir(self, home: &Path) -> Option<PathBuf> {
+        let root = home.join(self.profile_root());
+        let hosts = match self {
+            Browser::Firefox => "native-messaging-hosts",
+            _ => "NativeMessagingHosts",
+        };
+        root.is_dir().then(|| root.join(hosts))
+    }
+}
+
+/// Extensions allowed to talk to the host
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExtensionIds {
+    /// Chromium extension IDs, e.g. `abcdefghijklmnopabcdefghijklmnop`
+    pub chromium: Vec<String>,
+    /// Firefox add-on IDs, e.g. `motrix@example.com`
+    pub firefox: Vec<String>,
+}
+
+/// The host manifest for `browser`, or `None` when no extension of it is allowed
+pub fn manifest(browser: Browser, host: &Path, ids: &ExtensionIds) -> Option<Value> {
+    let mut manifest = json!({
+        "name": HOST_NAME,
+        "description": "Motrix download manager",
+        "path": host,
+        "type": "stdio",
+    });
+    if browser == Browser::Firefox {
+        if ids.firefox.is_empty() {
+            return None;
+        }
+        manifest["allowed_extensions"] = json!(ids.firefox);
+    } else {
+        if ids.chromium.is_empty() {
+            return None;
+        }
+        let origins: Vec<String> = ids.chromium.iter().map(|id| format!("chrome-extension://{}/", id)).collect();
+        manifest["allowed_origins"] = json!(origins);
+    }
+    Some(manifest)
+}
+
+/// Write the host manifest for every browser with a profile under `home`
+///
+/// Returns the manifests written.
+pub fn install(home: &Path, host: &Path, ids: &ExtensionIds) -> Result<Vec<PathBuf>> {
+    if !host.is_absolute() {
+        return Err(Error::Validation(format!("The host path must be absolute: {}", host.display())));
+    }
+    let mut written = Vec::new();
+    for browser in Browser::ALL {
+        let (Some(dir), Some(manifest)) = (browser.manifest_dir(home), manifest(browser, host, ids)) else {
+            continue;
+        };
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("{}.json", HOST_NAME));
+        std::fs::write(&path, serde_json::to_string_pretty(&manifest)?)?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// Remove the host manifests under `home`, returning those removed
+pub fn uninstall(home: &Path) -> Result<Vec<PathBuf>> {
+    let mut removed = Vec::new();
+    for browser in Browser::ALL {
+        let Some(dir) = browser.manifest_dir(home) else {
+            continue;
+        };
+        let path = dir.join(format!("{}.json", HOST_NAME));
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+            removed.push(path);
+        }
+    }
+    Ok(removed)
+}
+
+/// Read one message; `None` once the browser closed stdin
+pub fn read_message(reader: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = u32::from_ne_bytes(len) as usize;
+    if len > MAX_MESSAGE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Message of {} bytes is too large", len)));
+    }
+    let mut message = vec![0u8; len];
+    reader.read_exact(&mut message)?;
+    Ok(Some(message))
+}
+
+/// Write one message
+pub fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let message = serde_json::to_vec(message)?;
+    writer.write_all(&(message.len() as u32).to_ne_bytes())?;
+    writer.write_all(&message)?;
+    writer.flush()
+}
+
+/// Check a download like command line URLs are checked, decoding thunder:// and similar links
+///
+/// Only `Ping` and `Download` are passed on.
+fn validate(request: IpcRequest) -> Result<IpcRequest> {
+    match request {
+        IpcRequest::Download(mut download) => {
+            download.url = cli::decode_link(&download.url)?;
+            Ok(IpcRequest::Download(download))
+        }
+        IpcRequest::Ping => Ok(request),
+        // Extensions may add downloads, not control the app
+        _ => Err(Error::Validation("Only ping and download requests are accepted".to_string())),
+    }
+}
+
+/// Answer one message from the browser by asking the instance listening on `socket`
+pub async fn handle(message: &[u8], socket: &Path) -> Value {
+    let reply = match serde_json::from_slice::<IpcRequest>(message).map_err(Error::from).and_then(validate) {
+        Ok(request) => ipc::request_at(socket, &request).await.unwrap_or_else(IpcReply::from),
+        Err(e) => e.into(),
+    };
+    json!(reply)
+}
@@ -0,0 +1,393 @@
+//! Speed schedule and download windows
+//!
+//! Applies the speed profile of the weekly schedule to aria2's overall limits,
+//! checking at every minute boundary. A profile can also be forced for a while
+//! with `override_speed_profile`; the profile in effect is shown in the tray.
+//!
+//! Outside the download windows queued and running tasks are paused, and the
+//! tasks paused that way are resumed when a window opens. Tasks flagged
+//! "ignore schedule" are left alone.
+
+use crate::aria2::{self, Aria2Client, Aria2EventType, ClientEvent, ConnectionState, TaskStatus};
+use crate::config::{AppConfig, Weekday};
+use crate::{tray, Error, Result};
+use chrono::{Datelike, Local, Timelike};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+use tauri_plugin_store::StoreExt;
+use tokio::sync::{broadcast, MappedMutexGuard, Mutex, MutexGuard, Notify};
+
+/// Profile forced by the user, if any
+static OVERRIDE: Mutex<Option<SpeedOverride>> = Mutex::const_new(None);
+
+/// Wakes the scheduler when the override changes
+static WAKE: Notify = Notify::const_new();
+
+/// Tasks held or exempted by the download windows, loaded from the store on first use
+static WINDOW_STATE: Mutex<Option<WindowState>> = Mutex::const_new(None);
+
+/// Store key of `WindowState` in `config.json`
+const WINDOW_STATE_KEY: &str = "downloadWindowState";
+
+/// A profile forced regardless of the schedule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpeedOverride {
+    pub profile: String,
+    /// Unix time in milliseconds the override ends at; without one it lasts
+    /// until the scheduled profile changes
+    pub until: Option<u64>,
+    /// Profile the schedule had when the override started
+    pub scheduled: Option<String>,
+}
+
+impl SpeedOverride {
+    /// Whether the override still applies while the schedule has `scheduled`
+    pub fn is_active(&self, scheduled: Option<&str>, now: u64) -> bool {
+        match self.until {
+            Some(until) => now < until,
+            None => self.scheduled.as_deref() == scheduled,
+        }
+    }
+}
+
+/// Overall limits in effect
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpeedState {
+    /// Active profile; `None` means the overall limits from the settings
+    pub profile: Option<String>,
+    pub download_limit: String,
+    pub upload_limit: String,
+    /// Whether the profile was forced with `override_speed_profile`
+    pub overridden: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub until: Option<u64>,
+}
+
+/// Limits in effect at `minute` on `day`, given an active override
+pub fn effective(config: &AppConfig, day: Weekday, minute: u32, over: Option<&SpeedOverride>) -> SpeedState {
+    let forced = over.and_then(|over| Some((config.speed_schedule.profile(&over.profile)?, over.until)));
+    if let Some((profile, until)) = forced {
+        return SpeedState {
+            profile: Some(profile.name.clone()),
+            download_limit: profile.download_limit.clone(),
+            upload_limit: profile.upload_limit.clone(),
+            overridden: true,
+            until,
+        };
+    }
+    match config.speed_schedule.profile_at(day, minute) {
+        Some(profile) => SpeedState {
+            profile: Some(profile.name.clone()),
+            download_limit: profile.download_limit.clone(),
+            upload_limit: profile.upload_limit.clone(),
+            overridden: false,
+            until: None,
+        },
+        None => SpeedState {
+            profile: None,
+            download_limit: config.max_overall_download_limit.clone(),
+            upload_limit: config.max_overall_upload_limit.clone(),
+            overridden: false,
+            until: None,
+        },
+    }
+}
+
+/// Local weekday, minutes since midnight and Unix time in milliseconds
+fn now() -> (Weekday, u32, u64) {
+    let now = Local::now();
+    let day = match now.weekday() {
+        chrono::Weekday::Mon => Weekday::Mon,
+        chrono::Weekday::Tue => Weekday::Tue,
+        chrono::Weekday::Wed => Weekday::Wed,
+        chrono::Weekday::Thu => Weekday::Thu,
+        chrono::Weekday::Fri => Weekday::Fri,
+        chrono::Weekday::Sat => Weekday::Sat,
+        chrono::Weekday::Sun => Weekday::Sun,
+    };
+    (day, now.hour() * 60 + now.minute(), now.timestamp_millis().max(0) as u64)
+}
+
+/// Current limits, dropping an override that has run out
+async fn current(config: &AppConfig) -> SpeedState {
+    let (day, minute, now) = now();
+    let scheduled = config.speed_schedule.profile_at(day, minute).map(|profile| profile.name.as_str());
+    let mut over = OVERRIDE.lock().await;
+    if over.as_ref().is_some_and(|o| !o.is_active(scheduled, now)) {
+        *over = None;
+    }
+    effective(config, day, minute, over.as_ref())
+}
+
+/// Limits in effect right now
+pub async fn state(app: &AppHandle) -> Result<SpeedState> {
+    let config = aria2::load_config(app)?;
+    Ok(current(&config).await)
+}
+
+/// Force `profile` for `minutes`, or until the schedule moves on when `None`
+pub async fn set_override(app: &AppHandle, profile: String, minutes: Option<u64>) -> Result<SpeedState> {
+    let config = aria2::load_config(app)?;
+    if config.speed_schedule.profile(&profile).is_none() {
+        return Err(Error::Validation(format!("Unknown speed profile: {}", profile)));
+    }
+    let (day, minute, now) = now();
+    *OVERRIDE.lock().await = Some(SpeedOverride {
+        profile,
+        until: minutes.map(|minutes| now + minutes * 60_000),
+        scheduled: config.speed_schedule.profile_at(day, minute).map(|profile| profile.name.clone()),
+    });
+    reschedule();
+    Ok(current(&config).await)
+}
+
+/// Return to the schedule
+pub async fn clear_override() {
+    *OVERRIDE.lock().await = None;
+    reschedule();
+}
+
+/// Re-check the schedule now, e.g. after the config was saved
+pub fn reschedule() {
+    WAKE.notify_one();
+}
+
+/// Apply the limits in effect to the engine, returning them when something is active
+async fn apply(app: &AppHandle, applied: Option<&SpeedState>) -> Result<Option<SpeedState>> {
+    let config = aria2::load_config(app)?;
+    let state = current(&config).await;
+    let active = config.speed_schedule.enabled || state.overridden;
+    // With nothing scheduled or forced, leave the limits to the settings,
+    // restoring them once after the schedule was switched off
+    if !active && applied.is_none() {
+        return Ok(None);
+    }
+
+    let client = aria2::get_client().await?;
+    client
+        .change_global_option(json!({
+            "max-overall-download-limit": state.download_limit,
+            "max-overall-upload-limit": state.upload_limit,
+        }))
+        .await?;
+
+    if applied != Some(&state) {
+        tracing::info!(
+            "Speed profile {} (down {}, up {})",
+            state.profile.as_deref().unwrap_or("default"),
+            state.download_limit,
+            state.upload_limit
+        );
+        if let Err(e) = tray::set_speed_profile(app, state.profile.clone()) {
+            tracing::warn!("Failed to show speed profile in tray: {}", e);
+        }
+        let _ = app.emit("speed-profile", &state);
+    }
+    Ok(active.then_some(state))
+}
+
+/// Tasks the download windows act on, persisted across restarts
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WindowState {
+    /// Tasks flagged "ignore schedule"
+    pub ignored: Vec<String>,
+    /// Tasks paused because the window closed, resumed when it opens
+    pub held: Vec<String>,
+}
+
+/// Pause or resume tasks so the queue follows the download window
+///
+/// Returns whether `state` changed. Gids of tasks that left the queue are
+/// dropped from it.
+pub async fn enforce_window(client: &Aria2Client, open: bool, state: &mut WindowState) -> Result<bool> {
+    let before = state.clone();
+    let stat = client.get_global_stat().await?;
+    let tasks = client.tell_active_and_waiting(stat.num_waiting as i32).await?;
+    let status = |gid: &str| tasks.iter().find(|task| task.gid == gid).map(|task| task.status);
+    state.ignored.retain(|gid| status(gid).is_some());
+    state.held.retain(|gid| status(gid) == Some(TaskStatus::Paused));
+
+    // Held tasks that are due to run again: all of them once the window
+    // opens, otherwise those flagged since they were paused
+    let release: Vec<String> = state
+        .held
+        .iter()
+        .filter(|gid| open || state.ignored.contains(gid))
+        .cloned()
+        .collect();
+    for result in client.unpause_many(&release).await? {
+        match result.error {
+            Some(e) => tracing::warn!("Failed to resume {} for the download window: {}", result.gid, e),
+            None => state.held.retain(|gid| *gid != result.gid),
+        }
+    }
+
+    if !open {
+        let hold: Vec<String> = tasks
+            .iter()
+            .filter(|task| matches!(task.status, TaskStatus::Active | TaskStatus::Waiting))
+            .filter(|task| !state.ignored.contains(&task.gid))
+            .map(|task| task.gid.clone())
+            .collect();
+        for result in client.pause_many(&hold, false).await? {
+            match result.error {
+                Some(e) => tracing::warn!("Failed to pause {} outside the download window: {}", result.gid, e),
+                None => state.held.push(result.gid),
+            }
+        }
+    }
+    Ok(*state != before)
+}
+
+/// Lock the window state, loading it from the store on first use
+async fn lock_window_state(app: &AppHandle) -> Result<MappedMutexGuard<'static, WindowState>> {
+    let mut guard = WINDOW_STATE.lock().await;
+    if guard.is_none() {
+        let store = app.store("config.json")?;
+        *guard = Some(
+            store
+                .get(WINDOW_STATE_KEY)
+                .and_then(|value| serde_json::from_value(value).ok())
+                .unwrap_or_default(),
+        );
+    }
+    Ok(MutexGuard::map(guard, |state| state.get_or_insert_with(WindowState::default)))
+}
+
+fn save_window_state(app: &AppHandle, state: &WindowState) -> Result<()> {
+    let store = app.store("config.json")?;
+    store.set(WINDOW_STATE_KEY, serde_json::to_value(state)?);
+    store.save()?;
+    Ok(())
+}
+
+/// Tasks currently held or flagged "ignore schedule"
+pub async fn window_state(app: &AppHandle) -> Result<WindowState> {
+    Ok(lock_window_state(app).await?.clone())
+}
+
+/// Flag a task to run regardless of the download windows, or clear the flag
+pub async fn set_ignore_schedule(app: &AppHandle, gid: String, ignore: bool) -> Result<WindowState> {
+    let mut state = lock_window_state(app).await?;
+    state.ignored.retain(|g| *g != gid);
+    if ignore {
+        state.ignored.push(gid);
+    }
+    save_window_state(app, &state)?;
+    reschedule();
+    Ok(state.clone())
+}
+
+/// Resume tasks on launch (`resume_all_when_app_launched`) without breaking the window
+///
+/// Outside the window only tasks flagged "ignore schedule" are resumed; the
+/// rest are held so they start once the window opens.
+pub async fn resume_on_launch(app: &AppHandle, client: &Aria2Client) -> Result<()> {
+    let config = aria2::load_config(app)?;
+    let (day, minute, _) = now();
+    if config.download_windows.is_open(day, minute) {
+        client.unpause_all().await?;
+        return Ok(());
+    }
+
+    let mut state = lock_window_state(app).await?;
+    let stat = client.get_global_stat().await?;
+    let tasks = client.tell_active_and_waiting(stat.num_waiting as i32).await?;
+    let (ignored, held): (Vec<String>, Vec<String>) = tasks
+        .into_iter()
+        .filter(|task| task.status == TaskStatus::Paused)
+        .map(|task| task.gid)
+        .partition(|gid| state.ignored.contains(gid));
+    client.unpause_many(&ignored).await?;
+    for gid in held {
+        if !state.held.contains(&gid) {
+            state.held.push(gid);
+        }
+    }
+    save_window_state(app, &state)
+}
+
+/// Enforce the download window with the current config
+async fn apply_window(app: &AppHandle, client: &Aria2Client) -> Result<()> {
+    let config = aria2::load_config(app)?;
+    let (day, minute, _) = now();
+    let open = config.download_windows.is_open(day, minute);
+    let mut state = lock_window_state(app).await?;
+    // Nothing to do while tasks may run and none are waiting to be resumed
+    if open && state.held.is_empty() {
+        return Ok(());
+    }
+    if enforce_window(client, open, &mut *state).await? {
+        tracing::info!("Download window {}: {} task(s) held", if open { "open" } else { "closed" }, state.held.len());
+        save_window_state(app, &state)?;
+    }
+    Ok(())
+}
+
+/// Wait for the next task event of the client, or forever without one
+async fn next_event(events: &mut Option<broadcast::Receiver<ClientEvent>>) -> Option<ClientEvent> {
+    match events {
+        Some(receiver) => match receiver.recv().await {
+            Ok(event) => Some(event),
+            Err(broadcast::error::RecvError::Lagged(_)) => None,
+            Err(broadcast::error::RecvError::Closed) => {
+                *events = None;
+                None
+            }
+        },
+        None => std::future::pending().await,
+    }
+}
+
+/// Start applying the speed schedule and download windows
+///
+/// Limits and windows are re-applied every minute, which also restores them
+/// after an engine restart. Tasks that start outside the window are paused
+/// as soon as aria2 reports them.
+pub fn spawn(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut applied: Option<SpeedState> = None;
+        let mut subscribed: Weak<Aria2Client> = Weak::new();
+        let mut events: Option<broadcast::Receiver<ClientEvent>> = None;
+        let mut window_only = false;
+        loop {
+            if !window_only {
+                match apply(&app, applied.as_ref()).await {
+                    Ok(state) => applied = state,
+                    Err(e) => tracing::debug!("Failed to apply speed schedule: {}", e),
+                }
+            }
+            if let Ok(client) = aria2::get_client().await {
+                // Follow the engine's current client across restarts
+                if !std::ptr::eq(Arc::as_ptr(&client), subscribed.as_ptr()) {
+                    subscribed = Arc::downgrade(&client);
+                    events = Some(client.subscribe());
+                }
+                if let Err(e) = apply_window(&app, &client).await {
+                    tracing::debug!("Failed to apply download window: {}", e);
+                }
+            }
+
+            let wait = 60 - u64::from(Local::now().second().min(59));
+            let deadline = tokio::time::Instant::now() + Duration::from_secs(wait);
+            window_only = loop {
+                tokio::select! {
+                    _ = tokio::time::sleep_until(deadline) => break false,
+                    _ = WAKE.notified() => break false,
+                    event = next_event(&mut events) => match event {
+                        Some(ClientEvent::Task(event)) if matches!(event.event_type, Aria2EventType::DownloadStart) => {
+                            break true
+                        }
+                        Some(ClientEvent::Connection(ConnectionState::Connected)) => break false,
+                        _ => {}
+                    },
+                }
+            };
+        }
+    });
+}
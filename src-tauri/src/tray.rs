@@ -2,12 +2,19 @@
 
 use crate::aria2;
 use serde::Deserialize;
+use std::sync::Mutex;
 use tauri::{
     menu::{Menu, MenuItem},
     tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent},
     Manager, Runtime,
 };
 
+/// Labels of the current menu, kept so it can be rebuilt when the speed profile changes
+static LABELS: Mutex<Option<TrayLabels>> = Mutex::new(None);
+
+/// Name of the speed profile in effect, if any
+static SPEED_PROFILE: Mutex<Option<String>> = Mutex::new(None);
+
 /// Tray menu labels for i18n
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -16,15 +23,49 @@ pub struct TrayLabels {
     pub pause_all: String,
     pub resume_all: String,
     pub quit: String,
+    /// Current speed profile, with `{profile}` standing for its name
+    #[serde(default = "default_speed_profile_label")]
+    pub speed_profile: String,
 }
 
-pub fn create_tray<R: Runtime>(app: &tauri::App<R>) -> Result<(), tauri::Error> {
-    let show_i = MenuItem::with_id(app, "show", "Show Motrix", true, None::<&str>)?;
-    let pause_all_i = MenuItem::with_id(app, "pause_all", "Pause All", true, None::<&str>)?;
-    let resume_all_i = MenuItem::with_id(app, "resume_all", "Resume All", true, None::<&str>)?;
-    let quit_i = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
+fn default_speed_profile_label() -> String {
+    "Speed: {profile}".to_string()
+}
 
-    let menu = Menu::with_items(app, &[&show_i, &pause_all_i, &resume_all_i, &quit_i])?;
+impl Default for TrayLabels {
+    fn default() -> Self {
+        Self {
+            show: "Show Motrix".to_string(),
+            pause_all: "Pause All".to_string(),
+            resume_all: "Resume All".to_string(),
+            quit: "Quit".to_string(),
+            speed_profile: default_speed_profile_label(),
+        }
+    }
+}
+
+/// Build the tray menu from the current labels and speed profile
+fn build_menu<R: Runtime, M: Manager<R>>(app: &M) -> Result<Menu<R>, tauri::Error> {
+    let labels = LABELS.lock().unwrap().clone().unwrap_or_default();
+    let profile = SPEED_PROFILE.lock().unwrap().clone();
+
+    let show_i = MenuItem::with_id(app, "show", &labels.show, true, None::<&str>)?;
+    let pause_all_i = MenuItem::with_id(app, "pause_all", &labels.pause_all, true, None::<&str>)?;
+    let resume_all_i = MenuItem::with_id(app, "resume_all", &labels.resume_all, true, None::<&str>)?;
+    let quit_i = MenuItem::with_id(app, "quit", &labels.quit, true, None::<&str>)?;
+
+    match profile {
+        Some(profile) => {
+            let text = labels.speed_profile.replace("{profile}", &profile);
+            let profile_i = MenuItem::with_id(app, "speed_profile", text, false, None::<&str>)?;
+            Menu::with_items(app, &[&profile_i, &show_i, &pause_all_i, &resume_all_i, &quit_i])
+        }
+        None => Menu::with_items(app, &[&show_i, &pause_all_i, &resume_all_i, &quit_i]),
+    }
+}
+
+pub fn create_tray<R: Runtime>(app: &tauri::App<R>) -> Result<(), tauri::Error> {
+    let menu = build_menu(app)?;
 
     let _tray = TrayIconBuilder::with_id("main")
         .icon(app.default_window_icon().unwrap().clone())
@@ -96,15 +137,21 @@ pub fn create_tray<R: Runtime>(app: &tauri::App<R>) -> Result<(), tauri::Error>
 
 /// Update tray menu labels (called from frontend on locale change)
 pub fn update_tray_labels<R: Runtime>(app: &tauri::AppHandle<R>, labels: &TrayLabels) -> Result<(), tauri::Error> {
+    *LABELS.lock().unwrap() = Some(labels.clone());
     if let Some(tray) = app.tray_by_id("main") {
         // Rebuild menu with updated labels
-        let show_i = MenuItem::with_id(app, "show", &labels.show, true, None::<&str>)?;
-        let pause_all_i = MenuItem::with_id(app, "pause_all", &labels.pause_all, true, None::<&str>)?;
-        let resume_all_i = MenuItem::with_id(app, "resume_all", &labels.resume_all, true, None::<&str>)?;
-        let quit_i = MenuItem::with_id(app, "quit", &labels.quit, true, None::<&str>)?;
+        tray.set_menu(Some(build_menu(app)?))?;
+    }
+    Ok(())
+}
 
-        let menu = Menu::with_items(app, &[&show_i, &pause_all_i, &resume_all_i, &quit_i])?;
-        tray.set_menu(Some(menu))?;
+/// Show the speed profile in effect in the tray menu and tooltip (`None` hides it)
+pub fn set_speed_profile<R: Runtime>(app: &tauri::AppHandle<R>, profile: Option<String>) -> Result<(), tauri::Error> {
+    *SPEED_PROFILE.lock().unwrap() = profile.clone();
+    if let Some(tray) = app.tray_by_id("main") {
+        tray.set_menu(Some(build_menu(app)?))?;
+        let tooltip = profile.map_or_else(|| "Motrix".to_string(), |profile| format!("Motrix ({})", profile));
+        tray.set_tooltip(Some(tooltip))?;
     }
     Ok(())
 }
@@ -0,0 +1,468 @@
+//! Integration tests for `Aria2Client` against the mock aria2 server
+
+mod common;
+
+use common::mock_aria2::{MockAria2, MockTask};
+use common::{next_event, wait_for_connection, SECRET};
+use motrix_lib::aria2::{
+    Aria2Client, Aria2EventType, ClientEvent, ConnectionState, RpcEndpoint, TaskQuery, TaskSort, TaskStatus,
+};
+use motrix_lib::error::DownloadExit;
+use motrix_lib::Error;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+
+async fn ws_client(mock: &MockAria2) -> Aria2Client {
+    Aria2Client::new(mock.ws_endpoint()).await.expect("connect over WebSocket")
+}
+
+#[tokio::test]
+async fn add_uri_and_tell_status_decode_typed_task() {
+    let mock = MockAria2::start(SECRET).await;
+    let client = ws_client(&mock).await;
+
+    let gid = client
+        .add_uri(vec!["https://example.com/file.iso".into()], Some(json!({"dir": "/tmp/dl"})))
+        .await
+        .unwrap();
+    let task = client.tell_status(&gid).await.unwrap();
+
+    assert_eq!(task.gid, gid);
+    assert_eq!(task.status, TaskStatus::Active);
+    assert_eq!(task.total_length, 1024);
+    assert_eq!(task.dir, "/tmp/dl");
+    assert_eq!(task.files[0].path, "/tmp/dl/file.iso");
+    assert!(task.files[0].selected);
+}
+
+#[tokio::test]
+async fn tell_status_keys_keeps_gid_and_status() {
+    let mock = MockAria2::start(SECRET).await;
+    mock.insert_task(MockTask::new("0000000000000001", "paused", "https://example.com/a"));
+    let client = ws_client(&mock).await;
+
+    let task = client
+        .tell_status_keys("0000000000000001", &["totalLength".to_string()])
+        .await
+        .unwrap();
+    assert_eq!(task.status, TaskStatus::Paused);
+    assert_eq!(task.total_length, 1024);
+    assert!(task.files.is_empty());
+}
+
+#[tokio::test]
+async fn files_options_and_session_are_typed() {
+    let mock = MockAria2::start(SECRET).await;
+    let client = ws_client(&mock).await;
+    let gid = client
+        .add_uri(vec!["https://example.com/a.bin".into()], Some(json!({"split": "4"})))
+        .await
+        .unwrap();
+
+    let files = client.get_files(&gid).await.unwrap();
+    assert_eq!(files[0].index, 1);
+    assert_eq!(files[0].length, 1024);
+    assert_eq!(client.get_option(&gid).await.unwrap()["split"], "4");
+
+    client.change_global_option(json!({"max-concurrent-downloads": "3"})).await.unwrap();
+    assert_eq!(client.get_global_option().await.unwrap()["max-concurrent-downloads"], "3");
+    assert_eq!(client.get_session_info().await.unwrap().session_id.len(), 40);
+}
+
+#[tokio::test]
+async fn introspection_lists_methods_and_notifications() {
+    let mock = MockAria2::start(SECRET).await;
+    let client = ws_client(&mock).await;
+
+    assert!(client.list_methods().await.unwrap().contains(&"aria2.addUri".to_string()));
+    assert!(client
+        .list_notifications()
+        .await
+        .unwrap()
+        .contains(&"aria2.onDownloadComplete".to_string()));
+}
+
+#[tokio::test]
+async fn rpc_errors_carry_aria2_message() {
+    let mock = MockAria2::start(SECRET).await;
+    let client = ws_client(&mock).await;
+
+    let err = client.pause("ffffffffffffffff").await.unwrap_err();
+    assert!(err.to_string().contains("is not found"), "{}", err);
+
+    mock.fail_next("aria2.getGlobalStat", 7, "injected failure");
+    let err = client.get_global_stat().await.unwrap_err();
+    assert_eq!(err.kind(), "rpc");
+    assert_eq!(err.code(), Some(7));
+    assert!(err.to_string().contains("injected failure"), "{}", err);
+    assert!(client.get_global_stat().await.is_ok());
+}
+
+#[tokio::test]
+async fn errors_serialize_with_kind_and_code() {
+    let mock = MockAria2::start(SECRET).await;
+    let client = ws_client(&mock).await;
+
+    let err = client.unpause("ffffffffffffffff").await.unwrap_err();
+    let value = serde_json::to_value(&err).unwrap();
+    assert_eq!(value["kind"], "rpc");
+    assert_eq!(value["code"], 1);
+    assert!(value["message"].as_str().unwrap().contains("is not found"));
+
+    let err = client.add_uri(vec![], None).await.unwrap_err();
+    assert_eq!(
+        serde_json::to_value(&err).unwrap(),
+        json!({"kind": "validation", "code": null, "message": "Invalid input: no URI to download"})
+    );
+    assert!(mock.calls().iter().all(|call| call != "aria2.addUri"));
+}
+
+#[tokio::test]
+async fn failed_tasks_report_download_exit_code() {
+    let mock = MockAria2::start(SECRET).await;
+    let mut task = MockTask::new("0000000000000001", "error", "https://example.com/a");
+    task.error_code = Some("9".into());
+    mock.insert_task(task);
+    mock.insert_task(MockTask::new("0000000000000002", "active", "https://example.com/b"));
+    let client = ws_client(&mock).await;
+
+    let err = client.tell_status("0000000000000001").await.unwrap().error().unwrap();
+    assert_eq!(err.kind(), "download");
+    assert_eq!(err.code(), Some(9));
+    assert_eq!(err.download_exit(), Some(DownloadExit::DiskFull));
+    assert!(client.tell_status("0000000000000002").await.unwrap().error().is_none());
+}
+
+#[tokio::test]
+async fn wrong_secret_is_rejected() {
+    let mock = MockAria2::start(SECRET).await;
+    let endpoint = RpcEndpoint {
+        secret: "wrong".into(),
+        ..mock.ws_endpoint()
+    };
+    let client = Aria2Client::new(endpoint).await.unwrap();
+
+    let err = client.get_version().await.unwrap_err();
+    assert!(err.to_string().contains("Unauthorized"), "{}", err);
+}
+
+#[tokio::test]
+async fn malformed_result_is_reported() {
+    let mock = MockAria2::start(SECRET).await;
+    let client = ws_client(&mock).await;
+
+    mock.respond_with("aria2.tellStatus", json!({"status": "active"}));
+    let err = client.tell_status("0000000000000001").await.unwrap_err();
+    assert_eq!(err.kind(), "protocol");
+    assert!(err.to_string().contains("tellStatus"), "{}", err);
+}
+
+#[tokio::test]
+async fn notifications_are_published_as_task_events() {
+    let mock = MockAria2::start(SECRET).await;
+    let client = ws_client(&mock).await;
+    let mut events = client.subscribe();
+
+    mock.notify("aria2.onUnknownEvent", "0000000000000001");
+    mock.notify("aria2.onDownloadComplete", "0000000000000002");
+
+    let event = next_event(&mut events, |e| matches!(e, ClientEvent::Task(_))).await;
+    let ClientEvent::Task(event) = event else { unreachable!() };
+    assert_eq!(event.gid, "0000000000000002");
+    assert!(matches!(event.event_type, Aria2EventType::DownloadComplete));
+}
+
+#[tokio::test]
+async fn multicall_reports_each_result_separately() {
+    let mock = MockAria2::start(SECRET).await;
+    mock.insert_task(MockTask::new("0000000000000001", "active", "https://example.com/a"));
+    mock.insert_task(MockTask::new("0000000000000002", "complete", "https://example.com/b"));
+    let client = ws_client(&mock).await;
+
+    let gids = vec!["0000000000000001".to_string(), "0000000000000002".to_string()];
+    let results = client.pause_many(&gids, false).await.unwrap();
+    assert_eq!(results[0].gid, gids[0]);
+    assert!(results[0].error.is_none());
+    assert!(results[1].error.is_some());
+    assert_eq!(mock.task(&gids[0]).unwrap().status, "paused");
+
+    // Stopped tasks cannot be removed; purging their records is separate
+    let results = client.remove_many(&gids, false).await.unwrap();
+    assert!(results[0].error.is_none());
+    assert!(results[1].error.is_some());
+    assert_eq!(mock.task(&gids[0]).unwrap().status, "removed");
+    assert!(mock.task(&gids[1]).is_some());
+
+    let results = client.remove_download_results(&gids).await.unwrap();
+    assert!(results.iter().all(|r| r.error.is_none()), "{:?}", results);
+    assert!(mock.tasks().is_empty());
+}
+
+#[tokio::test]
+async fn tell_active_and_waiting_uses_one_round_trip() {
+    let mock = MockAria2::start(SECRET).await;
+    mock.insert_task(MockTask::new("0000000000000001", "active", "https://example.com/a"));
+    mock.insert_task(MockTask::new("0000000000000002", "waiting", "https://example.com/b"));
+    mock.insert_task(MockTask::new("0000000000000003", "complete", "https://example.com/c"));
+    let client = ws_client(&mock).await;
+    // Skip the calls made for the initial task snapshot
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    mock.clear_calls();
+
+    let tasks = client.tell_active_and_waiting(10).await.unwrap();
+    let gids: Vec<&str> = tasks.iter().map(|t| t.gid.as_str()).collect();
+    assert_eq!(gids, ["0000000000000001", "0000000000000002"]);
+    assert_eq!(mock.calls(), ["aria2.tellActive", "aria2.tellWaiting"]);
+}
+
+#[tokio::test]
+async fn unfiltered_queries_are_paged_by_aria2() {
+    let mock = MockAria2::start(SECRET).await;
+    mock.insert_task(MockTask::new("0000000000000001", "active", "https://example.com/a"));
+    for n in 2..=4 {
+        mock.insert_task(MockTask::new(&format!("{:016x}", n), "complete", "https://example.com/b"));
+    }
+    let client = ws_client(&mock).await;
+
+    let query = TaskQuery {
+        offset: 2,
+        limit: Some(2),
+        keys: Some(vec!["totalLength".into()]),
+        ..TaskQuery::default()
+    };
+    let page = client.query_tasks(&query).await.unwrap();
+    assert_eq!(page.total, 4);
+    let gids: Vec<&str> = page.tasks.iter().map(|t| t.gid.as_str()).collect();
+    assert_eq!(gids, ["0000000000000003", "0000000000000004"]);
+    // The projection reached aria2, so no files were sent
+    assert!(page.tasks.iter().all(|t| t.files.is_empty() && t.total_length == 1024));
+}
+
+#[tokio::test]
+async fn queries_filter_sort_and_project() {
+    let mock = MockAria2::start(SECRET).await;
+    for (n, (status, uri, speed)) in [
+        ("active", "https://example.com/movie.mkv", 10),
+        ("active", "https://example.com/song.mp3", 30),
+        ("paused", "https://mirror.example.com/Movie-extras.zip", 0),
+        ("complete", "https://example.com/movie-old.mkv", 0),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let mut task = MockTask::new(&format!("{:016x}", n + 1), status, uri);
+        task.download_speed = speed;
+        mock.insert_task(task);
+    }
+    let client = ws_client(&mock).await;
+
+    let query = TaskQuery {
+        status: vec![TaskStatus::Active, TaskStatus::Paused],
+        search: Some("MOVIE".into()),
+        sort: TaskSort::Speed,
+        descending: true,
+        keys: Some(vec!["downloadSpeed".into()]),
+        ..TaskQuery::default()
+    };
+    let page = client.query_tasks(&query).await.unwrap();
+    assert_eq!(page.total, 2);
+    let gids: Vec<&str> = page.tasks.iter().map(|t| t.gid.as_str()).collect();
+    assert_eq!(gids, ["0000000000000001", "0000000000000003"]);
+    // Files were only fetched for the search and are not returned
+    assert!(page.tasks.iter().all(|t| t.files.is_empty()));
+}
+
+#[tokio::test]
+async fn calls_time_out() {
+    let mock = MockAria2::start(SECRET).await;
+    mock.delay("aria2.getVersion", Duration::from_secs(2));
+    let client = ws_client(&mock).await.with_request_timeout(Duration::from_millis(200));
+
+    let err = client.get_version().await.unwrap_err();
+    assert!(matches!(err, Error::Timeout), "{}", err);
+}
+
+#[tokio::test]
+async fn disconnect_fails_pending_calls_and_reconnects() {
+    let mock = MockAria2::start(SECRET).await;
+    let client = Arc::new(ws_client(&mock).await);
+    let mut events = client.subscribe();
+
+    mock.delay("aria2.getVersion", Duration::from_secs(5));
+    let pending = tokio::spawn({
+        let client = client.clone();
+        async move { client.get_version().await }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    mock.delay("aria2.getVersion", Duration::ZERO);
+    mock.disconnect();
+
+    // The in-flight call is failed right away instead of waiting for the timeout
+    let err = tokio::time::timeout(Duration::from_secs(1), pending)
+        .await
+        .expect("pending call drained")
+        .unwrap()
+        .unwrap_err();
+    assert_eq!(err.kind(), "disconnected");
+
+    wait_for_connection(&mut events, ConnectionState::Disconnected).await;
+    wait_for_connection(&mut events, ConnectionState::Connected).await;
+    assert!(client.get_version().await.is_ok());
+}
+
+#[tokio::test]
+async fn graceful_close_triggers_reconnect() {
+    let mock = MockAria2::start(SECRET).await;
+    let client = ws_client(&mock).await;
+    let mut events = client.subscribe();
+
+    mock.close();
+
+    wait_for_connection(&mut events, ConnectionState::Disconnected).await;
+    wait_for_connection(&mut events, ConnectionState::Connected).await;
+    assert!(client.get_version().await.is_ok());
+}
+
+#[tokio::test]
+async fn calls_fail_fast_while_reconnecting() {
+    let mock = MockAria2::start(SECRET).await;
+    let client = ws_client(&mock).await;
+    let mut events = client.subscribe();
+
+    mock.set_offline(true);
+    mock.disconnect();
+    wait_for_connection(&mut events, ConnectionState::Disconnected).await;
+
+    let err = tokio::time::timeout(Duration::from_secs(1), client.get_version())
+        .await
+        .expect("call failed fast")
+        .unwrap_err();
+    assert!(err.to_string().contains("disconnected"), "{}", err);
+
+    // Keeps retrying past several failed attempts
+    tokio::time::sleep(Duration::from_secs(4)).await;
+    mock.set_offline(false);
+    wait_for_connection(&mut events, ConnectionState::Connected).await;
+    assert!(client.get_version().await.is_ok());
+}
+
+#[tokio::test]
+async fn missed_transitions_are_replayed_after_reconnect() {
+    let mock = MockAria2::start(SECRET).await;
+    mock.insert_task(MockTask::new("0000000000000001", "active", "https://example.com/a"));
+    mock.insert_task(MockTask::new("0000000000000002", "active", "https://example.com/b"));
+    let client = ws_client(&mock).await;
+    let mut events = client.subscribe();
+
+    // Let the tracker take its baseline, then change state while the socket is down
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    mock.set_offline(true);
+    mock.disconnect();
+    wait_for_connection(&mut events, ConnectionState::Disconnected).await;
+    mock.set_status("0000000000000001", "complete");
+    mock.set_status("0000000000000002", "error");
+    mock.set_offline(false);
+    wait_for_connection(&mut events, ConnectionState::Connected).await;
+
+    let mut replayed = Vec::new();
+    for _ in 0..2 {
+        let ClientEvent::Task(event) = next_event(&mut events, |e| matches!(e, ClientEvent::Task(_))).await else {
+            unreachable!()
+        };
+        replayed.push(event);
+    }
+    replayed.sort_by(|a, b| a.gid.cmp(&b.gid));
+    assert!(matches!(replayed[0].event_type, Aria2EventType::DownloadComplete));
+    assert!(matches!(replayed[1].event_type, Aria2EventType::DownloadError));
+}
+
+#[tokio::test]
+async fn notified_transitions_are_not_replayed() {
+    let mock = MockAria2::start(SECRET).await;
+    mock.insert_task(MockTask::new("0000000000000001", "active", "https://example.com/a"));
+    let client = ws_client(&mock).await;
+    let mut events = client.subscribe();
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    mock.set_status("0000000000000001", "complete");
+    mock.notify("aria2.onDownloadComplete", "0000000000000001");
+    next_event(&mut events, |e| matches!(e, ClientEvent::Task(_))).await;
+
+    mock.disconnect();
+    wait_for_connection(&mut events, ConnectionState::Connected).await;
+    // Give the tracker time to resync; nothing changed, so nothing is replayed
+    let replayed = tokio::time::timeout(Duration::from_millis(500), events.recv()).await;
+    assert!(replayed.is_err(), "unexpected event: {:?}", replayed);
+}
+
+#[tokio::test]
+async fn http_transport_round_trips_and_reports_errors() {
+    let mock = MockAria2::start(SECRET).await;
+    let client = Aria2Client::new(mock.http_endpoint()).await.expect("connect over HTTP");
+
+    let gid = client.add_uri(vec!["https://example.com/a".into()], None).await.unwrap();
+    assert_eq!(client.tell_status(&gid).await.unwrap().status, TaskStatus::Active);
+
+    let err = client.unpause(&gid).await.unwrap_err();
+    assert!(err.to_string().contains("cannot be unpaused"), "{}", err);
+}
+
+#[tokio::test]
+async fn http_transport_rejects_wrong_secret_up_front() {
+    let mock = MockAria2::start(SECRET).await;
+    let endpoint = RpcEndpoint {
+        secret: "wrong".into(),
+        ..mock.http_endpoint()
+    };
+    assert!(Aria2Client::new(endpoint).await.is_err());
+}
+
+#[tokio::test]
+async fn http_transport_synthesizes_events_from_polling() {
+    let mock = MockAria2::start(SECRET).await;
+    mock.insert_task(MockTask::new("0000000000000001", "active", "https://example.com/a"));
+    let client = Aria2Client::new(mock.http_endpoint()).await.unwrap();
+    let mut events = client.subscribe();
+
+    // Let the poller take its first snapshot before the status changes
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+    mock.set_status("0000000000000001", "complete");
+
+    let event = next_event(&mut events, |e| matches!(e, ClientEvent::Task(_))).await;
+    let ClientEvent::Task(event) = event else { unreachable!() };
+    assert_eq!(event.gid, "0000000000000001");
+    assert!(matches!(event.event_type, Aria2EventType::DownloadComplete));
+}
+
+#[tokio::test]
+async fn polling_sees_tasks_stopped_after_a_long_history() {
+    let mock = MockAria2::start(SECRET).await;
+    for i in 0..1100 {
+        mock.insert_task(MockTask::new(&format!("{:016x}", i + 0x100), "complete", "https://example.com/old"));
+    }
+    for i in 0..1100 {
+        mock.insert_task(MockTask::new(&format!("{:016x}", i + 0x1000), "waiting", "https://example.com/queued"));
+    }
+    // Listed last once stopped, as aria2 lists the most recently stopped task last
+    mock.insert_task(MockTask::new("0000000000000001", "active", "https://example.com/a"));
+    let client = Aria2Client::new(mock.http_endpoint()).await.unwrap();
+    let mut events = client.subscribe();
+
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+    mock.set_status("0000000000000001", "complete");
+    mock.set_status(&format!("{:016x}", 0x1000 + 1099), "paused");
+
+    let mut seen = Vec::new();
+    for _ in 0..2 {
+        let ClientEvent::Task(event) = next_event(&mut events, |e| matches!(e, ClientEvent::Task(_))).await else {
+            unreachable!()
+        };
+        seen.push((event.gid, event.event_type));
+    }
+    seen.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(seen[0].0, "0000000000000001");
+    assert!(matches!(seen[0].1, Aria2EventType::DownloadComplete));
+    assert_eq!(seen[1].0, format!("{:016x}", 0x1000 + 1099));
+    assert!(matches!(seen[1].1, Aria2EventType::DownloadPause));
+}
@@ -0,0 +1,196 @@
+//! Tests for the browser integration endpoint
+
+mod common;
+
+use common::mock_aria2::MockAria2;
+use common::SECRET;
+use motrix_lib::aria2::{self, Aria2Client};
+use motrix_lib::browser::{self, BrowserDownload};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+const TOKEN: &str = "browser-token";
+
+fn download(value: Value) -> BrowserDownload {
+    serde_json::from_value(value).unwrap()
+}
+
+/// A site answering one form POST with `answer`, and the request it received
+async fn form_site(answer: &'static str) -> (String, JoinHandle<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let request = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 4096];
+        // Every form in these tests sends `id=1`
+        while !request.ends_with(b"id=1") {
+            let n = stream.read(&mut buf).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            request.extend_from_slice(&buf[..n]);
+        }
+        stream.write_all(answer.as_bytes()).await.unwrap();
+        String::from_utf8_lossy(&request).to_lowercase()
+    });
+    (base, request)
+}
+
+#[test]
+fn the_browser_session_becomes_task_options() {
+    let options = download(json!({
+        "url": "https://example.com/files/report?id=1",
+        "cookies": [{"name": "session", "value": "abc"}, {"name": "lang", "value": "en"}],
+        "referer": "https://example.com/files",
+        "userAgent": "Mozilla/5.0",
+        "headers": {"Authorization": "Bearer xyz"},
+        "filename": "../../Report 2026.pdf",
+    }))
+    .options()
+    .unwrap();
+    assert_eq!(options["header"], json!(["Cookie: session=abc; lang=en", "Authorization: Bearer xyz"]));
+    assert_eq!(options["referer"], "https://example.com/files");
+    assert_eq!(options["user-agent"], "Mozilla/5.0");
+    assert_eq!(options["out"], "Report 2026.pdf");
+
+    let options = download(json!({"url": "https://example.com/a.zip", "cookies": "a=1; b=2"})).options().unwrap();
+    assert_eq!(options, json!({"header": ["Cookie: a=1; b=2"]}));
+    let options = download(json!({"url": "https://example.com/a.zip", "filename": ".."})).options().unwrap();
+    assert_eq!(options, json!({}));
+}
+
+#[test]
+fn unusable_downloads_are_rejected() {
+    let rejected = |value: Value| download(value).options().is_err();
+    assert!(rejected(json!({"url": "not a url"})));
+    assert!(rejected(json!({"url": "javascript:alert(1)"})));
+    assert!(rejected(json!({"url": "https://example.com/a", "cookies": "a=1\r\nX-Injected: 1"})));
+    assert!(rejected(json!({"url": "https://example.com/a", "headers": {"X-Bad:": "1"}})));
+}
+
+#[tokio::test]
+async fn form_posts_are_replayed_to_find_the_file() {
+    let (base, request) = form_site(concat!(
+        "HTTP/1.1 303 See Other\r\n",
+        "Location: /files/report.pdf?t=1\r\n",
+        "Set-Cookie: ticket=xyz; Path=/; HttpOnly\r\n",
+        "Set-Cookie: session=new; Path=/\r\n",
+        "Content-Length: 0\r\nConnection: close\r\n\r\n",
+    ))
+    .await;
+    let resolved = download(json!({
+        "url": format!("{}/export", base),
+        "cookies": "session=abc; lang=en",
+        "referer": "https://example.com/files",
+        "postData": "id=1",
+    }))
+    .resolve_post()
+    .await
+    .unwrap();
+    assert_eq!(resolved.url, format!("{}/files/report.pdf?t=1", base));
+    assert_eq!(resolved.post_data, None);
+    let options = resolved.options().unwrap();
+    assert_eq!(options["header"], json!(["Cookie: lang=en; ticket=xyz; session=new"]));
+    assert_eq!(options["referer"], "https://example.com/files");
+
+    let request = request.await.unwrap();
+    assert!(request.starts_with("post /export "), "{}", request);
+    assert!(request.contains("cookie: session=abc; lang=en\r\n"), "{}", request);
+    assert!(request.contains("content-type: application/x-www-form-urlencoded\r\n"), "{}", request);
+    assert!(request.ends_with("\r\n\r\nid=1"), "{}", request);
+
+    // The page's cookies stay with its own host, only the answer's cookies follow a redirect elsewhere
+    let (base, _) = form_site(concat!(
+        "HTTP/1.1 302 Found\r\n",
+        "Location: https://cdn.example.net/report.pdf\r\n",
+        "Set-Cookie: ticket=xyz; Path=/\r\n",
+        "Content-Length: 0\r\nConnection: close\r\n\r\n",
+    ))
+    .await;
+    let form = json!({"url": format!("{}/export", base), "cookies": "session=abc", "postData": "id=1"});
+    let resolved = download(form).resolve_post().await.unwrap();
+    assert_eq!(resolved.url, "https://cdn.example.net/report.pdf");
+    assert_eq!(resolved.options().unwrap()["header"], json!(["Cookie: ticket=xyz"]));
+    // Nor do they when the answer sets none
+    let (base, _) =
+        form_site("HTTP/1.1 302 Found\r\nLocation: http://127.0.0.2/a.pdf\r\nContent-Length: 0\r\n\r\n").await;
+    let form = json!({"url": format!("{}/export", base), "cookies": "session=abc", "postData": "id=1"});
+    let resolved = download(form).resolve_post().await.unwrap();
+    assert_eq!(resolved.options().unwrap().get("header"), None);
+
+    // aria2 cannot download a file the form answers with directly
+    let (base, _) = form_site("HTTP/1.1 200 OK\r\nContent-Length: 4\r\nConnection: close\r\n\r\ndata").await;
+    let form = download(json!({"url": format!("{}/export", base), "postData": "id=1"}));
+    assert_eq!(form.resolve_post().await.unwrap_err().kind(), "unsupported");
+    let form = download(json!({"url": "ftp://example.com/export", "postData": "id=1"}));
+    assert_eq!(form.resolve_post().await.unwrap_err().kind(), "validation");
+}
+
+#[tokio::test]
+async fn downloads_are_added_over_http() {
+    let mock = MockAria2::start(SECRET).await;
+    let client = Aria2Client::new(mock.ws_endpoint()).await.unwrap();
+    aria2::set_client(Some(Arc::new(client))).await;
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(browser::serve(listener, TOKEN.to_string()));
+
+    let http = reqwest::Client::new();
+    let post = |token: &str, body: Value| {
+        http.post(format!("{}/download", base))
+            .header("Authorization", format!("Bearer {}", token))
+            .header("Content-Type", "application/json")
+            .body(body.to_string())
+            .send()
+    };
+    let body = json!({
+        "url": "https://example.com/private/a.iso",
+        "cookies": "session=abc",
+        "referer": "https://example.com/private/",
+        "filename": "a.iso",
+    });
+
+    let response = post("wrong", body.clone()).await.unwrap();
+    assert_eq!(response.status(), 401);
+    assert!(mock.tasks().is_empty());
+
+    let response = post(TOKEN, body.clone()).await.unwrap();
+    assert_eq!(response.status(), 200);
+    let answer: Value = serde_json::from_str(&response.text().await.unwrap()).unwrap();
+    let task = mock.task(answer["gid"].as_str().unwrap()).unwrap();
+    assert_eq!(task.options["header"], json!(["Cookie: session=abc"]));
+    assert_eq!(task.options["referer"], "https://example.com/private/");
+    assert_eq!(task.options["out"], "a.iso");
+
+    // The extension decides what to do with duplicates, as the dialog does
+    let response = post(TOKEN, body).await.unwrap();
+    assert_eq!(response.status(), 409);
+    let answer: Value = serde_json::from_str(&response.text().await.unwrap()).unwrap();
+    assert_eq!(answer["kind"], "duplicate");
+    assert_eq!(answer["duplicate"]["gid"], task.gid.as_str());
+
+    let (site, _) = form_site(concat!(
+        "HTTP/1.1 302 Found\r\n",
+        "Location: https://cdn.example.com/b.zip\r\n",
+        "Content-Length: 0\r\nConnection: close\r\n\r\n",
+    ))
+    .await;
+    let response = post(TOKEN, json!({"url": format!("{}/b", site), "postData": "id=1"})).await.unwrap();
+    assert_eq!(response.status(), 200);
+    let answer: Value = serde_json::from_str(&response.text().await.unwrap()).unwrap();
+    let task = mock.task(answer["gid"].as_str().unwrap()).unwrap();
+    assert_eq!(task.uris, vec!["https://cdn.example.com/b.zip".to_string()]);
+    let response = http
+        .get(format!("{}/ping", base))
+        .header("Authorization", format!("Bearer {}", TOKEN))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let response = http.get(format!("{}/other", base)).bearer_auth(TOKEN).send().await.unwrap();
+    assert_eq!(response.status(), 404);
+}
@@ -0,0 +1,205 @@
+//! Tests for rule-based download categories
+
+mod common;
+
+use common::mock_aria2::{MockAria2, MockTask};
+use common::SECRET;
+use motrix_lib::aria2::Aria2Client;
+use motrix_lib::category::{self, mime_type, Categories, Source};
+use motrix_lib::config::{AppConfig, CategoryRule};
+use serde_json::{json, Map, Value};
+use std::path::PathBuf;
+
+fn config() -> AppConfig {
+    AppConfig {
+        download_dir: PathBuf::from("/downloads"),
+        categories: vec![
+            CategoryRule {
+                name: "Linux images".to_string(),
+                extensions: vec!["iso".to_string()],
+                domains: vec!["kernel.org".to_string()],
+                dir: "/data/linux".to_string(),
+                ..CategoryRule::default()
+            },
+            CategoryRule {
+                name: "Videos".to_string(),
+                mime_types: vec!["video/*".to_string()],
+                dir: "Videos".to_string(),
+                max_download_limit: Some("2M".to_string()),
+                tag: Some("media".to_string()),
+                ..CategoryRule::default()
+            },
+            CategoryRule {
+                name: "Archives".to_string(),
+                extensions: vec![".tar.gz".to_string(), "ZIP".to_string()],
+                dir: "Archives".to_string(),
+                ..CategoryRule::default()
+            },
+            CategoryRule {
+                name: "Nightly".to_string(),
+                url_pattern: Some(r"/nightly/\d+/".to_string()),
+                dir: "Nightly".to_string(),
+                ..CategoryRule::default()
+            },
+            CategoryRule {
+                name: "Catch-all".to_string(),
+                dir: "Other".to_string(),
+                ..CategoryRule::default()
+            },
+        ],
+        ..AppConfig::default()
+    }
+}
+
+fn classify(categories: &Categories, uri: &str) -> Option<String> {
+    let source = Source::from_uris(&[uri.to_string()], None);
+    categories.classify(&source).map(|rule| rule.name.clone())
+}
+
+#[test]
+fn rules_match_every_criterion_they_set() {
+    let categories = Categories::new(&config()).unwrap();
+    let name_of = |uri| classify(&categories, uri);
+
+    assert_eq!(name_of("https://cdn.kernel.org/pub/linux.iso").as_deref(), Some("Linux images"));
+    // Both the extension and the domain must match
+    assert_eq!(name_of("https://example.com/pub/linux.iso"), None);
+    assert_eq!(name_of("https://notkernel.org/pub/linux.iso"), None);
+
+    assert_eq!(name_of("https://example.com/Movie.MKV").as_deref(), Some("Videos"));
+    assert_eq!(name_of("https://example.com/src.tar.gz").as_deref(), Some("Archives"));
+    assert_eq!(name_of("https://example.com/src.zip").as_deref(), Some("Archives"));
+    assert_eq!(name_of("https://example.com/nightly/20260101/app.bin").as_deref(), Some("Nightly"));
+    assert_eq!(name_of("magnet:?xt=urn:btih:abc&dn=Show.S01E01.mp4").as_deref(), Some("Videos"));
+    // A rule without criteria never matches
+    assert_eq!(name_of("https://example.com/readme"), None);
+}
+
+#[test]
+fn names_come_from_out_uris_and_torrents() {
+    assert_eq!(mime_type("clip.WebM"), Some("video/webm"));
+    assert_eq!(mime_type("notes"), None);
+
+    let uris = ["https://example.com/get/".to_string(), "https://mirror.example.com/a.iso?x=1".to_string()];
+    assert_eq!(Source::from_uris(&uris, None).names, ["get", "a.iso"]);
+    assert_eq!(Source::from_uris(&uris, Some("sub/b.mkv")).names, ["b.mkv"]);
+
+    let torrent = b"d4:infod5:filesld6:lengthi10e4:pathl5:a.nfoeed6:lengthi900e4:pathl3:dir5:b.mkveee4:name3:setee";
+    assert_eq!(Source::from_torrent(torrent).names, ["b.mkv"]);
+    assert_eq!(Source::from_torrent(b"d4:infod6:lengthi5e4:name5:c.isoee").names, ["c.iso"]);
+    assert!(Source::from_torrent(b"garbage").names.is_empty());
+}
+
+#[test]
+fn invalid_url_patterns_are_rejected() {
+    let mut config = config();
+    config.categories[3].url_pattern = Some("(".to_string());
+    assert!(Categories::new(&config).is_err());
+}
+
+#[test]
+fn options_follow_the_matched_rule() {
+    let categories = Categories::new(&config()).unwrap();
+    let source = Source::from_uris(&["https://example.com/movie.mp4".to_string()], None);
+
+    let mut options = Map::new();
+    assert_eq!(categories.apply(&source, &mut options).unwrap().name, "Videos");
+    assert_eq!(options["dir"], json!("/downloads/Videos"));
+    assert_eq!(options["max-download-limit"], json!("2M"));
+
+    // The default folder is left to the rules, any other one is kept, and so is a given limit
+    let mut options = json!({"dir": "/downloads/", "max-download-limit": "1M"}).as_object().unwrap().clone();
+    categories.apply(&source, &mut options);
+    assert_eq!((options["dir"].as_str(), options["max-download-limit"].as_str()), (Some("/downloads/Videos"), Some("1M")));
+    let mut options = json!({"dir": "/elsewhere"}).as_object().unwrap().clone();
+    categories.apply(&source, &mut options);
+    assert_eq!(options["dir"], json!("/elsewhere"));
+
+    // Absolute folders are used as they are
+    let source = Source::from_uris(&["https://kernel.org/linux.iso".to_string()], None);
+    let mut options = Map::new();
+    categories.apply(&source, &mut options);
+    assert_eq!(options["dir"], json!("/data/linux"));
+}
+
+#[tokio::test]
+async fn tasks_move_once_their_real_name_is_known() {
+    let mock = MockAria2::start(SECRET).await;
+    let client = Aria2Client::new(mock.ws_endpoint()).await.unwrap();
+    category::configure(&config()).await.unwrap();
+
+    // Nothing to go by until the server names the file
+    let uris = vec!["https://example.com/download?id=1".to_string()];
+    let (options, routing) = category::route(Source::from_uris(&uris, None), None).await;
+    assert_eq!(options, Some(Value::Object(Map::new())));
+    mock.insert_task(MockTask::new("0000000000000001", "active", "https://example.com/files/movie.mkv"));
+    category::track("0000000000000001".to_string(), routing).await;
+    assert_eq!(category::category_of("0000000000000001").await, None);
+
+    category::reroute(&client, "0000000000000001").await.unwrap();
+    let task = mock.task("0000000000000001").unwrap();
+    assert_eq!(task.dir, "/downloads/Videos");
+    assert_eq!(task.status, "waiting");
+    assert_eq!(task.options["max-download-limit"], json!("2M"));
+    let routed = category::category_of("0000000000000001").await.unwrap();
+    assert_eq!((routed.name.as_str(), routed.tag.as_deref()), ("Videos", Some("media")));
+    // Nothing is kept once aria2 drops the record
+    category::forget(&["0000000000000001".to_string()]).await;
+    assert_eq!(category::category_of("0000000000000001").await, None);
+
+    // Tasks added to a folder of the user's choosing stay there
+    let options = Some(json!({"dir": "/elsewhere"}));
+    let (options, routing) = category::route(Source::from_uris(&uris, None), options).await;
+    let gid = client.add_uri(vec!["https://example.com/movie.mkv".to_string()], options).await.unwrap();
+    category::track(gid.clone(), routing).await;
+    category::reroute(&client, &gid).await.unwrap();
+    assert_eq!(mock.task(&gid).unwrap().dir, "/elsewhere");
+}
+
+#[tokio::test]
+async fn tasks_wait_for_their_name_and_keep_written_data() {
+    let mock = MockAria2::start(SECRET).await;
+    let client = Aria2Client::new(mock.ws_endpoint()).await.unwrap();
+    category::configure(&config()).await.unwrap();
+    let uris = vec!["https://example.com/download?id=2".to_string()];
+
+    // The server has not named the file yet; a later look routes it
+    let mut task = MockTask::new("00000000000000a2", "active", "https://example.com/download?id=2");
+    task.path = Some(String::new());
+    mock.insert_task(task);
+    let (_, routing) = category::route(Source::from_uris(&uris, None), None).await;
+    category::track("00000000000000a2".to_string(), routing).await;
+    category::reroute(&client, "00000000000000a2").await.unwrap();
+    assert_eq!(mock.task("00000000000000a2").unwrap().dir, "/downloads");
+    mock.update("00000000000000a2", |task| task.path = Some("/downloads/movie.mkv".to_string()));
+    category::reroute(&client, "00000000000000a2").await.unwrap();
+    assert_eq!(mock.task("00000000000000a2").unwrap().dir, "/downloads/Videos");
+
+    // Moving a task with data would restart it away from its partial file
+    let mut task = MockTask::new("00000000000000a3", "active", "https://example.com/files/show.mkv");
+    task.completed_length = 512;
+    mock.insert_task(task);
+    let (_, routing) = category::route(Source::from_uris(&uris, None), None).await;
+    category::track("00000000000000a3".to_string(), routing).await;
+    category::reroute(&client, "00000000000000a3").await.unwrap();
+    let task = mock.task("00000000000000a3").unwrap();
+    assert_eq!((task.dir.as_str(), task.status.as_str()), ("/downloads", "active"));
+    assert_eq!(task.options["max-download-limit"], json!("2M"));
+    assert_eq!(category::category_of("00000000000000a3").await.unwrap().name, "Videos");
+}
+
+#[tokio::test]
+async fn tasks_paused_for_a_move_resume_when_it_fails() {
+    let mock = MockAria2::start(SECRET).await;
+    let client = Aria2Client::new(mock.ws_endpoint()).await.unwrap();
+    category::configure(&config()).await.unwrap();
+    let uris = vec!["https://example.com/download?id=4".to_string()];
+
+    mock.insert_task(MockTask::new("00000000000000a4", "active", "https://example.com/files/clip.zip"));
+    let (_, routing) = category::route(Source::from_uris(&uris, None), None).await;
+    category::track("00000000000000a4".to_string(), routing).await;
+    mock.fail_next("aria2.changeOption", 1, "disk full");
+    assert!(category::reroute(&client, "00000000000000a4").await.is_err());
+    let task = mock.task("00000000000000a4").unwrap();
+    assert_eq!((task.dir.as_str(), task.status.as_str()), ("/downloads", "waiting"));
+}
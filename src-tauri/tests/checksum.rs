@@ -0,0 +1,128 @@
+//! Tests for checksum verification of finished downloads
+
+use motrix_lib::aria2::{Options, Task};
+use motrix_lib::checksum::{
+    parse_sidecar, sidecar_uris, verify_task, Checksum, ChecksumStatus, HashAlgorithm, VerifyRequest,
+};
+use motrix_lib::history::History;
+use serde_json::json;
+use std::path::PathBuf;
+
+const HELLO_SHA256: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+const HELLO_MD5: &str = "5d41402abc4b2a76b9719d911017c592";
+
+/// Write `hello` to a file of its own temporary directory
+fn hello_file() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("motrix-checksum-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("hello.txt");
+    std::fs::write(&path, "hello").unwrap();
+    path
+}
+
+fn complete_task(path: &std::path::Path) -> Task {
+    serde_json::from_value(json!({
+        "gid": "0000000000000001",
+        "status": "complete",
+        "dir": path.parent().unwrap(),
+        "files": [{
+            "index": "1",
+            "path": path,
+            "length": "5",
+            "completedLength": "5",
+            "selected": "true",
+            "uris": [{"uri": "https://example.com/hello.txt", "status": "used"}],
+        }],
+    }))
+    .unwrap()
+}
+
+#[test]
+fn checksums_parse_like_aria2_options() {
+    let checksum: Checksum = format!("sha-256={}", HELLO_SHA256.to_uppercase()).parse().unwrap();
+    assert_eq!(checksum.algorithm, HashAlgorithm::Sha256);
+    assert_eq!(checksum.digest, HELLO_SHA256);
+    assert_eq!(String::from(checksum), format!("sha-256={}", HELLO_SHA256));
+
+    assert!(format!("md5={}", HELLO_MD5).parse::<Checksum>().is_ok());
+    assert!(format!("sha-1={}", HELLO_MD5).parse::<Checksum>().is_err());
+    assert!("adler32=0a".parse::<Checksum>().is_err());
+    assert!(HELLO_SHA256.parse::<Checksum>().is_err());
+}
+
+#[test]
+fn files_hash_with_each_algorithm() {
+    let path = hello_file();
+    assert_eq!(HashAlgorithm::Sha256.hash_file(&path).unwrap(), HELLO_SHA256);
+    assert_eq!(HashAlgorithm::Md5.hash_file(&path).unwrap(), HELLO_MD5);
+    assert_eq!(
+        HashAlgorithm::Sha1.hash_file(&path).unwrap(),
+        "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d"
+    );
+}
+
+#[test]
+fn sidecars_sit_next_to_the_uri() {
+    let uris = sidecar_uris("https://example.com/pub/disk.iso?token=1");
+    assert_eq!(uris[0], ("https://example.com/pub/disk.iso.sha256".to_string(), HashAlgorithm::Sha256));
+    assert!(uris.contains(&("https://example.com/pub/SHA256SUMS".to_string(), HashAlgorithm::Sha256)));
+    assert!(uris.contains(&("https://example.com/pub/disk.iso.md5".to_string(), HashAlgorithm::Md5)));
+    assert!(sidecar_uris("magnet:?xt=urn:btih:abc").is_empty());
+    assert!(sidecar_uris("ftp://example.com/disk.iso").is_empty());
+}
+
+#[test]
+fn sidecar_formats_are_understood() {
+    let sums = format!(
+        "# comment\n{}  other.iso\n{} *disk.iso\n",
+        "0".repeat(64),
+        HELLO_SHA256.to_uppercase()
+    );
+    assert_eq!(parse_sidecar(&sums, "disk.iso", HashAlgorithm::Sha256, false).as_deref(), Some(HELLO_SHA256));
+    assert_eq!(parse_sidecar(&sums, "missing.iso", HashAlgorithm::Sha256, false), None);
+
+    let bsd = format!("SHA256 (dist/disk.iso) = {}\n", HELLO_SHA256);
+    assert_eq!(parse_sidecar(&bsd, "disk.iso", HashAlgorithm::Sha256, false).as_deref(), Some(HELLO_SHA256));
+
+    // A per-file sidecar may hold just the digest, or name the file differently
+    assert_eq!(parse_sidecar(HELLO_MD5, "disk.iso", HashAlgorithm::Md5, true).as_deref(), Some(HELLO_MD5));
+    let renamed = format!("{}  disk-v2.iso", HELLO_MD5);
+    assert_eq!(parse_sidecar(&renamed, "disk.iso", HashAlgorithm::Md5, true).as_deref(), Some(HELLO_MD5));
+    assert_eq!(parse_sidecar(&renamed, "disk.iso", HashAlgorithm::Md5, false), None);
+}
+
+#[tokio::test]
+async fn expected_checksum_verifies_or_mismatches() {
+    let path = hello_file();
+    let task = complete_task(&path);
+
+    let request = VerifyRequest {
+        expected_checksum: Some(format!("sha-256={}", HELLO_SHA256).parse().unwrap()),
+        sidecar: false,
+    };
+    let report = verify_task(&task, &request).await;
+    assert_eq!(report.status, ChecksumStatus::Verified);
+    assert_eq!(report.files[0].actual.as_deref(), Some(HELLO_SHA256));
+
+    let request = VerifyRequest {
+        expected_checksum: Some(format!("md5={}", "0".repeat(32)).parse().unwrap()),
+        sidecar: false,
+    };
+    let report = verify_task(&task, &request).await;
+    assert_eq!(report.status, ChecksumStatus::Mismatch);
+    assert_eq!(report.files[0].actual.as_deref(), Some(HELLO_MD5));
+
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(verify_task(&task, &request).await.status, ChecksumStatus::Unavailable);
+}
+
+#[test]
+fn mismatches_are_marked_in_history() {
+    let path = hello_file();
+    let mut history = History::open(path.with_file_name("history.json")).unwrap();
+    history.record(&complete_task(&path), Options::new(), 1);
+
+    assert!(history.set_checksum("0000000000000001", ChecksumStatus::Mismatch));
+    assert!(!history.set_checksum("0000000000000002", ChecksumStatus::Mismatch));
+    assert_eq!(history.get("0000000000000001").unwrap().checksum, Some(ChecksumStatus::Mismatch));
+}
@@ -0,0 +1,275 @@
+//! Tests for the main binary's command line
+
+mod common;
+
+use common::mock_aria2::MockAria2;
+use common::{temp_dir, SECRET};
+use motrix_lib::aria2::{self, Aria2Client, GlobalStat};
+use motrix_lib::cli::{self, LaunchRequest, LinkDecoder, LinkDecoders};
+use motrix_lib::deep_link::DeepLink;
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+fn argv(args: &[&str]) -> Vec<String> {
+    std::iter::once("motrix")
+        .chain(args.iter().copied())
+        .map(str::to_string)
+        .collect()
+}
+
+#[test]
+fn flags_become_a_launch_request() {
+    let cwd = Path::new("/home/user/work");
+    let request = cli::parse_args(
+        &argv(&[
+            "--hidden",
+            "--dir",
+            "isos",
+            "--header=Authorization: Bearer xyz",
+            "--paused",
+            "--quit-when-done",
+            "--out",
+            "debian.iso",
+            "thunder://QUFodHRwczovL2V4YW1wbGUuY29tL2EuaXNvWlo=",
+        ]),
+        cwd,
+    );
+    assert_eq!(
+        request,
+        LaunchRequest {
+            uris: vec!["https://example.com/a.iso".to_string()],
+            dir: Some(PathBuf::from("/home/user/work/isos")),
+            out: Some("debian.iso".to_string()),
+            headers: vec!["Authorization: Bearer xyz".to_string()],
+            paused: true,
+            hidden: true,
+            quit_when_done: true,
+            links: Vec::new(),
+            errors: Vec::new(),
+        }
+    );
+    assert_eq!(
+        request.options(),
+        json!({
+            "dir": "/home/user/work/isos",
+            "out": "debian.iso",
+            "header": ["Authorization: Bearer xyz"],
+            "pause": "true",
+        })
+    );
+
+    // motrix:// links are handled as deep links, not downloaded
+    let request = cli::parse_args(&argv(&["motrix://pause-all"]), cwd);
+    assert!(request.uris.is_empty());
+    assert_eq!(request.links, [DeepLink::PauseAll]);
+    assert_eq!(cli::parse_args(&argv(&["motrix://delete-all"]), cwd).errors.len(), 1);
+
+    // The plain launch a desktop entry makes
+    assert_eq!(cli::parse_args(&argv(&[]), cwd), LaunchRequest::default());
+    assert_eq!(LaunchRequest::default().options(), json!({}));
+}
+
+#[test]
+fn files_resolve_against_the_callers_directory() {
+    let cwd = temp_dir("cli");
+    std::fs::write(cwd.join("linux.torrent"), b"d4:infod4:name5:linuxee").unwrap();
+    std::fs::create_dir_all(cwd.join("lists")).unwrap();
+    std::fs::write(
+        cwd.join("lists/urls.txt"),
+        "# nightly builds\nhttps://example.com/a.zip\n\n  linux.torrent  \nmagnet:?xt=urn:btih:abc\n",
+    )
+    .unwrap();
+
+    let request = cli::parse_args(
+        &argv(&["--input-file", "lists/urls.txt", "https://example.com/b.zip"]),
+        &cwd,
+    );
+    assert!(request.errors.is_empty(), "{:?}", request.errors);
+    assert_eq!(
+        request.uris,
+        [
+            "https://example.com/a.zip".to_string(),
+            cwd.join("linux.torrent").to_string_lossy().into_owned(),
+            "magnet:?xt=urn:btih:abc".to_string(),
+            "https://example.com/b.zip".to_string(),
+        ]
+    );
+
+    // Relative to another directory the torrent is not found
+    let request = cli::parse_args(&argv(&["linux.torrent"]), Path::new("/nonexistent"));
+    assert!(request.uris.is_empty());
+    assert_eq!(request.errors.len(), 1);
+    assert_eq!(cli::parse_args(&argv(&["--input-file", "missing.txt"]), &cwd).errors.len(), 1);
+
+    // A bad line costs only itself
+    std::fs::write(cwd.join("lists/mixed.txt"), "notes.txt\nhttps://example.com/c.zip\n").unwrap();
+    let request = cli::parse_args(&argv(&["--input-file", "lists/mixed.txt"]), &cwd);
+    assert_eq!(request.uris, ["https://example.com/c.zip".to_string()]);
+    assert_eq!(request.errors, ["Invalid input: Not a URL, torrent or metalink file: notes.txt".to_string()]);
+}
+
+#[test]
+fn bad_arguments_are_reported_and_the_rest_kept() {
+    let cwd = Path::new("/home/user/work");
+    for (args, errors) in [
+        (&["--dir"][..], 1),
+        // The unknown option, then its value taken for a download
+        (&["--speed", "1M"], 2),
+        (&["--header", "NoColon", "https://example.com/a"], 1),
+        (&["--out", "a.iso", "https://example.com/a", "https://example.com/b"], 1),
+        (&["notes.txt"], 1),
+        (&["file:///etc/passwd"], 1),
+        (&["thunder://not-base64!"], 1),
+    ] {
+        let request = cli::parse_args(&argv(args), cwd);
+        assert_eq!(request.errors.len(), errors, "{:?}: {:?}", args, request.errors);
+    }
+
+    let request = cli::parse_args(
+        &argv(&["--header", "NoColon", "--out", "a.iso", "https://example.com/a", "https://example.com/b"]),
+        cwd,
+    );
+    assert_eq!(request.uris, ["https://example.com/a".to_string(), "https://example.com/b".to_string()]);
+    assert!(request.headers.is_empty());
+    assert_eq!(request.out, None);
+
+    // The URL is downloaded and the link aria2 cannot handle explained
+    let request = cli::parse_args(&argv(&["https://a/x.zip", "ed2k://|file|a.iso|1|0|/"]), cwd);
+    assert_eq!(request.uris, ["https://a/x.zip".to_string()]);
+    assert_eq!(request.errors.len(), 1);
+    assert!(request.errors[0].contains("ed2k:// links need an eD2k client"), "{}", request.errors[0]);
+}
+
+#[test]
+fn done_once_nothing_is_left_to_download() {
+    let stat = |active: u64, waiting: u64, stopped_total: u64| -> GlobalStat {
+        serde_json::from_value(json!({
+            "downloadSpeed": "0",
+            "uploadSpeed": "0",
+            "numActive": active.to_string(),
+            "numWaiting": waiting.to_string(),
+            "numStopped": stopped_total.to_string(),
+            "numStoppedTotal": stopped_total.to_string(),
+        }))
+        .unwrap()
+    };
+    assert!(!cli::is_done(&stat(1, 0, 0), 0, true));
+    assert!(!cli::is_done(&stat(0, 1, 0), 0, true));
+    // Nothing added yet
+    assert!(!cli::is_done(&stat(0, 0, 3), 3, false));
+    assert!(cli::is_done(&stat(0, 0, 3), 3, true));
+    // Finished between two checks
+    assert!(cli::is_done(&stat(0, 0, 4), 3, false));
+}
+
+#[tokio::test]
+async fn hidden_launches_add_downloads_directly() {
+    let mock = MockAria2::start(SECRET).await;
+    let client = Aria2Client::new(mock.ws_endpoint()).await.unwrap();
+    aria2::set_client(Some(Arc::new(client))).await;
+
+    let request = cli::parse_args(
+        &argv(&[
+            "--hidden",
+            "--paused",
+            "--dir",
+            "/srv/files",
+            "https://example.com/a.zip",
+            "https://example.com/b.zip",
+        ]),
+        Path::new("/"),
+    );
+    let gids = cli::add(&request).await.unwrap();
+    assert_eq!(gids.len(), 2);
+    for gid in gids {
+        let task = mock.task(&gid).unwrap();
+        assert_eq!(task.dir, "/srv/files");
+        assert_eq!(task.options["pause"], "true");
+    }
+}
+
+#[test]
+fn download_manager_links_are_unwrapped() {
+    let cases = [
+        (
+            "flashget://W0ZMQVNIR0VUXWh0dHBzOi8vZXhhbXBsZS5jb20vYS56aXBbRkxBU0hHRVRd&1234",
+            "https://example.com/a.zip",
+        ),
+        ("QQDL://aHR0cHM6Ly9leGFtcGxlLmNvbS9iLnppcA%3D%3D/", "https://example.com/b.zip"),
+        ("qqdl://aHR0cHM6Ly9leGFtcGxlLmNvbS9iLnppcA==/", "https://example.com/b.zip"),
+        // Unpadded base64 ending in `/`, which is part of the payload
+        ("qqdl://aHR0cHM6Ly9leGFtcGxlLmNvbS9hYi56aXA/", "https://example.com/ab.zip?"),
+        (
+            "flashget://W0ZMQVNIR0VUXWh0dHBzOi8vZXhhbXBsZS5jb20vYS56aXBbRkxBU0hHRVRd&1234/",
+            "https://example.com/a.zip",
+        ),
+        ("thunder://QUFmdHA6Ly9leGFtcGxlLmNvbS9jIGQuaXNvIFpa", "ftp://example.com/c%20d.iso"),
+        // A slash after unpadded base64 that does not decode with it
+        ("thunder://QUFodHRwOi8vYS5jb20vZmlsZVpa/", "http://a.com/file"),
+        // thunder:// wrapping flashget://
+        (
+            "thunder://QUFmbGFzaGdldDovL1cwWk1RVk5JUjBWVVhXaDBkSEJ6T2k4dlpYaGhiWEJzWlM1amIyMHZZUzU2YVhCYlJreEJVMGhIUlZSZFpa",
+            "https://example.com/a.zip",
+        ),
+        ("sftp://user@example.com/srv/d.iso", "sftp://user@example.com/srv/d.iso"),
+        ("  magnet:?xt=urn:btih:abc&dn=My File ", "magnet:?xt=urn:btih:abc&dn=My%20File"),
+    ];
+    for (link, url) in cases {
+        assert_eq!(cli::decode_link(link).unwrap(), url, "{}", link);
+        assert!(cli::is_downloadable_url(link));
+    }
+
+    let error = cli::decode_link("ed2k://|file|a.iso|1024|0123456789ABCDEF0123456789ABCDEF|/").unwrap_err();
+    assert!(matches!(error, motrix_lib::Error::Unsupported(_)), "{}", error);
+    assert!(cli::is_link(
+        "ed2k://|file|a.iso|1024|0123456789ABCDEF0123456789ABCDEF|/"
+    ));
+    assert!(!cli::is_downloadable_url(
+        "ed2k://|file|a.iso|1024|0123456789ABCDEF0123456789ABCDEF|/"
+    ));
+
+    for link in [
+        "file:///etc/passwd",
+        "C:\\Downloads\\a.torrent",
+        "flashget://%%%",
+        "qqdl://",
+    ] {
+        assert!(cli::decode_link(link).is_err(), "{}", link);
+    }
+}
+
+#[test]
+fn percent_encoding_is_normalized() {
+    assert_eq!(
+        cli::normalize_percent_encoding("https://example.com/f\u{fc}r dich%e4%zz.zip?q=a b"),
+        "https://example.com/f%C3%BCr%20dich%E4%25zz.zip?q=a%20b"
+    );
+    assert_eq!(
+        cli::normalize_percent_encoding("https://example.com/a%2Fb"),
+        "https://example.com/a%2Fb"
+    );
+}
+
+#[test]
+fn decoders_can_be_added() {
+    struct Mirror;
+
+    impl LinkDecoder for Mirror {
+        fn scheme(&self) -> &str {
+            "mirror"
+        }
+
+        fn decode(&self, link: &str) -> motrix_lib::Result<String> {
+            Ok(link.replacen("mirror://", "https://mirror.example.com/", 1))
+        }
+    }
+
+    let mut decoders = LinkDecoders::default();
+    assert!(decoders.decode("mirror://a.zip").is_err());
+    decoders.register(Mirror);
+    assert_eq!(
+        decoders.decode("MIRROR://a.zip").unwrap(),
+        "https://mirror.example.com/a.zip"
+    );
+}
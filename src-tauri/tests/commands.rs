@@ -0,0 +1,195 @@
+//! Integration tests for the command layer backed by the mock aria2 server
+//!
+//! Commands share the global client, so each test holds `GLOBAL_CLIENT`
+//! while it swaps in a client of its own.
+
+mod common;
+
+use common::mock_aria2::{MockAria2, MockTask};
+use common::SECRET;
+use motrix_lib::aria2::{self, Aria2Client, TaskStatus};
+use motrix_lib::checksum::VerifyRequest;
+use motrix_lib::commands;
+use motrix_lib::duplicate::DuplicateAction;
+use serde_json::json;
+use std::sync::Arc;
+use tokio::sync::{Mutex, MutexGuard};
+
+static GLOBAL_CLIENT: Mutex<()> = Mutex::const_new(());
+
+/// Start a mock server and install a client for it as the global client
+async fn setup() -> (MockAria2, MutexGuard<'static, ()>) {
+    let guard = GLOBAL_CLIENT.lock().await;
+    let mock = MockAria2::start(SECRET).await;
+    let client = Aria2Client::new(mock.ws_endpoint()).await.unwrap();
+    aria2::set_client(Some(Arc::new(client))).await;
+    (mock, guard)
+}
+
+#[tokio::test]
+async fn commands_fail_without_client() {
+    let _guard = GLOBAL_CLIENT.lock().await;
+    aria2::set_client(None).await;
+
+    let err = commands::get_global_stat().await.unwrap_err();
+    assert!(err.to_string().contains("not initialized"), "{}", err);
+}
+
+#[tokio::test]
+async fn add_and_inspect_task() {
+    let (mock, _guard) = setup().await;
+
+    let gid = commands::add_uri(vec!["https://example.com/a.zip".into()], Some(json!({"dir": "/data"})), None, None)
+        .await
+        .unwrap();
+    let task = commands::get_task_info(gid.clone(), None).await.unwrap();
+    assert_eq!(task.status, TaskStatus::Active);
+    assert_eq!(task.dir, "/data");
+    assert_eq!(mock.task(&gid).unwrap().options["dir"], "/data");
+
+    let stat = commands::get_global_stat().await.unwrap();
+    assert_eq!(stat.num_active, 1);
+}
+
+#[tokio::test]
+async fn expected_checksums_reach_aria2_for_single_file_downloads() {
+    let (mock, _guard) = setup().await;
+    let digest = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+    let verify = VerifyRequest {
+        expected_checksum: Some(format!("SHA256={}", digest).parse().unwrap()),
+        sidecar: false,
+    };
+
+    // No verifier is loaded here; the task is added all the same
+    let gid = commands::add_uri(vec!["https://example.com/hello.txt".into()], None, Some(verify.clone()), None)
+        .await
+        .unwrap();
+    assert_eq!(mock.task(&gid).unwrap().options["checksum"], format!("sha-256={}", digest));
+
+    let err = commands::add_uri(vec!["magnet:?xt=urn:btih:abc".into()], None, Some(verify), None)
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), "validation");
+}
+
+#[tokio::test]
+async fn duplicates_wait_for_the_caller_to_decide() {
+    let (mock, _guard) = setup().await;
+    let uri = "https://example.com/a.zip".to_string();
+    let gid = commands::add_uri(vec![uri.clone()], None, None, None).await.unwrap();
+
+    let err = commands::add_uri(vec![format!("{}#again", uri)], None, None, None).await.unwrap_err();
+    assert_eq!(err.kind(), "duplicate");
+    let err = serde_json::to_value(&err).unwrap();
+    assert_eq!(err["duplicate"]["gid"], json!(gid));
+    assert_eq!(err["duplicate"]["reason"], "uri");
+
+    let skipped = commands::add_uri(vec![uri.clone()], None, None, Some(DuplicateAction::Skip)).await.unwrap();
+    assert_eq!(skipped, gid);
+    let both = commands::add_uri(vec![uri.clone()], None, None, Some(DuplicateAction::KeepBoth)).await.unwrap();
+    assert_ne!(both, gid);
+    assert_eq!(mock.task(&both).unwrap().options["auto-file-renaming"], "true");
+
+    // Restarting replaces the first task found
+    let restarted = commands::add_uri(vec![uri], None, None, Some(DuplicateAction::Restart)).await.unwrap();
+    assert!(mock.task(&gid).is_none());
+    assert_eq!(mock.task(&restarted).unwrap().options["allow-overwrite"], "true");
+}
+
+#[tokio::test]
+async fn task_list_merges_active_and_waiting() {
+    let (mock, _guard) = setup().await;
+    mock.insert_task(MockTask::new("0000000000000001", "active", "https://example.com/a"));
+    mock.insert_task(MockTask::new("0000000000000002", "paused", "https://example.com/b"));
+    mock.insert_task(MockTask::new("0000000000000003", "error", "https://example.com/c"));
+
+    let active = commands::get_task_list("active".into()).await.unwrap();
+    assert_eq!(active.len(), 2);
+    let stopped = commands::get_task_list("stopped".into()).await.unwrap();
+    assert_eq!(stopped.len(), 1);
+    assert_eq!(stopped[0].status, TaskStatus::Error);
+}
+
+#[tokio::test]
+async fn query_tasks_pages_matches() {
+    let (mock, _guard) = setup().await;
+    mock.insert_task(MockTask::new("0000000000000001", "active", "https://example.com/a.iso"));
+    mock.insert_task(MockTask::new("0000000000000002", "error", "https://example.com/b.iso"));
+    mock.insert_task(MockTask::new("0000000000000003", "complete", "https://example.com/c.zip"));
+
+    let query = serde_json::from_value(json!({
+        "status": ["complete", "error"],
+        "search": ".iso",
+        "limit": 10,
+    }))
+    .unwrap();
+    let page = commands::query_tasks(query).await.unwrap();
+    assert_eq!(page.total, 1);
+    assert_eq!(page.tasks[0].status, TaskStatus::Error);
+}
+
+#[tokio::test]
+async fn batch_commands_report_per_gid_results() {
+    let (mock, _guard) = setup().await;
+    mock.insert_task(MockTask::new("0000000000000001", "active", "https://example.com/a"));
+    mock.insert_task(MockTask::new("0000000000000002", "active", "https://example.com/b"));
+    let gids = vec![
+        "0000000000000001".to_string(),
+        "0000000000000002".to_string(),
+        "ffffffffffffffff".to_string(),
+    ];
+
+    let paused = commands::pause_tasks(gids.clone(), None).await.unwrap();
+    assert!(paused[0].error.is_none() && paused[1].error.is_none());
+    assert!(paused[2].error.is_some());
+
+    let resumed = commands::resume_tasks(gids[..2].to_vec()).await.unwrap();
+    assert!(resumed.iter().all(|r| r.error.is_none()));
+    assert_eq!(mock.task(&gids[0]).unwrap().status, "waiting");
+
+    let removed = commands::remove_tasks(gids[..2].to_vec(), None).await.unwrap();
+    assert!(removed.iter().all(|r| r.error.is_none()));
+    assert!(mock.tasks().iter().all(|task| task.status == "removed"));
+    let purged = commands::remove_task_records(gids[..2].to_vec()).await.unwrap();
+    assert!(purged.iter().all(|r| r.error.is_none()));
+    assert!(mock.tasks().is_empty());
+}
+
+#[tokio::test]
+async fn global_options_reach_the_engine() {
+    let (mock, _guard) = setup().await;
+
+    commands::change_global_option(json!({"max-overall-download-limit": "1M"}))
+        .await
+        .unwrap();
+    assert_eq!(mock.global_option("max-overall-download-limit"), Some(json!("1M")));
+}
+
+#[tokio::test]
+async fn mirrors_can_be_inspected_and_edited() {
+    let (mock, _guard) = setup().await;
+    let mut task = MockTask::new("0000000000000001", "active", "https://a.example.com/f.iso");
+    task.download_speed = 2048;
+    mock.insert_task(task);
+    let gid = "0000000000000001".to_string();
+
+    let change = commands::change_task_uri(
+        gid.clone(),
+        1,
+        vec!["https://a.example.com/f.iso".into()],
+        vec!["https://b.example.com/f.iso".into(), "https://c.example.com/f.iso".into()],
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!((change.deleted, change.added), (1, 2));
+
+    let servers = commands::get_task_servers(gid.clone()).await.unwrap();
+    assert_eq!(servers[0].index, 1);
+    assert_eq!(servers[0].servers.len(), 2);
+    assert_eq!(servers[0].servers[0].current_uri, "https://b.example.com/f.iso");
+    assert_eq!(servers[0].servers[0].download_speed, 2048);
+
+    let uris = commands::get_task_uris(gid).await.unwrap();
+    assert_eq!(uris.len(), 2);
+}
@@ -0,0 +1,662 @@
+//! In-process mock of the aria2 JSON-RPC server
+//!
+//! Serves WebSocket and HTTP on two localhost ports and implements the core
+//! aria2 methods against an in-memory task table. Tests can inject
+//! notifications, per-method delays and errors, and abrupt disconnects.
+
+use futures_util::{SinkExt, StreamExt};
+use motrix_lib::aria2::RpcEndpoint;
+use motrix_lib::config::RpcScheme;
+use serde_json::{json, Map, Value};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, mpsc};
+use tokio_tungstenite::tungstenite::Message;
+
+/// aria2's generic RPC error code
+pub const ERR_GENERIC: i64 = 1;
+
+/// A task held by the mock
+#[derive(Debug, Clone)]
+pub struct MockTask {
+    pub gid: String,
+    pub status: String,
+    pub uris: Vec<String>,
+    pub dir: String,
+    pub total_length: u64,
+    pub completed_length: u64,
+    pub download_speed: u64,
+    pub info_hash: Option<String>,
+    pub error_code: Option<String>,
+    pub options: Map<String, Value>,
+    /// Path reported for the file instead of `<dir>/<last URI segment>`; empty while unknown
+    pub path: Option<String>,
+}
+
+impl MockTask {
+    pub fn new(gid: &str, status: &str, uri: &str) -> Self {
+        Self {
+            gid: gid.to_string(),
+            status: status.to_string(),
+            uris: vec![uri.to_string()],
+            dir: "/downloads".to_string(),
+            total_length: 1024,
+            completed_length: 0,
+            download_speed: 0,
+            info_hash: None,
+            error_code: None,
+            options: Map::new(),
+            path: None,
+        }
+    }
+
+    fn file_name(&self) -> String {
+        self.uris
+            .first()
+            .and_then(|uri| uri.rsplit('/').next())
+            .unwrap_or("file")
+            .to_string()
+    }
+
+    fn uris_json(&self) -> Value {
+        json!(self.uris.iter().map(|uri| json!({"uri": uri, "status": "used"})).collect::<Vec<_>>())
+    }
+
+    fn files_json(&self) -> Value {
+        json!([{
+            "index": "1",
+            "path": self.path.clone().unwrap_or_else(|| format!("{}/{}", self.dir, self.file_name())),
+            "length": self.total_length.to_string(),
+            "completedLength": self.completed_length.to_string(),
+            "selected": "true",
+            "uris": self.uris_json(),
+        }])
+    }
+
+    /// Render as aria2 does: numbers as strings, optional key filter
+    fn to_json(&self, keys: Option<&[String]>) -> Value {
+        let mut value = json!({
+            "gid": self.gid,
+            "status": self.status,
+            "totalLength": self.total_length.to_string(),
+            "completedLength": self.completed_length.to_string(),
+            "uploadLength": "0",
+            "downloadSpeed": self.download_speed.to_string(),
+            "uploadSpeed": "0",
+            "connections": "1",
+            "dir": self.dir,
+            "files": self.files_json(),
+        });
+        let map = value.as_object_mut().unwrap();
+        if let Some(info_hash) = &self.info_hash {
+            map.insert("infoHash".into(), json!(info_hash));
+            map.insert("bittorrent".into(), json!({"info": {"name": self.file_name()}, "mode": "single"}));
+        }
+        if let Some(code) = &self.error_code {
+            map.insert("errorCode".into(), json!(code));
+            map.insert("errorMessage".into(), json!("mock failure"));
+        }
+        if let Some(keys) = keys {
+            map.retain(|key, _| keys.iter().any(|k| k == key));
+        }
+        value
+    }
+
+    fn is_waiting(&self) -> bool {
+        self.status == "waiting" || self.status == "paused"
+    }
+
+    fn is_stopped(&self) -> bool {
+        matches!(self.status.as_str(), "complete" | "error" | "removed")
+    }
+}
+
+#[derive(Default)]
+struct State {
+    tasks: Vec<MockTask>,
+    global_options: Map<String, Value>,
+    next_gid: u64,
+    delays: HashMap<String, Duration>,
+    failures: HashMap<String, VecDeque<(i64, String)>>,
+    overrides: HashMap<String, Value>,
+    calls: Vec<String>,
+    /// Parameters of the authenticated calls, without the token
+    params: Vec<(String, Vec<Value>)>,
+    /// Gids of stopped tasks in the order they stopped, as aria2 lists them
+    stopped: Vec<String>,
+    /// Tasks that ever stopped, aria2's `numStoppedTotal`
+    stopped_total: u64,
+}
+
+type RpcOutcome = Result<Value, (i64, String)>;
+
+/// Handle to a running mock server; the server lives until the runtime ends
+#[derive(Clone)]
+pub struct MockAria2 {
+    secret: String,
+    ws_port: u16,
+    http_port: u16,
+    state: Arc<Mutex<State>>,
+    notifications: broadcast::Sender<String>,
+    disconnects: broadcast::Sender<bool>,
+    offline: Arc<AtomicBool>,
+}
+
+impl MockAria2 {
+    /// Start serving on ephemeral localhost ports
+    pub async fn start(secret: &str) -> Self {
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let http_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let (notifications, _) = broadcast::channel(64);
+        let (disconnects, _) = broadcast::channel(4);
+
+        let mock = Self {
+            secret: secret.to_string(),
+            ws_port: ws_listener.local_addr().unwrap().port(),
+            http_port: http_listener.local_addr().unwrap().port(),
+            state: Arc::new(Mutex::new(State::default())),
+            notifications,
+            disconnects,
+            offline: Arc::new(AtomicBool::new(false)),
+        };
+
+        let server = mock.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = ws_listener.accept().await {
+                if server.offline.load(Ordering::SeqCst) {
+                    continue;
+                }
+                tokio::spawn(server.clone().serve_ws(stream));
+            }
+        });
+        let server = mock.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = http_listener.accept().await {
+                tokio::spawn(server.clone().serve_http(stream));
+            }
+        });
+
+        mock
+    }
+
+    pub fn ws_endpoint(&self) -> RpcEndpoint {
+        RpcEndpoint::local(self.ws_port, self.secret.clone())
+    }
+
+    pub fn http_endpoint(&self) -> RpcEndpoint {
+        RpcEndpoint {
+            scheme: RpcScheme::Http,
+            ..RpcEndpoint::local(self.http_port, self.secret.clone())
+        }
+    }
+
+    pub fn insert_task(&self, task: MockTask) {
+        self.state.lock().unwrap().tasks.push(task);
+    }
+
+    pub fn task(&self, gid: &str) -> Option<MockTask> {
+        self.state.lock().unwrap().tasks.iter().find(|t| t.gid == gid).cloned()
+    }
+
+    pub fn tasks(&self) -> Vec<MockTask> {
+        self.state.lock().unwrap().tasks.clone()
+    }
+
+    /// Modify a task in place without sending a notification
+    pub fn update(&self, gid: &str, f: impl FnOnce(&mut MockTask)) {
+        if let Some(task) = self.state.lock().unwrap().tasks.iter_mut().find(|t| t.gid == gid) {
+            f(task);
+        }
+    }
+
+    /// Change a task's status without sending a notification
+    pub fn set_status(&self, gid: &str, status: &str) {
+        if let Some(task) = self.state.lock().unwrap().tasks.iter_mut().find(|t| t.gid == gid) {
+            task.status = status.to_string();
+        }
+    }
+
+    /// Push a notification such as `aria2.onDownloadComplete` to every WebSocket client
+    pub fn notify(&self, method: &str, gid: &str) {
+        let message = json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": [{"gid": gid}],
+        });
+        let _ = self.notifications.send(message.to_string());
+    }
+
+    /// Delay every response to `method`
+    pub fn delay(&self, method: &str, delay: Duration) {
+        self.state.lock().unwrap().delays.insert(method.to_string(), delay);
+    }
+
+    /// Make the next call to `method` fail with the given aria2 error
+    pub fn fail_next(&self, method: &str, code: i64, message: &str) {
+        self.state
+            .lock()
+            .unwrap()
+            .failures
+            .entry(method.to_string())
+            .or_default()
+            .push_back((code, message.to_string()));
+    }
+
+    /// Answer every call to `method` with `result` instead of the real one
+    pub fn respond_with(&self, method: &str, result: Value) {
+        self.state.lock().unwrap().overrides.insert(method.to_string(), result);
+    }
+
+    /// Drop every WebSocket connection without a closing handshake
+    pub fn disconnect(&self) {
+        let _ = self.disconnects.send(false);
+    }
+
+    /// Close every WebSocket connection with a close frame
+    pub fn close(&self) {
+        let _ = self.disconnects.send(true);
+    }
+
+    /// Refuse new WebSocket connections while `offline` is set
+    pub fn set_offline(&self, offline: bool) {
+        self.offline.store(offline, Ordering::SeqCst);
+    }
+
+    /// Methods received so far, in order (multicall entries are listed individually)
+    pub fn calls(&self) -> Vec<String> {
+        self.state.lock().unwrap().calls.clone()
+    }
+
+    /// Parameters of each `method` call received so far, without the token
+    pub fn call_params(&self, method: &str) -> Vec<Vec<Value>> {
+        let state = self.state.lock().unwrap();
+        state
+            .params
+            .iter()
+            .filter(|(name, _)| name == method)
+            .map(|(_, params)| params.clone())
+            .collect()
+    }
+
+    pub fn clear_calls(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.calls.clear();
+        state.params.clear();
+    }
+
+    pub fn global_option(&self, key: &str) -> Option<Value> {
+        self.state.lock().unwrap().global_options.get(key).cloned()
+    }
+
+    async fn serve_ws(self, stream: TcpStream) {
+        let Ok(ws) = tokio_tungstenite::accept_async(stream).await else {
+            return;
+        };
+        let (mut write, mut read) = ws.split();
+        let (out_tx, mut out_rx) = mpsc::unbounded_channel::<String>();
+        let mut notifications = self.notifications.subscribe();
+        let mut disconnects = self.disconnects.subscribe();
+
+        loop {
+            tokio::select! {
+                msg = read.next() => {
+                    let Some(Ok(Message::Text(text))) = msg else {
+                        return;
+                    };
+                    // Answer concurrently so a delayed call does not block the others
+                    let server = self.clone();
+                    let out_tx = out_tx.clone();
+                    tokio::spawn(async move {
+                        if let Some(response) = server.handle_text(&text).await {
+                            let _ = out_tx.send(response.0);
+                        }
+                    });
+                }
+                Some(out) = out_rx.recv() => {
+                    if write.send(Message::Text(out)).await.is_err() {
+                        return;
+                    }
+                }
+                Ok(notification) = notifications.recv() => {
+                    if write.send(Message::Text(notification)).await.is_err() {
+                        return;
+                    }
+                }
+                graceful = disconnects.recv() => {
+                    if graceful.unwrap_or(false) {
+                        let _ = write.send(Message::Close(None)).await;
+                    }
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn serve_http(self, mut stream: TcpStream) {
+        let mut buf = Vec::new();
+        let mut chunk = [0u8; 4096];
+        let (header_end, content_length) = loop {
+            let Ok(n) = stream.read(&mut chunk).await else {
+                return;
+            };
+            if n == 0 {
+                return;
+            }
+            buf.extend_from_slice(&chunk[..n]);
+            if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                let head = String::from_utf8_lossy(&buf[..pos]).to_lowercase();
+                let length = head
+                    .lines()
+                    .find_map(|line| line.strip_prefix("content-length:"))
+                    .and_then(|v| v.trim().parse::<usize>().ok())
+                    .unwrap_or(0);
+                break (pos + 4, length);
+            }
+        };
+        while buf.len() < header_end + content_length {
+            let Ok(n) = stream.read(&mut chunk).await else {
+                return;
+            };
+            if n == 0 {
+                return;
+            }
+            buf.extend_from_slice(&chunk[..n]);
+        }
+
+        let body = String::from_utf8_lossy(&buf[header_end..header_end + content_length]).to_string();
+        let (body, ok) = self.handle_text(&body).await.unwrap_or_else(|| (String::new(), false));
+        let status = if ok { "200 OK" } else { "400 Bad Request" };
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+        let _ = stream.write_all(response.as_bytes()).await;
+        let _ = stream.shutdown().await;
+    }
+
+    /// Handle one JSON-RPC request; returns the response text and whether it succeeded
+    async fn handle_text(&self, text: &str) -> Option<(String, bool)> {
+        let request: Value = serde_json::from_str(text).ok()?;
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let method = request.get("method")?.as_str()?.to_string();
+        let params = request
+            .get("params")
+            .and_then(|p| p.as_array())
+            .cloned()
+            .unwrap_or_default();
+
+        let delay = self.state.lock().unwrap().delays.get(&method).copied();
+        if let Some(delay) = delay {
+            tokio::time::sleep(delay).await;
+        }
+
+        let outcome = self.dispatch(&method, params);
+        let ok = outcome.is_ok();
+        let response = match outcome {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err((code, message)) => {
+                json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}})
+            }
+        };
+        Some((response.to_string(), ok))
+    }
+
+    fn dispatch(&self, method: &str, params: Vec<Value>) -> RpcOutcome {
+        if method == "system.multicall" {
+            let calls = params.first().and_then(|c| c.as_array()).cloned().unwrap_or_default();
+            let results: Vec<Value> = calls
+                .into_iter()
+                .map(|call| {
+                    let name = call.get("methodName").and_then(|m| m.as_str()).unwrap_or_default();
+                    let params = call.get("params").and_then(|p| p.as_array()).cloned().unwrap_or_default();
+                    match self.dispatch(name, params) {
+                        Ok(result) => json!([result]),
+                        Err((code, message)) => json!({"code": code, "message": message}),
+                    }
+                })
+                .collect();
+            return Ok(json!(results));
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.calls.push(method.to_string());
+        // Introspection methods do not take the secret token
+        match method {
+            "system.listMethods" => return Ok(json!(["aria2.addUri", "aria2.tellStatus", "system.multicall"])),
+            "system.listNotifications" => return Ok(json!(["aria2.onDownloadStart", "aria2.onDownloadComplete"])),
+            _ => {}
+        }
+
+        // Every aria2.* method must carry the secret token first
+        let mut params = params.into_iter();
+        let token = params.next();
+        if token.as_ref().and_then(|t| t.as_str()) != Some(format!("token:{}", self.secret).as_str()) {
+            return Err((ERR_GENERIC, "Unauthorized".to_string()));
+        }
+        let params: Vec<Value> = params.collect();
+        state.params.push((method.to_string(), params.clone()));
+
+        if let Some(failure) = state.failures.get_mut(method).and_then(|q| q.pop_front()) {
+            return Err(failure);
+        }
+        if let Some(result) = state.overrides.get(method) {
+            return Ok(result.clone());
+        }
+
+        let method = method.strip_prefix("aria2.").unwrap_or(method);
+        state.handle(method, &params)
+    }
+}
+
+fn not_found(gid: &str) -> (i64, String) {
+    (ERR_GENERIC, format!("GID {} is not found", gid))
+}
+
+fn keys_param(value: Option<&Value>) -> Option<Vec<String>> {
+    value.and_then(|v| serde_json::from_value(v.clone()).ok())
+}
+
+fn window(tasks: Vec<&MockTask>, params: &[Value]) -> Value {
+    let offset = params.first().and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+    let num = params.get(1).and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+    let keys = keys_param(params.get(2));
+    json!(tasks
+        .into_iter()
+        .skip(offset)
+        .take(num)
+        .map(|t| t.to_json(keys.as_deref()))
+        .collect::<Vec<_>>())
+}
+
+impl State {
+    fn find(&mut self, gid: &str) -> Result<&mut MockTask, (i64, String)> {
+        self.tasks.iter_mut().find(|t| t.gid == gid).ok_or_else(|| not_found(gid))
+    }
+
+    /// Take in tasks that stopped or were dropped since the last call
+    fn track_stopped(&mut self) {
+        let now: HashSet<&str> = self.tasks.iter().filter(|t| t.is_stopped()).map(|t| t.gid.as_str()).collect();
+        self.stopped.retain(|gid| now.contains(gid.as_str()));
+        let known: HashSet<String> = self.stopped.iter().cloned().collect();
+        for task in self.tasks.iter().filter(|t| t.is_stopped() && !known.contains(&t.gid)) {
+            self.stopped.push(task.gid.clone());
+            self.stopped_total += 1;
+        }
+    }
+
+    fn handle(&mut self, method: &str, params: &[Value]) -> RpcOutcome {
+        self.track_stopped();
+        let gid_param = params.first().and_then(|v| v.as_str()).unwrap_or_default().to_string();
+        match method {
+            "getVersion" => Ok(json!({"version": "1.37.0", "enabledFeatures": ["BitTorrent", "Metalink"]})),
+            "addUri" => {
+                let uris: Vec<String> = params
+                    .first()
+                    .and_then(|v| serde_json::from_value(v.clone()).ok())
+                    .unwrap_or_default();
+                if uris.is_empty() {
+                    return Err((ERR_GENERIC, "No URI to download.".to_string()));
+                }
+                self.next_gid += 1;
+                let gid = format!("{:016x}", self.next_gid);
+                let mut task = MockTask::new(&gid, "active", &uris[0]);
+                task.uris = uris;
+                if let Some(options) = params.get(1).and_then(|v| v.as_object()) {
+                    if let Some(dir) = options.get("dir").and_then(|v| v.as_str()) {
+                        task.dir = dir.to_string();
+                    }
+                    task.options = options.clone();
+                }
+                self.tasks.push(task);
+                Ok(json!(gid))
+            }
+            "tellStatus" => {
+                let keys = keys_param(params.get(1));
+                Ok(self.find(&gid_param)?.to_json(keys.as_deref()))
+            }
+            "tellActive" => {
+                let keys = keys_param(params.first());
+                Ok(json!(self
+                    .tasks
+                    .iter()
+                    .filter(|t| t.status == "active")
+                    .map(|t| t.to_json(keys.as_deref()))
+                    .collect::<Vec<_>>()))
+            }
+            "tellWaiting" => Ok(window(self.tasks.iter().filter(|t| t.is_waiting()).collect(), params)),
+            "tellStopped" => {
+                let tasks: HashMap<&str, &MockTask> = self.tasks.iter().map(|t| (t.gid.as_str(), t)).collect();
+                Ok(window(self.stopped.iter().filter_map(|gid| tasks.get(gid.as_str()).copied()).collect(), params))
+            }
+            "getGlobalStat" => {
+                let count = |f: fn(&MockTask) -> bool| self.tasks.iter().filter(|t| f(t)).count().to_string();
+                let speed: u64 = self.tasks.iter().map(|t| t.download_speed).sum();
+                Ok(json!({
+                    "downloadSpeed": speed.to_string(),
+                    "uploadSpeed": "0",
+                    "numActive": count(|t| t.status == "active"),
+                    "numWaiting": count(|t| t.is_waiting()),
+                    "numStopped": count(|t| t.is_stopped()),
+                    "numStoppedTotal": self.stopped_total.to_string(),
+                }))
+            }
+            "pause" | "forcePause" => {
+                let task = self.find(&gid_param)?;
+                if task.status != "active" && task.status != "waiting" {
+                    return Err((ERR_GENERIC, format!("GID#{} cannot be paused now", gid_param)));
+                }
+                task.status = "paused".to_string();
+                Ok(json!(gid_param))
+            }
+            "unpause" => {
+                let task = self.find(&gid_param)?;
+                if task.status != "paused" {
+                    return Err((ERR_GENERIC, format!("GID#{} cannot be unpaused now", gid_param)));
+                }
+                task.status = "waiting".to_string();
+                Ok(json!(gid_param))
+            }
+            "remove" | "forceRemove" => {
+                let task = self.find(&gid_param)?;
+                if task.is_stopped() {
+                    return Err(not_found(&gid_param));
+                }
+                task.status = "removed".to_string();
+                Ok(json!(gid_param))
+            }
+            "removeDownloadResult" => {
+                let before = self.tasks.len();
+                self.tasks.retain(|t| !(t.gid == gid_param && t.is_stopped()));
+                if self.tasks.len() == before {
+                    return Err((ERR_GENERIC, format!("Could not remove download result of GID#{}", gid_param)));
+                }
+                Ok(json!("OK"))
+            }
+            "purgeDownloadResult" => {
+                self.tasks.retain(|t| !t.is_stopped());
+                Ok(json!("OK"))
+            }
+            "pauseAll" | "forcePauseAll" => {
+                for task in self.tasks.iter_mut().filter(|t| t.status == "active" || t.status == "waiting") {
+                    task.status = "paused".to_string();
+                }
+                Ok(json!("OK"))
+            }
+            "unpauseAll" => {
+                for task in self.tasks.iter_mut().filter(|t| t.status == "paused") {
+                    task.status = "waiting".to_string();
+                }
+                Ok(json!("OK"))
+            }
+            "changeGlobalOption" => {
+                if let Some(options) = params.first().and_then(|v| v.as_object()) {
+                    self.global_options.extend(options.clone());
+                }
+                Ok(json!("OK"))
+            }
+            "getGlobalOption" => Ok(Value::Object(self.global_options.clone())),
+            "changeOption" => {
+                let options = params.get(1).and_then(|v| v.as_object()).cloned().unwrap_or_default();
+                let task = self.find(&gid_param)?;
+                if let Some(dir) = options.get("dir").and_then(|v| v.as_str()) {
+                    // Like aria2, only a stopped task can move
+                    if task.status == "active" {
+                        return Err((ERR_GENERIC, "dir cannot be changed for an active download".to_string()));
+                    }
+                    task.dir = dir.to_string();
+                }
+                task.options.extend(options);
+                Ok(json!("OK"))
+            }
+            "getOption" => Ok(Value::Object(self.find(&gid_param)?.options.clone())),
+            "getUris" => Ok(self.find(&gid_param)?.uris_json()),
+            "getFiles" => Ok(self.find(&gid_param)?.files_json()),
+            "getServers" => {
+                let task = self.find(&gid_param)?;
+                if task.status != "active" {
+                    return Err((ERR_GENERIC, format!("No active download for GID#{}", gid_param)));
+                }
+                let servers: Vec<Value> = task
+                    .uris
+                    .iter()
+                    .map(|uri| json!({"uri": uri, "currentUri": uri, "downloadSpeed": task.download_speed.to_string()}))
+                    .collect();
+                Ok(json!([{"index": "1", "servers": servers}]))
+            }
+            "changeUri" => {
+                let strings = |value: Option<&Value>| -> Vec<String> {
+                    value.and_then(|v| serde_json::from_value(v.clone()).ok()).unwrap_or_default()
+                };
+                let (del_uris, add_uris) = (strings(params.get(2)), strings(params.get(3)));
+                if params.get(1).and_then(|v| v.as_u64()) != Some(1) {
+                    return Err((ERR_GENERIC, "The given file index is out of range".to_string()));
+                }
+                let task = self.find(&gid_param)?;
+                let before = task.uris.len();
+                task.uris.retain(|uri| !del_uris.contains(uri));
+                let deleted = before - task.uris.len();
+                match params.get(4).and_then(|v| v.as_u64()) {
+                    Some(position) => {
+                        let position = (position as usize).min(task.uris.len());
+                        task.uris.splice(position..position, add_uris.iter().cloned());
+                    }
+                    None => task.uris.extend(add_uris.iter().cloned()),
+                }
+                Ok(json!([deleted, add_uris.len()]))
+            }
+            "getSessionInfo" => Ok(json!({"sessionId": "0123456789abcdef0123456789abcdef01234567"})),
+            "getPeers" => {
+                self.find(&gid_param)?;
+                Ok(json!([]))
+            }
+            "saveSession" | "shutdown" | "forceShutdown" => Ok(json!("OK")),
+            other => Err((ERR_GENERIC, format!("No such method: {}", other))),
+        }
+    }
+}
@@ -0,0 +1,45 @@
+//! Shared helpers for integration tests
+//!
+//! Each test crate uses a different subset of these helpers.
+
+#![allow(dead_code)]
+
+pub mod mock_aria2;
+
+use motrix_lib::aria2::{ClientEvent, ConnectionState};
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+/// Secret used by every mock server
+pub const SECRET: &str = "test-secret";
+
+/// A fresh directory of its own under the system temp dir
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("motrix-{}-{}", name, uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Wait up to ten seconds for the next event matching `pred`
+pub async fn next_event<F>(events: &mut broadcast::Receiver<ClientEvent>, mut pred: F) -> ClientEvent
+where
+    F: FnMut(&ClientEvent) -> bool,
+{
+    tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            match events.recv().await {
+                Ok(event) if pred(&event) => return event,
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => panic!("event channel closed"),
+            }
+        }
+    })
+    .await
+    .expect("timed out waiting for event")
+}
+
+/// Wait for the connection to reach `state`
+pub async fn wait_for_connection(events: &mut broadcast::Receiver<ClientEvent>, state: ConnectionState) {
+    next_event(events, |event| matches!(event, ClientEvent::Connection(s) if *s == state)).await;
+}
@@ -0,0 +1,202 @@
+//! Tests for `motrix-cli` argument parsing and the commands it sends
+
+mod common;
+
+use common::mock_aria2::{MockAria2, MockTask};
+use common::{temp_dir, SECRET};
+use motrix_lib::control::{self, Command, Connection, Target};
+use motrix_lib::duplicate::DuplicateAction;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+fn args(line: &str) -> Vec<String> {
+    line.split_whitespace().map(str::to_string).collect()
+}
+
+fn parse(line: &str) -> motrix_lib::Result<control::Invocation> {
+    control::parse(&args(line), Path::new("/home/user/work"))
+}
+
+async fn run(target: &Target, line: &str) -> motrix_lib::Result<String> {
+    let invocation = parse(line)?;
+    let mut out = Vec::new();
+    control::run(target, invocation.command, invocation.json, &mut out).await?;
+    Ok(String::from_utf8(out).unwrap())
+}
+
+#[test]
+fn add_options_are_parsed_and_paths_resolved() {
+    let invocation = control::parse(
+        &[
+            "--json",
+            "add",
+            "--dir",
+            "builds",
+            "--out",
+            "app.tar.gz",
+            "--header",
+            "Authorization: Bearer xyz",
+            "https://example.com/app.tar.gz",
+            "--engine",
+            "--on-duplicate",
+            "keep-both",
+        ]
+        .map(str::to_string),
+        Path::new("/home/user/work"),
+    )
+    .unwrap();
+    assert!(invocation.json);
+    assert_eq!(invocation.connection, Connection::Engine);
+    let Command::Add {
+        uris,
+        options,
+        on_duplicate,
+    } = invocation.command
+    else {
+        panic!("not an add: {:?}", invocation.command);
+    };
+    assert_eq!(uris, ["https://example.com/app.tar.gz"]);
+    assert_eq!(on_duplicate, Some(DuplicateAction::KeepBoth));
+    assert_eq!(
+        Value::Object(options),
+        json!({
+            "dir": "/home/user/work/builds",
+            "out": "app.tar.gz",
+            "header": ["Authorization: Bearer xyz"],
+        })
+    );
+
+    let invocation = parse("add --dir /srv/files magnet:?xt=urn:btih:abc --config conf/config.json").unwrap();
+    assert_eq!(
+        invocation.config,
+        Some(PathBuf::from("/home/user/work/conf/config.json"))
+    );
+    let Command::Add { options, .. } = invocation.command else {
+        panic!("not an add");
+    };
+    assert_eq!(options["dir"], "/srv/files");
+}
+
+#[test]
+fn bad_command_lines_are_rejected() {
+    for line in [
+        "",
+        "--json",
+        "fetch https://example.com/a",
+        "add",
+        "add --dir",
+        "add --header NoColon https://example.com/a",
+        "add --on-duplicate maybe https://example.com/a",
+        "add --speed 1M https://example.com/a",
+        "list finished",
+        "list active waiting",
+        "pause",
+        "stat now",
+        "limit",
+        "limit --both 1M",
+        "wait",
+        "wait 0000000000000001 --timeout soon",
+    ] {
+        let error = parse(line).unwrap_err();
+        assert!(
+            matches!(error, motrix_lib::Error::Validation(_)),
+            "{:?}: {}",
+            line,
+            error
+        );
+    }
+
+    assert_eq!(parse("list").unwrap().command, Command::List { status: None });
+    assert_eq!(
+        parse("wait 0000000000000001 --timeout 30").unwrap().command,
+        Command::Wait {
+            gid: "0000000000000001".to_string(),
+            timeout: Some(Duration::from_secs(30)),
+        }
+    );
+    assert_eq!(
+        parse("limit --upload 0").unwrap().command,
+        Command::Limit {
+            download: None,
+            upload: Some("0".to_string()),
+        }
+    );
+}
+
+#[tokio::test]
+async fn commands_reach_aria2_directly_or_through_the_app() {
+    let mock = MockAria2::start(SECRET).await;
+    let dir = temp_dir("control");
+    let config = dir.join("config.json");
+    let stored = json!({"config": {"rpcPort": mock.ws_endpoint().port, "rpcSecret": SECRET}});
+    std::fs::write(&config, stored.to_string()).unwrap();
+    assert_eq!(control::stored_config(&config).unwrap().rpc_secret, SECRET);
+    assert!(control::stored_config(&dir.join("missing.json")).is_err());
+
+    // Nothing listens on the socket, so only --app fails
+    let socket = dir.join("motrix.sock");
+    assert!(Target::connect(Connection::App, &socket, Some(&config)).await.is_err());
+    let target = Target::connect(Connection::Auto, &socket, Some(&config)).await.unwrap();
+    assert_eq!(target, Target::Engine);
+
+    let gid = run(&target, "add --out a.iso https://example.com/a.iso").await.unwrap();
+    let task = mock.task(gid.trim()).unwrap();
+    assert_eq!(task.options["out"], "a.iso");
+
+    mock.insert_task(MockTask::new(
+        "00000000000000aa",
+        "complete",
+        "https://example.com/done.zip",
+    ));
+    let table = run(&target, "list").await.unwrap();
+    let lines: Vec<&str> = table.lines().collect();
+    assert!(lines[0].starts_with("GID"));
+    assert!(lines
+        .iter()
+        .any(|line| line.contains("00000000000000aa") && line.ends_with("done.zip")));
+    let tasks: Value = serde_json::from_str(&run(&target, "--json list stopped").await.unwrap()).unwrap();
+    assert_eq!(tasks.as_array().unwrap().len(), 1);
+
+    assert_eq!(run(&target, &format!("pause {}", gid.trim())).await.unwrap(), gid);
+    let stat: Value = serde_json::from_str(&run(&target, "--json stat").await.unwrap()).unwrap();
+    assert!(stat.get("numActive").is_some());
+    assert!(run(&target, "stat").await.unwrap().contains("Download speed:"));
+    assert_eq!(run(&target, "limit --download 2M").await.unwrap(), "");
+    assert_eq!(mock.global_option("max-overall-download-limit"), Some(json!("2M")));
+
+    assert_eq!(
+        run(&target, "wait 00000000000000aa").await.unwrap(),
+        "00000000000000aa  complete  done.zip\n"
+    );
+    let mut failed = MockTask::new("00000000000000bb", "error", "https://example.com/gone.zip");
+    failed.error_code = Some("3".to_string());
+    mock.insert_task(failed);
+    let error = run(&target, "wait 00000000000000bb").await.unwrap_err();
+    assert!(matches!(error, motrix_lib::Error::Download { code: 3, .. }));
+    let error = run(&target, &format!("wait {} --timeout 0", gid.trim()))
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("Timed out"));
+
+    #[cfg(unix)]
+    {
+        use motrix_lib::ipc::{self, IpcRequest};
+
+        let path = socket.clone();
+        tokio::spawn(async move { ipc::serve(&path).await });
+        for _ in 0..50 {
+            if ipc::request_at(&socket, &IpcRequest::Ping).await.is_ok() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let target = Target::connect(Connection::App, &socket, None).await.unwrap();
+        assert_eq!(target, Target::App(socket.clone()));
+        let gid = run(&target, "--json add https://example.com/b.iso").await.unwrap();
+        let gid: Value = serde_json::from_str(&gid).unwrap();
+        assert!(mock.task(gid["gid"].as_str().unwrap()).is_some());
+        let error = run(&target, "remove ffffffffffffffff").await.unwrap_err();
+        assert!(matches!(error, motrix_lib::Error::Custom(_)));
+    }
+}
@@ -0,0 +1,83 @@
+//! Tests for motrix:// deep links
+
+use motrix_lib::deep_link::{self, Confirmation, DeepLink};
+use std::path::PathBuf;
+
+#[test]
+fn links_follow_the_grammar() {
+    let link = deep_link::parse("motrix://add?uri=https%3A%2F%2Fexample.com%2Fa.iso&dir=%2Fsrv%2Fisos&out=debian.iso")
+        .unwrap();
+    assert_eq!(
+        link,
+        DeepLink::Add {
+            uris: vec!["https://example.com/a.iso".to_string()],
+            dir: Some(PathBuf::from("/srv/isos")),
+            out: Some("debian.iso".to_string()),
+        }
+    );
+
+    let link = deep_link::parse("motrix://add?uri=magnet:?xt=urn:btih:abc&uri=https://example.com/b.zip").unwrap();
+    let DeepLink::Add { uris, .. } = link else {
+        panic!("not an add");
+    };
+    assert_eq!(uris.len(), 2);
+
+    assert_eq!(deep_link::parse("MOTRIX://Pause-All").unwrap(), DeepLink::PauseAll);
+    assert_eq!(deep_link::parse("motrix://pause-all/").unwrap(), DeepLink::PauseAll);
+    assert_eq!(
+        deep_link::parse("motrix://open?gid=2089b05ecca3d829").unwrap(),
+        DeepLink::Open {
+            gid: "2089b05ecca3d829".to_string()
+        }
+    );
+
+    // Links from before the grammar wrap a download URL
+    assert_eq!(
+        deep_link::parse("motrix://https://example.com/c.zip").unwrap(),
+        DeepLink::Add {
+            uris: vec!["https://example.com/c.zip".to_string()],
+            dir: None,
+            out: None,
+        }
+    );
+}
+
+#[test]
+fn untrusted_values_are_rejected() {
+    for link in [
+        "https://example.com/a.zip",
+        "motrix://",
+        "motrix://delete-all",
+        "motrix://add",
+        "motrix://add?uri=file:///etc/passwd",
+        "motrix://add?uri=motrix://pause-all",
+        "motrix://add?uri=https://example.com/a&dir=relative/dir",
+        "motrix://add?uri=https://example.com/a&dir=/a&dir=/b",
+        "motrix://add?uri=https://example.com/a&out=../../.bashrc",
+        "motrix://add?uri=https://example.com/a&out=..",
+        "motrix://add?uri=https://example.com/a&uri=https://example.com/b&out=a.zip",
+        // The add dialog would send a page's headers, such as cookies, without showing them
+        "motrix://add?uri=https://example.com/a&header=Cookie:%20session%3Dabc",
+        "motrix://add?uri=https://example.com/a&header=X-Evil%0D%0AHost:%20x",
+        "motrix://add?uri=https://example.com/a&speed=1M",
+        "motrix://add/extra?uri=https://example.com/a",
+        "motrix://pause-all?now=1",
+        "motrix://open",
+        "motrix://open?gid=../../etc",
+        "motrix://open?gid=2089b05ecca3d829&gid=2089b05ecca3d830",
+    ] {
+        assert!(deep_link::parse(link).is_err(), "{}", link);
+    }
+}
+
+#[test]
+fn only_harmless_links_act_without_the_user() {
+    let add = deep_link::parse("motrix://add?uri=https://example.com/a.zip").unwrap();
+    assert_eq!(add.confirmation(), Confirmation::Review);
+    assert_eq!(DeepLink::PauseAll.confirmation(), Confirmation::Ask);
+    let open = deep_link::parse("motrix://open?gid=0000000000000001").unwrap();
+    assert_eq!(open.confirmation(), Confirmation::None);
+
+    assert!(deep_link::is_deep_link("Motrix://open?gid=1"));
+    assert!(!deep_link::is_deep_link("motrix:"));
+}
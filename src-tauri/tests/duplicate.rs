@@ -0,0 +1,90 @@
+//! Tests for duplicate detection when adding downloads
+
+mod common;
+
+use common::mock_aria2::{MockAria2, MockTask};
+use common::SECRET;
+use motrix_lib::aria2::{self, Aria2Client, TaskStatus};
+use motrix_lib::duplicate::{self, info_bytes, magnet_info_hash, Candidate, DuplicateReason};
+use serde_json::json;
+
+const INFO_HASH: &str = "c12fe1c06bba254a9dc9f519b335aa7c1367a88a";
+
+/// A single-file torrent named `c.iso`
+fn torrent() -> Vec<u8> {
+    let mut data = b"d8:announce22:http://tracker.example4:infod6:lengthi5e4:name5:c.iso12:piece lengthi16384e6:pieces20:".to_vec();
+    data.extend([b'a'; 20]);
+    data.extend(b"ee");
+    data
+}
+
+#[test]
+fn info_hashes_come_from_magnets_and_torrents() {
+    let hex = format!("magnet:?xt=urn:btih:{}&dn=c.iso", INFO_HASH.to_uppercase());
+    assert_eq!(magnet_info_hash(&hex).as_deref(), Some(INFO_HASH));
+    let base32 = "magnet:?dn=c.iso&xt=urn:btih:YEX6DQDLXISUVHOJ6UM3GNNKPQJWPKEK";
+    assert_eq!(magnet_info_hash(base32).as_deref(), Some(INFO_HASH));
+    assert_eq!(magnet_info_hash("https://example.com/?xt=urn:btih:abc"), None);
+
+    let data = torrent();
+    assert!(info_bytes(&data).unwrap().starts_with(b"d6:length"));
+    let candidate = Candidate::from_torrent(&data, Some(&json!({"dir": "/data"})));
+    assert_eq!(candidate.info_hash.as_deref(), Some("6d27c63fcd1d2932f633ec1a72764c449a2e9e1b"));
+    assert_eq!((candidate.name.as_deref(), candidate.dir.as_deref()), (Some("c.iso"), Some("/data")));
+    assert_eq!(info_bytes(b"d4:infod4:name"), None);
+}
+
+#[tokio::test]
+async fn existing_tasks_are_found_by_uri_and_info_hash() {
+    let mock = MockAria2::start(SECRET).await;
+    mock.insert_task(MockTask::new("0000000000000001", "waiting", "https://example.com/a.iso"));
+    mock.insert_task(MockTask::new("0000000000000002", "complete", "https://example.com/b.iso"));
+    mock.insert_task(MockTask::new("0000000000000003", "removed", "https://example.com/c.iso"));
+    let mut bt = MockTask::new("0000000000000004", "active", "https://example.com/d.iso");
+    bt.info_hash = Some(INFO_HASH.to_string());
+    mock.insert_task(bt);
+    let client = Aria2Client::new(mock.ws_endpoint()).await.unwrap();
+    let find = |uri: &str| {
+        let candidate = Candidate::from_uris(&[uri.to_string()], None);
+        let client = &client;
+        async move { duplicate::find(client, &candidate).await.unwrap() }
+    };
+
+    let found = find("HTTPS://EXAMPLE.COM/a.iso#top").await.unwrap();
+    assert_eq!((found.reason, found.gid.as_deref()), (DuplicateReason::Uri, Some("0000000000000001")));
+    let found = find("https://example.com/b.iso").await.unwrap();
+    assert_eq!((found.gid.as_deref(), found.status), (Some("0000000000000002"), Some(TaskStatus::Complete)));
+    // Removed on purpose, so fine to download again
+    assert!(find("https://example.com/c.iso").await.is_none());
+
+    let found = find(&format!("magnet:?xt=urn:btih:{}", INFO_HASH)).await.unwrap();
+    assert_eq!((found.reason, found.gid.as_deref()), (DuplicateReason::InfoHash, Some("0000000000000004")));
+}
+
+#[tokio::test]
+async fn existing_files_are_found_in_the_target_folder() {
+    let mock = MockAria2::start(SECRET).await;
+    let client = Aria2Client::new(mock.ws_endpoint()).await.unwrap();
+    let dir = std::env::temp_dir().join(format!("motrix-duplicate-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("file.iso"), "data").unwrap();
+
+    let uris = ["https://example.com/file.iso".to_string()];
+    let options = json!({"dir": dir});
+    let found = duplicate::find(&client, &Candidate::from_uris(&uris, Some(&options))).await.unwrap().unwrap();
+    assert_eq!((found.reason, found.gid), (DuplicateReason::File, None));
+    assert_eq!(found.path.unwrap(), dir.join("file.iso").to_string_lossy());
+
+    let renamed = json!({"dir": dir, "out": "other.iso"});
+    assert!(duplicate::find(&client, &Candidate::from_uris(&uris, Some(&renamed))).await.unwrap().is_none());
+
+    // Without a folder in the options, aria2's global one is used
+    client.change_global_option(json!({"dir": dir})).await.unwrap();
+    assert!(duplicate::find(&client, &Candidate::from_uris(&uris, None)).await.unwrap().is_some());
+
+    // An external daemon's folder is on its own host
+    aria2::set_external_engine(true);
+    let found = duplicate::find(&client, &Candidate::from_uris(&uris, None)).await;
+    aria2::set_external_engine(false);
+    assert!(found.unwrap().is_none());
+}
@@ -0,0 +1,44 @@
+//! Tests for holding launch requests until the app is ready
+
+use motrix_lib::cli::LaunchRequest;
+use motrix_lib::handoff::Pending;
+
+fn request(uri: &str) -> LaunchRequest {
+    LaunchRequest {
+        uris: vec![uri.to_string()],
+        ..LaunchRequest::default()
+    }
+}
+
+#[test]
+fn requests_wait_for_the_frontend_and_the_engine() {
+    let mut pending = Pending::new();
+    assert!(pending.push(request("https://example.com/a.zip")).is_empty());
+    assert!(pending.set_frontend_ready().is_empty());
+    assert!(pending.push(request("https://example.com/b.zip")).is_empty());
+
+    let delivered = pending.set_engine_ready();
+    assert_eq!(
+        delivered,
+        [
+            request("https://example.com/a.zip"),
+            request("https://example.com/b.zip")
+        ]
+    );
+
+    // Delivered once: a reloaded frontend gets nothing again
+    assert!(pending.set_frontend_ready().is_empty());
+    assert!(pending.set_engine_ready().is_empty());
+    assert_eq!(
+        pending.push(request("https://example.com/c.zip")),
+        [request("https://example.com/c.zip")]
+    );
+}
+
+#[test]
+fn readiness_may_come_in_any_order() {
+    let mut pending = Pending::default();
+    assert!(pending.set_engine_ready().is_empty());
+    assert!(pending.push(request("https://example.com/a.zip")).is_empty());
+    assert_eq!(pending.set_frontend_ready().len(), 1);
+}
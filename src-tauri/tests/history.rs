@@ -0,0 +1,176 @@
+//! Tests for the persistent download history
+
+mod common;
+
+use common::mock_aria2::MockAria2;
+use common::SECRET;
+use motrix_lib::aria2::{Aria2Client, Options, Task, TaskStatus};
+use motrix_lib::browser::BrowserDownload;
+use motrix_lib::history::{self, ExportFormat, History, HistoryQuery};
+use serde_json::json;
+use std::path::PathBuf;
+
+/// A fresh history file path in its own temporary directory
+fn history_path() -> PathBuf {
+    std::env::temp_dir()
+        .join(format!("motrix-history-{}", uuid::Uuid::new_v4()))
+        .join("history.json")
+}
+
+fn task(gid: &str, status: &str, uri: &str) -> Task {
+    let name = uri.rsplit('/').next().unwrap();
+    serde_json::from_value(json!({
+        "gid": gid,
+        "status": status,
+        "totalLength": "2048",
+        "completedLength": if status == "complete" { "2048" } else { "512" },
+        "dir": "/downloads",
+        "files": [{
+            "index": "1",
+            "path": format!("/downloads/{}", name),
+            "length": "2048",
+            "completedLength": "512",
+            "selected": "true",
+            "uris": [{"uri": uri, "status": "used"}, {"uri": uri, "status": "waiting"}],
+        }],
+    }))
+    .unwrap()
+}
+
+#[test]
+fn entries_follow_tasks_and_survive_reopening() {
+    let path = history_path();
+    let mut history = History::open(path.clone()).unwrap();
+    assert!(history.entries().is_empty());
+
+    let options = Options::from([("split".to_string(), "4".to_string())]);
+    history.record(&task("0000000000000001", "active", "https://example.com/a.iso"), options, 1_000);
+    history.record(&task("0000000000000001", "complete", "https://example.com/a.iso"), Options::new(), 5_000);
+    history.save().unwrap();
+
+    let history = History::open(path).unwrap();
+    let entry = history.get("0000000000000001").unwrap();
+    assert_eq!(entry.name, "a.iso");
+    assert_eq!(entry.uris, ["https://example.com/a.iso"]);
+    assert_eq!(entry.files, ["/downloads/a.iso"]);
+    assert_eq!(entry.status, TaskStatus::Complete);
+    assert_eq!(entry.completed_length, 2048);
+    // Options are kept from the first sighting
+    assert_eq!(entry.options["split"], "4");
+    assert_eq!((entry.added_at, entry.finished_at), (1_000, Some(5_000)));
+}
+
+#[test]
+fn browser_cookies_and_credentials_are_not_persisted() {
+    let path = history_path();
+    let download: BrowserDownload = serde_json::from_value(json!({
+        "url": "https://example.com/a.iso",
+        "cookies": "session=abc",
+        "headers": {"Authorization": "Bearer xyz"},
+        "referer": "https://example.com/",
+    }))
+    .unwrap();
+    // As aria2's getOption reports them, along with credentials given as options
+    let mut options: Options = download
+        .options()
+        .unwrap()
+        .as_object()
+        .unwrap()
+        .iter()
+        .map(|(key, value)| match value {
+            serde_json::Value::Array(lines) => {
+                let lines: Vec<&str> = lines.iter().filter_map(|line| line.as_str()).collect();
+                (key.clone(), lines.join("\n"))
+            }
+            value => (key.clone(), value.as_str().unwrap().to_string()),
+        })
+        .collect();
+    options.insert("http-user".to_string(), "me".to_string());
+    options.insert("ftp-passwd".to_string(), "secret".to_string());
+
+    let mut history = History::open(path.clone()).unwrap();
+    history.record(&task("0000000000000001", "complete", "https://example.com/a.iso"), options, 1);
+    history.save().unwrap();
+
+    let history = History::open(path.clone()).unwrap();
+    let entry = history.get("0000000000000001").unwrap();
+    assert_eq!(entry.options, Options::from([("referer".to_string(), "https://example.com/".to_string())]));
+    let saved = std::fs::read_to_string(&path).unwrap();
+    assert!(!saved.contains("session=abc") && !saved.contains("Bearer") && !saved.contains("secret"));
+}
+
+#[test]
+fn oldest_finished_entries_are_dropped_beyond_the_limit() {
+    let mut history = History::open(history_path()).unwrap();
+    history.record(&task("0000000000000001", "active", "https://example.com/1.iso"), Options::new(), 1);
+    for i in 2..=5 {
+        let gid = format!("{:016x}", i);
+        history.record(&task(&gid, "complete", &format!("https://example.com/{}.iso", i)), Options::new(), i);
+    }
+    history.set_limit(3);
+
+    // The unfinished task stays however old it is
+    let gids: Vec<&str> = history.entries().iter().map(|entry| entry.gid.as_str()).collect();
+    assert_eq!(gids, ["0000000000000001", "0000000000000004", "0000000000000005"]);
+    history.record(&task("0000000000000006", "complete", "https://example.com/6.iso"), Options::new(), 6);
+    assert!(history.get("0000000000000004").is_none());
+    assert_eq!(history.get("0000000000000006").unwrap().name, "6.iso");
+    assert_eq!(history.entries().len(), 3);
+}
+
+#[test]
+fn search_filters_and_pages_newest_first() {
+    let mut history = History::open(history_path()).unwrap();
+    history.record(&task("0000000000000001", "complete", "https://example.com/movie.mkv"), Options::new(), 1);
+    history.record(&task("0000000000000002", "error", "https://example.com/song.mp3"), Options::new(), 2);
+    history.record(&task("0000000000000003", "complete", "https://mirror.example.com/Movie-2.mkv"), Options::new(), 3);
+    history.mark_removed("0000000000000002", 4);
+
+    let page = history.search(&HistoryQuery {
+        search: Some("movie".into()),
+        limit: Some(1),
+        ..HistoryQuery::default()
+    });
+    assert_eq!(page.total, 2);
+    assert_eq!(page.entries[0].gid, "0000000000000003");
+
+    let page = history.search(&HistoryQuery {
+        status: vec![TaskStatus::Error],
+        ..HistoryQuery::default()
+    });
+    // Already stopped entries keep their final status
+    assert_eq!(page.total, 1);
+}
+
+#[test]
+fn export_writes_csv_and_json() {
+    let mut history = History::open(history_path()).unwrap();
+    history.record(&task("0000000000000001", "complete", "https://example.com/a,b.iso"), Options::new(), 1);
+
+    let dir = history_path().parent().unwrap().to_path_buf();
+    std::fs::create_dir_all(&dir).unwrap();
+
+    history.export(&dir.join("history.csv"), ExportFormat::Csv).unwrap();
+    let csv = std::fs::read_to_string(dir.join("history.csv")).unwrap();
+    let mut lines = csv.lines();
+    assert!(lines.next().unwrap().starts_with("gid,name,status"));
+    assert!(lines.next().unwrap().starts_with("0000000000000001,\"a,b.iso\",complete,2048,2048"));
+
+    history.export(&dir.join("history.json"), ExportFormat::Json).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&std::fs::read(dir.join("history.json")).unwrap()).unwrap();
+    assert_eq!(json[0]["gid"], "0000000000000001");
+}
+
+#[tokio::test]
+async fn readd_uses_recorded_uris_and_options() {
+    let mock = MockAria2::start(SECRET).await;
+    let client = Aria2Client::new(mock.ws_endpoint()).await.unwrap();
+    let mut history = History::open(history_path()).unwrap();
+    let options = Options::from([("dir".to_string(), "/data".to_string())]);
+    history.record(&task("0000000000000001", "error", "https://example.com/a.iso"), options, 1);
+
+    let gid = history::readd(&client, history.get("0000000000000001").unwrap()).await.unwrap();
+    let readded = mock.task(&gid).unwrap();
+    assert_eq!(readded.uris, ["https://example.com/a.iso"]);
+    assert_eq!(readded.dir, "/data");
+}
@@ -0,0 +1,148 @@
+//! Tests for the native messaging host and the IPC it forwards over
+
+mod common;
+
+use common::temp_dir;
+use motrix_lib::native_host::{self, Browser, ExtensionIds, HOST_NAME};
+use serde_json::{json, Value};
+use std::io::Cursor;
+use std::path::Path;
+
+#[test]
+fn messages_are_length_prefixed_json() {
+    let mut buf = Vec::new();
+    native_host::write_message(&mut buf, &json!({"type": "ping"})).unwrap();
+    native_host::write_message(&mut buf, &json!({"gid": "0000000000000001"})).unwrap();
+    assert_eq!(&buf[..4], &15u32.to_ne_bytes());
+
+    let mut reader = Cursor::new(buf);
+    let first = native_host::read_message(&mut reader).unwrap().unwrap();
+    assert_eq!(serde_json::from_slice::<Value>(&first).unwrap(), json!({"type": "ping"}));
+    assert!(native_host::read_message(&mut reader).unwrap().is_some());
+    assert!(native_host::read_message(&mut reader).unwrap().is_none());
+
+    let mut huge = Cursor::new(u32::MAX.to_ne_bytes().to_vec());
+    assert!(native_host::read_message(&mut huge).is_err());
+}
+
+#[test]
+fn manifests_go_to_the_browsers_found() {
+    let home = temp_dir("native-home");
+    std::fs::create_dir_all(home.join(".config/chromium")).unwrap();
+    std::fs::create_dir_all(home.join(".mozilla")).unwrap();
+    let host = Path::new("/opt/motrix/motrix-native-host");
+    let ids = ExtensionIds {
+        chromium: vec!["abcdefghijklmnopabcdefghijklmnop".to_string()],
+        firefox: vec!["motrix@example.com".to_string()],
+    };
+
+    assert_eq!(Browser::Chrome.manifest_dir(&home), None);
+    let written = native_host::install(&home, host, &ids).unwrap();
+    let chromium = home.join(format!(".config/chromium/NativeMessagingHosts/{}.json", HOST_NAME));
+    let firefox = home.join(format!(".mozilla/native-messaging-hosts/{}.json", HOST_NAME));
+    assert_eq!(written, [chromium.clone(), firefox.clone()]);
+
+    let manifest: Value = serde_json::from_str(&std::fs::read_to_string(&chromium).unwrap()).unwrap();
+    assert_eq!(manifest["path"], "/opt/motrix/motrix-native-host");
+    assert_eq!(manifest["type"], "stdio");
+    assert_eq!(manifest["allowed_origins"], json!(["chrome-extension://abcdefghijklmnopabcdefghijklmnop/"]));
+    let manifest: Value = serde_json::from_str(&std::fs::read_to_string(&firefox).unwrap()).unwrap();
+    assert_eq!(manifest["allowed_extensions"], json!(["motrix@example.com"]));
+
+    // Browsers without an allowed extension get no manifest
+    let firefox_only = ExtensionIds {
+        firefox: ids.firefox.clone(),
+        ..ExtensionIds::default()
+    };
+    assert!(native_host::manifest(Browser::Chromium, host, &firefox_only).is_none());
+    assert!(native_host::install(&home, Path::new("relative/host"), &ids).is_err());
+
+    assert_eq!(native_host::uninstall(&home).unwrap(), [chromium.clone(), firefox]);
+    assert!(!chromium.exists());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn messages_reach_the_running_app() {
+    use common::mock_aria2::MockAria2;
+    use common::SECRET;
+    use motrix_lib::aria2::{self, Aria2Client};
+    use motrix_lib::ipc::{self, IpcRequest};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let mock = MockAria2::start(SECRET).await;
+    let client = Aria2Client::new(mock.ws_endpoint()).await.unwrap();
+    aria2::set_client(Some(Arc::new(client))).await;
+    let socket = temp_dir("ipc").join("motrix.sock");
+
+    // Nobody listening yet
+    let reply = native_host::handle(br#"{"type": "ping"}"#, &socket).await;
+    assert_eq!(reply["error"]["kind"], "disconnected");
+
+    let path = socket.clone();
+    tokio::spawn(async move { ipc::serve(&path).await });
+    for _ in 0..50 {
+        if ipc::request_at(&socket, &IpcRequest::Ping).await.is_ok() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    let reply = native_host::handle(br#"{"type": "ping"}"#, &socket).await;
+    assert_eq!(reply["version"], env!("CARGO_PKG_VERSION"));
+    // A second instance must not take the socket over
+    assert!(ipc::serve(&socket).await.is_err());
+
+    let message = json!({
+        "type": "download",
+        "url": "thunder://QUFodHRwczovL2V4YW1wbGUuY29tL2EuaXNvWlo=",
+        "cookies": "session=abc",
+    });
+    let reply = native_host::handle(message.to_string().as_bytes(), &socket).await;
+    let task = mock.task(reply["gid"].as_str().unwrap()).unwrap();
+    assert_eq!(task.uris, ["https://example.com/a.iso"]);
+    assert_eq!(task.options["header"], json!(["Cookie: session=abc"]));
+
+    let reply = native_host::handle(br#"{"type": "download", "url": "file:///etc/passwd"}"#, &socket).await;
+    assert_eq!(reply["error"]["kind"], "validation");
+    let reply = native_host::handle(b"not json", &socket).await;
+    assert_eq!(reply["error"]["kind"], "json");
+
+    // A line without end is cut off at the limit, answered once and the connection closed
+    let (mut reader, mut writer) = tokio::net::UnixStream::connect(&socket).await.unwrap().into_split();
+    let flood = tokio::spawn(async move { writer.write_all(&vec![b'x'; 6 * 1024 * 1024]).await });
+    let mut reply = String::new();
+    reader.read_to_string(&mut reply).await.unwrap();
+    assert_eq!(reply.lines().count(), 1, "{}", reply);
+    assert!(reply.contains("Request too large"), "{}", reply);
+    let _ = flood.await;
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn without_a_runtime_dir_the_socket_is_in_a_private_dir() {
+    use motrix_lib::ipc::{self, IpcRequest};
+    use std::os::unix::fs::PermissionsExt;
+
+    std::env::remove_var(ipc::SOCKET_ENV);
+    std::env::remove_var("XDG_RUNTIME_DIR");
+    let socket = ipc::socket_path();
+    let dir = socket.parent().unwrap().to_path_buf();
+    // Not the temp dir every user shares
+    assert_eq!(dir.parent(), Some(std::env::temp_dir().as_path()));
+    assert!(dir.file_name().unwrap().to_string_lossy().starts_with("motrix-"));
+
+    // One left readable by others is closed up again
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let path = socket.clone();
+    tokio::spawn(async move { ipc::serve(&path).await });
+    for _ in 0..50 {
+        if ipc::request_at(&socket, &IpcRequest::Ping).await.is_ok() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert!(ipc::request_at(&socket, &IpcRequest::Ping).await.is_ok());
+    assert_eq!(std::fs::metadata(&dir).unwrap().permissions().mode() & 0o777, 0o700);
+}
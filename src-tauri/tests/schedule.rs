@@ -0,0 +1,159 @@
+//! Tests for the weekly speed schedule and download windows
+
+mod common;
+
+use common::mock_aria2::{MockAria2, MockTask};
+use common::SECRET;
+use motrix_lib::aria2::Aria2Client;
+use motrix_lib::config::{
+    parse_time, AppConfig, DownloadWindows, SpeedProfile, SpeedRule, SpeedSchedule, TimeWindow, Weekday,
+};
+use motrix_lib::schedule::{effective, enforce_window, SpeedOverride, WindowState};
+use serde_json::json;
+
+fn window(days: &[Weekday], start: &str, end: &str) -> TimeWindow {
+    TimeWindow {
+        days: days.to_vec(),
+        start: start.to_string(),
+        end: end.to_string(),
+    }
+}
+
+fn profile(name: &str, download_limit: &str) -> SpeedProfile {
+    SpeedProfile {
+        name: name.to_string(),
+        download_limit: download_limit.to_string(),
+        upload_limit: "0".to_string(),
+    }
+}
+
+/// Limited to 2M on weekdays 07:00–22:00, unlimited nights and weekends
+fn office_config() -> AppConfig {
+    let weekdays = [Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri];
+    AppConfig {
+        max_overall_download_limit: "0".to_string(),
+        speed_schedule: SpeedSchedule {
+            enabled: true,
+            profiles: vec![profile("work", "2M"), profile("slow", "100K")],
+            rules: vec![SpeedRule {
+                window: window(&weekdays, "07:00", "22:00"),
+                profile: "work".to_string(),
+            }],
+        },
+        ..AppConfig::default()
+    }
+}
+
+#[test]
+fn parses_times() {
+    assert_eq!(parse_time("00:00"), Some(0));
+    assert_eq!(parse_time("7:30"), Some(450));
+    assert_eq!(parse_time("23:59"), Some(1439));
+    assert_eq!(parse_time("24:00"), None);
+    assert_eq!(parse_time("12:60"), None);
+    assert_eq!(parse_time("noon"), None);
+}
+
+#[test]
+fn windows_wrap_past_midnight() {
+    let night = window(&[Weekday::Fri], "23:00", "06:00");
+    assert!(night.contains(Weekday::Fri, parse_time("23:30").unwrap()));
+    // The early hours belong to the window that started the day before
+    assert!(night.contains(Weekday::Sat, parse_time("05:59").unwrap()));
+    assert!(!night.contains(Weekday::Sat, parse_time("06:00").unwrap()));
+    assert!(!night.contains(Weekday::Fri, parse_time("05:00").unwrap()));
+
+    let all_day = window(&[], "00:00", "00:00");
+    assert!(all_day.contains(Weekday::Sun, 0));
+    assert!(all_day.contains(Weekday::Wed, 1439));
+}
+
+#[test]
+fn office_hours_use_the_work_profile() {
+    let config = office_config();
+    let schedule = &config.speed_schedule;
+    assert_eq!(schedule.profile_at(Weekday::Tue, parse_time("09:00").unwrap()).unwrap().name, "work");
+    assert!(schedule.profile_at(Weekday::Tue, parse_time("22:00").unwrap()).is_none());
+    assert!(schedule.profile_at(Weekday::Sat, parse_time("09:00").unwrap()).is_none());
+
+    let state = effective(&config, Weekday::Tue, parse_time("09:00").unwrap(), None);
+    assert_eq!((state.profile.as_deref(), state.download_limit.as_str()), (Some("work"), "2M"));
+    let state = effective(&config, Weekday::Sat, parse_time("09:00").unwrap(), None);
+    assert_eq!((state.profile, state.download_limit.as_str()), (None, "0"));
+
+    let mut disabled = config.clone();
+    disabled.speed_schedule.enabled = false;
+    assert!(disabled.speed_schedule.profile_at(Weekday::Tue, parse_time("09:00").unwrap()).is_none());
+}
+
+#[test]
+fn overrides_end_when_the_schedule_moves_on() {
+    let config = office_config();
+    let over = SpeedOverride {
+        profile: "slow".to_string(),
+        until: None,
+        scheduled: Some("work".to_string()),
+    };
+    let state = effective(&config, Weekday::Tue, parse_time("09:00").unwrap(), Some(&over));
+    assert_eq!((state.profile.as_deref(), state.overridden), (Some("slow"), true));
+    assert!(over.is_active(Some("work"), 0));
+    assert!(!over.is_active(None, 0));
+
+    let timed = SpeedOverride { until: Some(1_000), ..over };
+    assert!(timed.is_active(None, 999));
+    assert!(!timed.is_active(Some("work"), 1_000));
+}
+
+#[test]
+fn schedule_round_trips_through_config_json() {
+    let config = office_config();
+    let value = serde_json::to_value(&config).unwrap();
+    assert_eq!(
+        value["speedSchedule"]["rules"][0],
+        json!({"days": ["mon", "tue", "wed", "thu", "fri"], "start": "07:00", "end": "22:00", "profile": "work"})
+    );
+    let back: AppConfig = serde_json::from_value(value).unwrap();
+    assert_eq!(back.speed_schedule, config.speed_schedule);
+}
+
+#[test]
+fn download_windows_are_open_when_disabled() {
+    let mut windows = DownloadWindows {
+        enabled: false,
+        windows: vec![window(&[], "01:00", "07:00")],
+    };
+    assert!(windows.is_open(Weekday::Mon, parse_time("12:00").unwrap()));
+    windows.enabled = true;
+    assert!(!windows.is_open(Weekday::Mon, parse_time("12:00").unwrap()));
+    assert!(windows.is_open(Weekday::Mon, parse_time("03:00").unwrap()));
+}
+
+#[tokio::test]
+async fn closed_window_holds_tasks_until_it_opens() {
+    let mock = MockAria2::start(SECRET).await;
+    mock.insert_task(MockTask::new("0000000000000001", "active", "https://example.com/a.iso"));
+    mock.insert_task(MockTask::new("0000000000000002", "waiting", "https://example.com/b.iso"));
+    mock.insert_task(MockTask::new("0000000000000003", "paused", "https://example.com/c.iso"));
+    mock.insert_task(MockTask::new("0000000000000004", "active", "https://example.com/d.iso"));
+    let client = Aria2Client::new(mock.ws_endpoint()).await.unwrap();
+    let mut state = WindowState {
+        ignored: vec!["0000000000000004".to_string()],
+        held: vec![],
+    };
+
+    assert!(enforce_window(&client, false, &mut state).await.unwrap());
+    assert_eq!(state.held, ["0000000000000001", "0000000000000002"]);
+    let status = |gid: &str| mock.task(gid).unwrap().status;
+    assert_eq!(status("0000000000000001"), "paused");
+    assert_eq!(status("0000000000000002"), "paused");
+    // Flagged tasks keep running
+    assert_eq!(status("0000000000000004"), "active");
+    assert!(!enforce_window(&client, false, &mut state).await.unwrap());
+
+    assert!(enforce_window(&client, true, &mut state).await.unwrap());
+    assert!(state.held.is_empty());
+    assert_eq!(status("0000000000000001"), "waiting");
+    assert_eq!(status("0000000000000002"), "waiting");
+    // Paused by the user, so not ours to resume
+    assert_eq!(status("0000000000000003"), "paused");
+}
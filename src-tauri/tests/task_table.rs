@@ -0,0 +1,137 @@
+//! Tests for the cached task table behind `task-delta`
+
+mod common;
+
+use common::mock_aria2::{MockAria2, MockTask};
+use common::SECRET;
+use motrix_lib::aria2::{Aria2Client, TaskStatus, TaskTable};
+use serde_json::json;
+use std::time::Duration;
+
+async fn setup() -> (MockAria2, Aria2Client) {
+    let mock = MockAria2::start(SECRET).await;
+    mock.insert_task(MockTask::new("0000000000000001", "active", "https://example.com/a"));
+    mock.insert_task(MockTask::new("0000000000000002", "waiting", "https://example.com/b"));
+    mock.insert_task(MockTask::new("0000000000000003", "complete", "https://example.com/c"));
+    let client = Aria2Client::new(mock.ws_endpoint()).await.unwrap();
+    // Let the reconnect tracker take its baseline before counting calls
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    (mock, client)
+}
+
+#[tokio::test]
+async fn first_refresh_adds_everything() {
+    let (_mock, client) = setup().await;
+    let mut table = TaskTable::default();
+
+    let delta = table.refresh(&client).await.unwrap();
+    assert!(delta.reset);
+    assert_eq!(delta.added.len(), 3);
+    assert!(delta.updated.is_empty() && delta.removed.is_empty());
+    assert_eq!(delta.stat.unwrap().num_active, 1);
+    assert!(table.is_busy());
+
+    assert!(table.refresh(&client).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn updates_carry_only_changed_fields() {
+    let (mock, client) = setup().await;
+    let mut table = TaskTable::default();
+    table.refresh(&client).await.unwrap();
+
+    mock.update("0000000000000001", |task| {
+        task.download_speed = 4096;
+        task.completed_length = 512;
+    });
+    let delta = table.refresh(&client).await.unwrap();
+
+    assert_eq!(delta.updated.len(), 1);
+    let change = &delta.updated[0];
+    assert_eq!(change["gid"], "0000000000000001");
+    assert_eq!(change["downloadSpeed"], 4096);
+    assert_eq!(change["completedLength"], 512);
+    assert!(!change.contains_key("status"));
+    // The files entry changed too, since it carries the completed length
+    assert_eq!(change.len(), 4);
+    assert_eq!(delta.stat.unwrap().download_speed, 4096);
+}
+
+#[tokio::test]
+async fn stopped_tasks_are_fetched_only_when_counters_move() {
+    let (mock, client) = setup().await;
+    let mut table = TaskTable::default();
+    table.refresh(&client).await.unwrap();
+
+    mock.clear_calls();
+    mock.update("0000000000000001", |task| task.download_speed = 1);
+    table.refresh(&client).await.unwrap();
+    assert!(!mock.calls().iter().any(|call| call == "aria2.tellStopped"));
+    assert_eq!(table.len(), 3);
+
+    // Completing a task moves the counters, so the stopped list is read again
+    mock.set_status("0000000000000001", "complete");
+    let delta = table.refresh(&client).await.unwrap();
+    assert!(mock.calls().iter().any(|call| call == "aria2.tellStopped"));
+    assert!(delta.added.is_empty() && delta.removed.is_empty());
+    assert_eq!(delta.updated[0]["status"], json!(TaskStatus::Complete));
+}
+
+#[tokio::test]
+async fn only_newly_stopped_records_are_fetched() {
+    let (mock, client) = setup().await;
+    for i in 0..50 {
+        let gid = format!("{:016x}", 0x100 + i);
+        mock.insert_task(MockTask::new(&gid, "complete", "https://example.com/old"));
+    }
+    let mut table = TaskTable::default();
+    table.refresh(&client).await.unwrap();
+    assert_eq!(table.len(), 53);
+
+    // The record that just stopped is the last of 52, fetched alone with a key list
+    mock.clear_calls();
+    mock.set_status("0000000000000001", "complete");
+    let delta = table.refresh(&client).await.unwrap();
+    let stopped = mock.call_params("aria2.tellStopped");
+    assert_eq!(stopped.len(), 1);
+    assert_eq!(stopped[0][..2], [json!(51), json!(1)]);
+    assert!(stopped[0][2].as_array().unwrap().contains(&json!("files")));
+    assert!(!stopped[0][2].as_array().unwrap().contains(&json!("bitfield")));
+    assert_eq!(delta.updated[0]["status"], json!(TaskStatus::Complete));
+    assert_eq!(table.snapshot().added.last().unwrap().gid, "0000000000000001");
+
+    // A purge shows in the counters; only the gids left are listed
+    mock.clear_calls();
+    client.remove_download_result("0000000000000110").await.unwrap();
+    let delta = table.refresh(&client).await.unwrap();
+    assert_eq!(delta.removed, ["0000000000000110"]);
+    let stopped = mock.call_params("aria2.tellStopped");
+    assert_eq!(stopped, [vec![json!(0), json!(51), json!(["gid"])]]);
+    assert_eq!(table.len(), 52);
+}
+
+#[tokio::test]
+async fn purged_records_are_removed() {
+    let (_mock, client) = setup().await;
+    let mut table = TaskTable::default();
+    table.refresh(&client).await.unwrap();
+
+    client.remove_download_result("0000000000000003").await.unwrap();
+    let delta = table.refresh(&client).await.unwrap();
+    assert_eq!(delta.removed, ["0000000000000003"]);
+    assert_eq!(table.len(), 2);
+}
+
+#[tokio::test]
+async fn snapshot_keeps_aria2_order() {
+    let (_mock, client) = setup().await;
+    let mut table = TaskTable::default();
+    table.refresh(&client).await.unwrap();
+
+    let snapshot = table.snapshot();
+    assert!(snapshot.reset);
+    let gids: Vec<_> = snapshot.added.iter().map(|task| task.gid.as_str()).collect();
+    assert_eq!(gids, ["0000000000000001", "0000000000000002", "0000000000000003"]);
+    assert_eq!(snapshot.added[0].status, TaskStatus::Active);
+    assert_eq!(snapshot.stat.unwrap().num_stopped, 1);
+}
@@ -5,13 +5,21 @@ import { invoke } from '@tauri-apps/api/core'
 import { useTaskStore } from '@/stores/task'
 import { useAppStore } from '@/stores/app'
 import { ElNotification } from 'element-plus'
-import { getTaskName } from '@/utils'
+import { exitCodeMessage, getTaskName } from '@/utils'
+import type { ChecksumReport, GlobalStat, TaskDelta } from '@/types'
 
 export interface Aria2Event {
   eventType: 'download_start' | 'download_pause' | 'download_stop' | 'download_complete' | 'download_error' | 'bt_download_complete'
   gid: string
 }
 
+export type EngineState =
+  | { state: 'running' }
+  | { state: 'crashed'; code: number | null; signal: number | null }
+  | { state: 'restarting'; attempt: number; delayMs: number }
+  | { state: 'failed'; code: number | null }
+  | { state: 'stopped' }
+
 export function useAria2Events() {
   const { t } = useI18n()
   const taskStore = useTaskStore()
@@ -44,20 +52,14 @@ export function useAria2Events() {
     }
   }
 
+  // Task lists follow 'task-delta'; these events only drive side effects
   async function handleAria2Event(event: Aria2Event) {
     switch (event.eventType) {
       case 'download_start':
-        taskStore.fetchTasks('active')
         invoke('prevent_sleep').catch(() => {})
         break
 
-      case 'download_pause':
-        taskStore.fetchTasks('active')
-        break
-
       case 'download_stop':
-        taskStore.fetchTasks('active')
-        taskStore.fetchTasks('stopped')
         checkAndAllowSleep()
         break
 
@@ -74,9 +76,6 @@ export function useAria2Events() {
             ElNotification({ title: t('task.completed'), message: event.gid, type: 'success', duration: 5000 })
           }
         }
-        taskStore.fetchTasks('active')
-        await taskStore.fetchTasks('stopped')
-        taskStore.fetchGlobalStat()
         // Auto clear completed task record if configured (delay to let UI show it)
         if (appStore.config?.autoClearCompleted) {
           setTimeout(() => {
@@ -90,15 +89,14 @@ export function useAria2Events() {
         try {
           const info = await invoke<Record<string, unknown>>('get_task_info', { gid: event.gid })
           const name = getTaskName(info as { files?: { path?: string }[]; bittorrent?: { info?: { name?: string } } })
-          const errorMsg = info.errorMessage ? `: ${info.errorMessage}` : ''
+          const reason = (info.errorMessage as string | undefined) || exitCodeMessage(info.errorCode as string | undefined, t)
+          const errorMsg = reason ? `: ${reason}` : ''
           sendSystemNotification(t('task.error'), `${name}${errorMsg}`)
           ElNotification({ title: t('task.error'), message: `${name}${errorMsg}`, type: 'error', duration: 8000 })
         } catch {
           sendSystemNotification(t('task.error'), event.gid)
           ElNotification({ title: t('task.error'), message: event.gid, type: 'error', duration: 8000 })
         }
-        taskStore.fetchTasks('active')
-        taskStore.fetchTasks('stopped')
         checkAndAllowSleep()
         break
     }
@@ -106,8 +104,8 @@ export function useAria2Events() {
 
   async function checkAndAllowSleep() {
     try {
-      const stat = await invoke<{ numActive: string }>('get_global_stat')
-      if (parseInt(stat.numActive || '0') === 0) {
+      const stat = await invoke<GlobalStat>('get_global_stat')
+      if (stat.numActive === 0) {
         invoke('allow_sleep').catch(() => {})
       }
     } catch {
@@ -115,6 +113,19 @@ export function useAria2Events() {
     }
   }
 
+  let unlistenTasks: UnlistenFn | null = null
+
+  async function setupTaskListener() {
+    try {
+      unlistenTasks = await listen<TaskDelta>('task-delta', (event) => {
+        taskStore.applyDelta(event.payload)
+      })
+      await taskStore.loadTasks()
+    } catch (error) {
+      console.error('Failed to setup task delta listener:', error)
+    }
+  }
+
   let unlistenConnection: UnlistenFn | null = null
 
   async function setupConnectionListener() {
@@ -131,18 +142,87 @@ export function useAria2Events() {
     }
   }
 
+  let unlistenEngine: UnlistenFn | null = null
+  let engineCrashed = false
+
+  async function setupEngineListener() {
+    try {
+      unlistenEngine = await listen<EngineState>('engine-state', (event) => {
+        const payload = event.payload
+        switch (payload.state) {
+          case 'crashed':
+            engineCrashed = true
+            ElNotification({
+              title: t('engine.crashed'),
+              message: t('engine.exitCode', { code: payload.code ?? payload.signal ?? '?' }),
+              type: 'warning',
+              duration: 5000,
+            })
+            break
+          case 'failed':
+            sendSystemNotification(t('engine.failed'), t('engine.exitCode', { code: payload.code ?? '?' }))
+            ElNotification({ title: t('engine.failed'), message: t('engine.restartApp'), type: 'error', duration: 0 })
+            break
+          case 'running':
+            if (engineCrashed) {
+              engineCrashed = false
+              ElNotification({ title: t('engine.restarted'), message: '', type: 'success', duration: 3000 })
+              taskStore.fetchTasks()
+              taskStore.fetchGlobalStat()
+            }
+            break
+        }
+      })
+    } catch {
+      // ignore
+    }
+  }
+
+  let unlistenChecksum: UnlistenFn | null = null
+
+  async function setupChecksumListener() {
+    try {
+      unlistenChecksum = await listen<ChecksumReport>('task-checksum', (event) => {
+        const report = event.payload
+        const file = report.files.find((f) => f.status === report.status)
+        const name = file?.path.split(/[\\/]/).pop() || report.gid
+        if (report.status === 'mismatch') {
+          const message = t('checksum.mismatchDetail', { name })
+          sendSystemNotification(t('checksum.mismatch'), message)
+          ElNotification({ title: t('checksum.mismatch'), message, type: 'error', duration: 0 })
+        } else if (report.status === 'verified') {
+          ElNotification({ title: t('checksum.verified'), message: name, type: 'success', duration: 3000 })
+        }
+      })
+    } catch {
+      // ignore
+    }
+  }
+
   onMounted(() => {
     setupEventListener()
+    setupTaskListener()
     setupConnectionListener()
+    setupEngineListener()
+    setupChecksumListener()
   })
 
   onUnmounted(() => {
     if (unlisten) {
       unlisten()
     }
+    if (unlistenTasks) {
+      unlistenTasks()
+    }
     if (unlistenConnection) {
       unlistenConnection()
     }
+    if (unlistenEngine) {
+      unlistenEngine()
+    }
+    if (unlistenChecksum) {
+      unlistenChecksum()
+    }
   })
 
   return {
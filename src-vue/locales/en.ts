@@ -40,6 +40,8 @@ export default {
     openFile: 'Open File',
     showInFolder: 'Show in Folder',
     copyLink: 'Copy Link',
+    ignoreSchedule: 'Ignore Download Schedule',
+    followSchedule: 'Follow Download Schedule',
     linkCopied: 'Link copied',
     failedOpenFile: 'Failed to open file',
     failedOpenFolder: 'Failed to open folder',
@@ -137,6 +139,10 @@ export default {
     userAgent: 'User Agent',
     rpcPort: 'RPC Port',
     rpcPortTip: 'Requires restart to take effect',
+    browserIntegration: 'Browser Integration',
+    browserIntegrationTip: 'Lets browser extensions send downloads with their cookies and referer',
+    browserIntegrationPort: 'Browser Integration Port',
+    browserIntegrationToken: 'Browser Integration Token',
     maxOverallDownloadLimit: 'Global Download Limit',
     maxOverallUploadLimit: 'Global Upload Limit',
     allowOverwrite: 'Allow Overwrite',
@@ -188,6 +194,14 @@ export default {
     cookiePlaceholder: 'Custom Cookie value',
     authorization: 'Authorization',
     authorizationPlaceholder: 'e.g. Bearer token123',
+    checksum: 'Checksum',
+    checksumPlaceholder: 'e.g. sha-256=9f86d08...',
+    verifySidecar: 'Verify against published .sha256 / .md5 / SHA256SUMS files',
+    duplicateTitle: 'Already Downloaded',
+    duplicateTask: '{name} is already in the task list. Download it again?',
+    duplicateFile: '{path} already exists. Download it again?',
+    duplicateRestart: 'Restart',
+    duplicateKeepBoth: 'Keep Both',
     cancel: 'Cancel',
     add: 'Add',
     metalink: 'Metalink',
@@ -212,10 +226,61 @@ export default {
     downloading: 'Downloading v{version}...',
     installAndRestart: 'Install & Restart',
   },
+  engine: {
+    crashed: 'Download engine stopped unexpectedly, restarting...',
+    restarted: 'Download engine restarted',
+    failed: 'Download engine could not be restarted',
+    exitCode: 'Exit code: {code}',
+    restartApp: 'Please restart Motrix',
+  },
+  checksum: {
+    verified: 'Checksum verified',
+    mismatch: 'Checksum mismatch',
+    mismatchDetail: '{name} does not match its expected checksum',
+  },
+  errors: {
+    timeout: 'The download engine did not respond in time',
+    disconnected: 'Not connected to the download engine',
+    exit: {
+      1: 'An unknown error occurred',
+      2: 'Timed out',
+      3: 'Resource was not found',
+      4: 'Too many "resource not found" errors',
+      5: 'Download speed was too slow',
+      6: 'Network problem occurred',
+      7: 'There were unfinished downloads',
+      8: 'Remote server does not support resuming',
+      9: 'Not enough disk space',
+      10: 'Piece length differs from the control file',
+      11: 'The same file is already being downloaded',
+      12: 'The same torrent is already being downloaded',
+      13: 'File already exists',
+      14: 'Renaming the file failed',
+      15: 'Could not open the existing file',
+      16: 'Could not create the file',
+      17: 'File I/O error',
+      18: 'Could not create the directory',
+      19: 'Name resolution failed',
+      20: 'Could not parse the Metalink document',
+      21: 'FTP command failed',
+      22: 'Bad or unexpected HTTP response',
+      23: 'Too many redirects',
+      24: 'HTTP authorization failed',
+      25: 'Could not parse the bencoded file',
+      26: 'Torrent file is corrupted or incomplete',
+      27: 'Invalid magnet link',
+      28: 'Bad or unrecognized option',
+      29: 'Remote server is overloaded',
+      30: 'Could not parse the JSON-RPC request',
+      31: 'Reserved',
+      32: 'Checksum validation failed',
+    },
+  },
   tray: {
     show: 'Show Motrix',
     pauseAll: 'Pause All',
     resumeAll: 'Resume All',
     quit: 'Quit',
+    speedProfile: 'Speed: {profile}',
   },
 }
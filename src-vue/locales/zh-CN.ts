@@ -40,6 +40,8 @@ export default {
     openFile: '打开文件',
     showInFolder: '在文件夹中显示',
     copyLink: '复制链接',
+    ignoreSchedule: '不受下载时段限制',
+    followSchedule: '遵循下载时段',
     linkCopied: '链接已复制',
     failedOpenFile: '打开文件失败',
     failedOpenFolder: '打开文件夹失败',
@@ -137,6 +139,10 @@ export default {
     userAgent: '用户代理',
     rpcPort: 'RPC 端口',
     rpcPortTip: '需要重启后生效',
+    browserIntegration: '浏览器集成',
+    browserIntegrationTip: '允许浏览器扩展连同 Cookie 与来源页面一起发送下载',
+    browserIntegrationPort: '浏览器集成端口',
+    browserIntegrationToken: '浏览器集成令牌',
     maxOverallDownloadLimit: '全局下载限速',
     maxOverallUploadLimit: '全局上传限速',
     allowOverwrite: '允许覆盖文件',
@@ -188,6 +194,14 @@ export default {
     cookiePlaceholder: '自定义 Cookie 值',
     authorization: '授权信息',
     authorizationPlaceholder: '例如 Bearer token123',
+    checksum: '校验和',
+    checksumPlaceholder: '例如 sha-256=9f86d08...',
+    verifySidecar: '使用同目录发布的 .sha256 / .md5 / SHA256SUMS 文件校验',
+    duplicateTitle: '重复下载',
+    duplicateTask: '{name} 已在任务列表中，是否重新下载？',
+    duplicateFile: '{path} 已存在，是否重新下载？',
+    duplicateRestart: '重新下载',
+    duplicateKeepBoth: '保留两者',
     cancel: '取消',
     add: '添加',
     metalink: 'Metalink',
@@ -212,10 +226,61 @@ export default {
     downloading: '正在下载 v{version}...',
     installAndRestart: '安装并重启',
   },
+  engine: {
+    crashed: '下载引擎意外退出，正在重启...',
+    restarted: '下载引擎已重启',
+    failed: '下载引擎无法重启',
+    exitCode: '退出码：{code}',
+    restartApp: '请重新启动 Motrix',
+  },
+  checksum: {
+    verified: '校验和验证通过',
+    mismatch: '校验和不匹配',
+    mismatchDetail: '{name} 与预期的校验和不一致',
+  },
+  errors: {
+    timeout: '下载引擎响应超时',
+    disconnected: '未连接到下载引擎',
+    exit: {
+      1: '发生未知错误',
+      2: '超时',
+      3: '资源未找到',
+      4: '“资源未找到”错误过多',
+      5: '下载速度过慢',
+      6: '网络出现问题',
+      7: '存在未完成的下载',
+      8: '远程服务器不支持断点续传',
+      9: '磁盘空间不足',
+      10: '分片大小与控制文件不一致',
+      11: '相同文件正在下载',
+      12: '相同种子正在下载',
+      13: '文件已存在',
+      14: '文件重命名失败',
+      15: '无法打开已有文件',
+      16: '无法创建文件',
+      17: '文件读写错误',
+      18: '无法创建目录',
+      19: '域名解析失败',
+      20: '无法解析 Metalink 文件',
+      21: 'FTP 命令失败',
+      22: 'HTTP 响应头错误或异常',
+      23: '重定向次数过多',
+      24: 'HTTP 认证失败',
+      25: '无法解析 bencode 文件',
+      26: '种子文件损坏或信息缺失',
+      27: '无效的磁力链接',
+      28: '选项错误或无法识别',
+      29: '远程服务器过载',
+      30: '无法解析 JSON-RPC 请求',
+      31: '保留',
+      32: '校验和验证失败',
+    },
+  },
   tray: {
     show: '显示 Motrix',
     pauseAll: '全部暂停',
     resumeAll: '全部恢复',
     quit: '退出',
+    speedProfile: '限速方案：{profile}',
   },
 }
@@ -32,6 +32,8 @@ export const useAppStore = defineStore('app', () => {
           pauseAll: t('tray.pauseAll'),
           resumeAll: t('tray.resumeAll'),
           quit: t('tray.quit'),
+          // Placeholder filled in by the backend
+          speedProfile: t('tray.speedProfile', { profile: '{profile}' }),
         }
       })
     } catch (e) {
@@ -106,6 +108,12 @@ export const useAppStore = defineStore('app', () => {
         btDetachSeedOnly: 'bt-detach-seed-only',
       }
 
+      // The backend speed schedule owns the overall limits while it is enabled
+      if (updated.speedSchedule?.enabled) {
+        delete aria2Keys.maxOverallDownloadLimit
+        delete aria2Keys.maxOverallUploadLimit
+      }
+
       const engineOptions: Record<string, string> = {}
       for (const [configKey, aria2Key] of Object.entries(aria2Keys)) {
         if (configKey in newConfig) {
@@ -217,8 +225,17 @@ export const useAppStore = defineStore('app', () => {
       noProxy: '',
       rpcPort: 16800,
       rpcSecret: '',
+      engineMode: 'builtin',
+      externalRpcScheme: 'ws',
+      externalRpcHost: '127.0.0.1',
+      externalRpcPort: 6800,
+      externalRpcSecret: '',
       maxOverallDownloadLimit: '0',
       maxOverallUploadLimit: '0',
+      speedSchedule: { enabled: false, profiles: [], rules: [] },
+      downloadWindows: { enabled: false, windows: [] },
+      categories: [],
+      browserIntegration: { enabled: false, port: 16801, token: '' },
       allowOverwrite: false,
       autoFileRenaming: true,
       continueDownload: true,
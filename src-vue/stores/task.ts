@@ -1,15 +1,22 @@
 import { defineStore } from 'pinia'
-import { ref, computed } from 'vue'
-import type { Task, GlobalStat, AddTaskOptions } from '@/types'
+import { ref, shallowRef, triggerRef, computed } from 'vue'
+import type { Task, TaskStatus, GlobalStat, AddTaskOptions, GidResult, TaskDelta, WindowState, VerifyRequest, DuplicateAction } from '@/types'
 import { invoke } from '@tauri-apps/api/core'
 
 export type TaskListType = 'active' | 'waiting' | 'stopped'
 export type SortField = 'name' | 'size' | 'progress' | 'speed' | 'default'
 export type SortOrder = 'asc' | 'desc'
 
+const LIST_STATUSES: Record<TaskListType, TaskStatus[]> = {
+  active: ['active', 'waiting', 'paused'],
+  waiting: ['waiting', 'paused'],
+  stopped: ['complete', 'error', 'removed'],
+}
+
 export const useTaskStore = defineStore('task', () => {
   // State
-  const tasks = ref<Task[]>([])
+  // Every task known to the backend poller, kept current by 'task-delta'
+  const taskTable = shallowRef(new Map<string, Task>())
   const currentListType = ref<TaskListType>('active')
   const selectedGids = ref<string[]>([])
   const currentTask = ref<Task | null>(null)
@@ -19,8 +26,15 @@ export const useTaskStore = defineStore('task', () => {
   const searchQuery = ref('')
   const sortField = ref<SortField>('default')
   const sortOrder = ref<SortOrder>('asc')
+  // Tasks flagged to run outside the download windows
+  const ignoredSchedule = ref<string[]>([])
 
   // Getters
+  const tasks = computed(() => {
+    const statuses = LIST_STATUSES[currentListType.value]
+    return [...taskTable.value.values()].filter((t) => statuses.includes(t.status))
+  })
+
   const filteredTasks = computed(() => {
     let result = [...tasks.value]
 
@@ -52,18 +66,16 @@ export const useTaskStore = defineStore('task', () => {
             break
           }
           case 'size':
-            cmp = parseInt(a.totalLength || '0') - parseInt(b.totalLength || '0')
+            cmp = a.totalLength - b.totalLength
             break
           case 'progress': {
-            const pA = parseInt(a.totalLength || '0') > 0
-              ? parseInt(a.completedLength || '0') / parseInt(a.totalLength || '1') : 0
-            const pB = parseInt(b.totalLength || '0') > 0
-              ? parseInt(b.completedLength || '0') / parseInt(b.totalLength || '1') : 0
+            const pA = a.totalLength > 0 ? a.completedLength / a.totalLength : 0
+            const pB = b.totalLength > 0 ? b.completedLength / b.totalLength : 0
             cmp = pA - pB
             break
           }
           case 'speed':
-            cmp = parseInt(a.downloadSpeed || '0') - parseInt(b.downloadSpeed || '0')
+            cmp = a.downloadSpeed - b.downloadSpeed
             break
         }
         return sortOrder.value === 'desc' ? -cmp : cmp
@@ -74,19 +86,68 @@ export const useTaskStore = defineStore('task', () => {
   })
 
   // Actions
-  async function fetchTasks(type?: TaskListType) {
-    const listType = type ?? currentListType.value
+  function applyDelta(delta: TaskDelta) {
+    const table = taskTable.value
+    if (delta.reset) table.clear()
+    for (const task of delta.added) {
+      table.set(task.gid, task)
+    }
+    for (const change of delta.updated) {
+      const task = table.get(change.gid)
+      if (!task) continue
+      const next: Record<string, unknown> = { ...task }
+      for (const [key, value] of Object.entries(change)) {
+        if (value === null) delete next[key]
+        else next[key] = value
+      }
+      table.set(change.gid, next as unknown as Task)
+    }
+    for (const gid of delta.removed) {
+      table.delete(gid)
+    }
+    triggerRef(taskTable)
+    if (delta.stat) globalStat.value = delta.stat
+
+    // Drop selections of tasks that are gone
+    selectedGids.value = selectedGids.value.filter((gid) => table.has(gid))
+  }
 
+  // Seed the table; later changes arrive as 'task-delta'
+  async function loadTasks() {
     try {
-      const result = await invoke<Task[]>('get_task_list', { taskType: listType })
-      tasks.value = result
-      currentListType.value = listType
+      applyDelta(await invoke<TaskDelta>('get_task_snapshot'))
+    } catch (error) {
+      // The engine is still starting; its first delta resets the table
+      console.warn('Failed to load tasks:', error)
+    }
+    try {
+      ignoredSchedule.value = (await invoke<WindowState>('get_download_window_state')).ignored
+    } catch (error) {
+      console.warn('Failed to load download window state:', error)
+    }
+  }
 
-      // Update selected gids (remove non-existent)
-      const gids = new Set(result.map((t) => t.gid))
+  async function setIgnoreSchedule(gid: string, ignore: boolean) {
+    try {
+      const state = await invoke<WindowState>('set_task_ignore_schedule', { gid, ignore })
+      ignoredSchedule.value = state.ignored
+    } catch (error) {
+      console.error('Failed to change task schedule flag:', error)
+    }
+  }
+
+  // Switch the visible list and ask the backend poller for a fresh tick
+  async function fetchTasks(type?: TaskListType) {
+    if (type && type !== currentListType.value) {
+      currentListType.value = type
+      const gids = new Set(tasks.value.map((t) => t.gid))
       selectedGids.value = selectedGids.value.filter((gid) => gids.has(gid))
+    }
+
+    try {
+      await invoke('refresh_tasks')
     } catch (error) {
-      console.error('Failed to fetch tasks:', error)
+      console.error('Failed to refresh tasks:', error)
     }
   }
 
@@ -98,9 +159,14 @@ export const useTaskStore = defineStore('task', () => {
     }
   }
 
-  async function addUri(uris: string[], options?: AddTaskOptions) {
+  async function addUri(
+    uris: string[],
+    options?: AddTaskOptions,
+    verify?: VerifyRequest,
+    onDuplicate?: DuplicateAction,
+  ) {
     try {
-      await invoke('add_uri', { uris, options })
+      await invoke('add_uri', { uris, options, verify, onDuplicate })
       await fetchTasks()
     } catch (error) {
       console.error('Failed to add URI:', error)
@@ -108,9 +174,9 @@ export const useTaskStore = defineStore('task', () => {
     }
   }
 
-  async function addTorrent(torrent: string, options?: AddTaskOptions) {
+  async function addTorrent(torrent: string, options?: AddTaskOptions, onDuplicate?: DuplicateAction) {
     try {
-      await invoke('add_torrent', { torrent, options })
+      await invoke('add_torrent', { torrent, options, onDuplicate })
       await fetchTasks()
     } catch (error) {
       console.error('Failed to add torrent:', error)
@@ -237,34 +303,79 @@ export const useTaskStore = defineStore('task', () => {
   }
 
   // Batch operations
+  function reportBatchFailures(action: string, results: GidResult[]) {
+    for (const result of results) {
+      if (result.error) {
+        console.error(`Failed to ${action} ${result.gid}:`, result.error.message)
+      }
+    }
+  }
+
   async function pauseSelectedTasks() {
     const gids = [...selectedGids.value]
-    await Promise.all(gids.map(gid => {
-      const task = tasks.value.find(t => t.gid === gid)
-      const isBT = task?.bittorrent !== undefined
-      return invoke(isBT ? 'force_pause_task' : 'pause_task', { gid }).catch(e =>
-        console.error(`Failed to pause ${gid}:`, e)
-      )
-    }))
+    // BT tasks need a force pause
+    const btGids = gids.filter(gid => tasks.value.find(t => t.gid === gid)?.bittorrent !== undefined)
+    const otherGids = gids.filter(gid => !btGids.includes(gid))
+    try {
+      const results = await Promise.all([
+        btGids.length > 0 ? invoke<GidResult[]>('pause_tasks', { gids: btGids, force: true }) : [],
+        otherGids.length > 0 ? invoke<GidResult[]>('pause_tasks', { gids: otherGids, force: false }) : [],
+      ])
+      reportBatchFailures('pause', results.flat())
+    } catch (error) {
+      console.error('Failed to pause selected tasks:', error)
+    }
     await fetchTasks()
   }
 
   async function resumeSelectedTasks() {
     const gids = [...selectedGids.value]
-    await Promise.all(gids.map(gid =>
-      invoke('resume_task', { gid }).catch(e =>
-        console.error(`Failed to resume ${gid}:`, e)
-      )
-    ))
+    try {
+      reportBatchFailures('resume', await invoke<GidResult[]>('resume_tasks', { gids }))
+    } catch (error) {
+      console.error('Failed to resume selected tasks:', error)
+    }
     await fetchTasks()
   }
 
   async function removeSelectedTasks(deleteFiles = false) {
     const gids = [...selectedGids.value]
-    for (const gid of gids) {
-      await removeTask(gid, deleteFiles)
+    if (deleteFiles) {
+      const filePaths = tasks.value
+        .filter(t => gids.includes(t.gid))
+        .flatMap(t => t.files?.map(f => f.path) || [])
+        .filter(p => p && p.length > 0)
+      if (filePaths.length > 0) {
+        try {
+          await invoke('delete_task_files', { filePaths })
+        } catch (e) {
+          console.warn('Failed to delete some files:', e)
+        }
+      }
+    }
+    try {
+      // Stopped tasks only have a record left to remove
+      const stopped = new Set(tasks.value.filter(t => LIST_STATUSES.stopped.includes(t.status)).map(t => t.gid))
+      const running = gids.filter(gid => !stopped.has(gid))
+      const [removed, records] = await Promise.all([
+        running.length > 0 ? invoke<GidResult[]>('remove_tasks', { gids: running, force: true }) : [],
+        stopped.size > 0 ? invoke<GidResult[]>('remove_task_records', { gids: gids.filter(gid => stopped.has(gid)) }) : [],
+      ])
+      reportBatchFailures('remove', [...removed, ...records])
+      // Also drop the records the removed tasks leave; ones not stopped yet are skipped
+      const cleared = removed.filter(r => !r.error).map(r => r.gid)
+      if (cleared.length > 0) {
+        await invoke('remove_task_records', { gids: cleared }).catch(() => {})
+      }
+    } catch (error) {
+      console.error('Failed to remove selected tasks:', error)
+    }
+    if (currentTask.value && gids.includes(currentTask.value.gid)) {
+      currentTask.value = null
+      detailVisible.value = false
     }
     clearSelection()
+    await fetchTasks()
   }
 
   async function pauseAllTasks() {
@@ -357,9 +468,12 @@ export const useTaskStore = defineStore('task', () => {
     searchQuery,
     sortField,
     sortOrder,
+    ignoredSchedule,
     // Getters
     filteredTasks,
     // Actions
+    applyDelta,
+    loadTasks,
     fetchTasks,
     fetchGlobalStat,
     addUri,
@@ -385,5 +499,6 @@ export const useTaskStore = defineStore('task', () => {
     retryTask,
     moveTaskUp,
     moveTaskDown,
+    setIgnoreSchedule,
   }
 })
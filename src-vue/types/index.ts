@@ -2,13 +2,15 @@
 export interface Task {
   gid: string
   status: TaskStatus
-  totalLength: string
-  completedLength: string
-  uploadLength: string
-  downloadSpeed: string
-  uploadSpeed: string
-  connections: string
-  numSeeders?: string
+  totalLength: number
+  completedLength: number
+  uploadLength: number
+  downloadSpeed: number
+  uploadSpeed: number
+  connections: number
+  numSeeders?: number
+  seeder?: boolean
+  infoHash?: string
   dir: string
   files: TaskFile[]
   bittorrent?: BittorrentInfo
@@ -19,24 +21,24 @@ export interface Task {
 export type TaskStatus = 'active' | 'waiting' | 'paused' | 'error' | 'complete' | 'removed'
 
 export interface TaskFile {
-  index: string
+  index: number
   path: string
-  length: string
-  completedLength: string
-  selected: string
+  length: number
+  completedLength: number
+  selected: boolean
   uris: TaskUri[]
 }
 
 export interface TaskUri {
   uri: string
-  status: string
+  status: 'used' | 'waiting'
 }
 
 export interface BittorrentInfo {
   announceList?: string[][]
   comment?: string
   creationDate?: number
-  mode?: string
+  mode?: 'single' | 'multi'
   info?: {
     name: string
   }
@@ -44,12 +46,145 @@ export interface BittorrentInfo {
 
 // Global statistics
 export interface GlobalStat {
-  downloadSpeed: string
-  uploadSpeed: string
-  numActive: string
-  numWaiting: string
-  numStopped: string
-  numStoppedTotal: string
+  downloadSpeed: number
+  uploadSpeed: number
+  numActive: number
+  numWaiting: number
+  numStopped: number
+  numStoppedTotal: number
+}
+
+// Task table changes pushed as 'task-delta' (get_task_snapshot returns a reset delta)
+export interface TaskDelta {
+  // Replace the whole table with `added`
+  reset?: boolean
+  added: Task[]
+  // Changed fields only; fields that disappeared are null
+  updated: (Partial<Record<keyof Task, unknown>> & { gid: string })[]
+  removed: string[]
+  stat?: GlobalStat
+}
+
+// Arguments of query_tasks; every field is optional
+export interface TaskQuery {
+  status?: TaskStatus[]
+  // Case-insensitive substring of the task name or a URI
+  search?: string
+  // 'added' keeps aria2's own order
+  sort?: 'added' | 'speed' | 'progress' | 'size'
+  descending?: boolean
+  offset?: number
+  limit?: number
+  // Fields to fetch from aria2 (gid and status are always included)
+  keys?: (keyof Task)[]
+}
+
+export interface TaskPage {
+  tasks: Task[]
+  total: number
+}
+
+// Download history record (search_history)
+export interface HistoryEntry {
+  gid: string
+  name: string
+  uris: string[]
+  infoHash?: string
+  dir: string
+  files: string[]
+  totalLength: number
+  completedLength: number
+  // Options that differed from the global ones
+  options: Aria2Options
+  status: TaskStatus
+  errorCode?: string
+  errorMessage?: string
+  // Unix time in milliseconds
+  addedAt: number
+  finishedAt?: number
+  checksum?: ChecksumStatus
+  category?: TaskCategory
+}
+
+export interface HistoryQuery {
+  search?: string
+  status?: TaskStatus[]
+  offset?: number
+  limit?: number
+}
+
+export interface HistoryPage {
+  entries: HistoryEntry[]
+  total: number
+}
+
+// BitTorrent peer
+export interface Peer {
+  peerId: string
+  ip: string
+  port: number
+  bitfield: string
+  amChoking: boolean
+  peerChoking: boolean
+  downloadSpeed: number
+  uploadSpeed: number
+  seeder: boolean
+}
+
+// Mirrors a file is downloaded from (get_task_servers)
+export interface FileServers {
+  index: number
+  servers: Server[]
+}
+
+export interface Server {
+  uri: string
+  currentUri: string
+  downloadSpeed: number
+}
+
+// Outcome of change_task_uri
+export interface UriChange {
+  deleted: number
+  added: number
+}
+
+// Task or global aria2 options, all values are strings
+export type Aria2Options = Record<string, string>
+
+export interface SessionInfo {
+  sessionId: string
+}
+
+// Error returned by backend commands
+export interface AppError {
+  kind:
+    | 'io' | 'json' | 'rpc' | 'download' | 'timeout' | 'disconnected' | 'protocol'
+    | 'validation' | 'websocket' | 'config' | 'tauri' | 'store' | 'custom' | 'duplicate' | 'unsupported'
+  // aria2 RPC error code (kind 'rpc') or download exit code (kind 'download')
+  code: number | null
+  message: string
+  // What the new download duplicates (kind 'duplicate')
+  duplicate?: Duplicate
+}
+
+// An existing task or file a new download duplicates
+export interface Duplicate {
+  reason: 'uri' | 'infoHash' | 'file'
+  // Absent when only the file exists
+  gid?: string
+  status?: TaskStatus
+  name: string
+  path?: string
+}
+
+// What to do with a duplicate download (onDuplicate of add_uri / add_torrent)
+export type DuplicateAction = 'ask' | 'skip' | 'restart' | 'keepBoth'
+
+// Per-gid outcome of a batch operation
+export interface GidResult {
+  gid: string
+  error?: AppError
 }
 
 // Configuration types
@@ -89,8 +224,17 @@ export interface AppConfig {
   noProxy: string
   rpcPort: number
   rpcSecret: string
+  engineMode: 'builtin' | 'external'
+  externalRpcScheme: 'ws' | 'wss' | 'http' | 'https'
+  externalRpcHost: string
+  externalRpcPort: number
+  externalRpcSecret: string
   maxOverallDownloadLimit: string
   maxOverallUploadLimit: string
+  speedSchedule: SpeedSchedule
+  downloadWindows: DownloadWindows
+  categories: CategoryRule[]
+  browserIntegration: BrowserIntegration
   allowOverwrite: boolean
   autoFileRenaming: boolean
   continueDownload: boolean
@@ -102,6 +246,128 @@ export interface AppConfig {
   btDetachSeedOnly: boolean
 }
 
+// Local endpoint browser extensions POST captured downloads to (127.0.0.1 only)
+export interface BrowserIntegration {
+  enabled: boolean
+  port: number
+  // Sent by extensions as "Authorization: Bearer <token>"
+  token: string
+}
+
+// Recurring stretch of local time; an end before the start runs past midnight
+export type Weekday = 'mon' | 'tue' | 'wed' | 'thu' | 'fri' | 'sat' | 'sun'
+
+export interface TimeWindow {
+  // Empty means every day
+  days: Weekday[]
+  // HH:MM
+  start: string
+  end: string
+}
+
+export interface SpeedProfile {
+  name: string
+  // aria2 size syntax, '0' is unlimited
+  downloadLimit: string
+  uploadLimit: string
+}
+
+export interface SpeedRule extends TimeWindow {
+  profile: string
+}
+
+// Weekly speed schedule; the first matching rule wins
+export interface SpeedSchedule {
+  enabled: boolean
+  profiles: SpeedProfile[]
+  rules: SpeedRule[]
+}
+
+// Hours the download queue may run in; tasks are paused outside them
+export interface DownloadWindows {
+  enabled: boolean
+  windows: TimeWindow[]
+}
+
+// Routes matching downloads to a folder; every criterion set must match,
+// rules are checked in order and the first match wins
+export interface CategoryRule {
+  name: string
+  // Without the dot, e.g. 'mkv' or 'tar.gz'
+  extensions: string[]
+  // Guessed from the file name; 'video/*' matches a whole type
+  mimeTypes: string[]
+  // Subdomains match too
+  domains: string[]
+  // Regular expression searched for in the URIs
+  urlPattern?: string
+  // Relative folders sit in the download dir
+  dir: string
+  maxDownloadLimit?: string
+  tag?: string
+}
+
+// Category a task was routed to (get_task_categories)
+export interface TaskCategory {
+  name: string
+  tag?: string
+}
+
+// Tasks the download windows act on (get_download_window_state)
+export interface WindowState {
+  // Flagged "ignore schedule"
+  ignored: string[]
+  // Paused outside the window, resumed when it opens
+  held: string[]
+}
+
+// Limits in effect (get_speed_profile, speed-profile event)
+export interface SpeedState {
+  // null means the overall limits from the settings
+  profile: string | null
+  downloadLimit: string
+  uploadLimit: string
+  overridden: boolean
+  // Unix time in milliseconds the override ends at
+  until?: number
+}
+
+// Checksum verification after completion (add_uri)
+export interface VerifyRequest {
+  // aria2 checksum syntax, e.g. 'sha-256=<hex>'
+  expectedChecksum?: string
+  // Look for .sha256 / .md5 / SHA256SUMS files next to the URI
+  sidecar?: boolean
+}
+
+export type ChecksumStatus = 'verified' | 'mismatch' | 'unavailable'
+
+// Payload of the 'task-checksum' event
+export interface ChecksumReport {
+  gid: string
+  status: ChecksumStatus
+  files: {
+    path: string
+    status: ChecksumStatus
+    expected?: string
+    actual?: string
+    // Sidecar the expected digest came from
+    source?: string
+  }[]
+}
+
+// Payload of the 'open-urls' event: a command line of this or a second instance
+export interface LaunchRequest {
+  // URLs and absolute torrent / metalink paths
+  uris: string[]
+  dir?: string
+  out?: string
+  headers?: string[]
+  paused: boolean
+  hidden: boolean
+  quitWhenDone: boolean
+}
+
 // Add task options
 export interface AddTaskOptions {
   dir?: string
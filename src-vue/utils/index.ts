@@ -1,3 +1,5 @@
+import type { AppError } from '@/types'
+
 /**
  * Format bytes to human readable string
  */
@@ -92,18 +94,33 @@ export function isBtTask(task: { bittorrent?: unknown }): boolean {
 }
 
 /**
- * Decode thunder:// URL to real download URL
- * thunder:// format: thunder://BASE64(AA<real_url>ZZ)
+ * Check whether a rejected invoke() carries a backend error
+ */
+export function isAppError(error: unknown): error is AppError {
+  return typeof error === 'object' && error !== null && 'kind' in error && 'message' in error
+}
+
+/**
+ * Localized message for a download exit code, or '' if unknown
+ */
+export function exitCodeMessage(code: number | string | null | undefined, t: (key: string) => string): string {
+  const n = Number(code)
+  return Number.isInteger(n) && n >= 1 && n <= 32 ? t(`errors.exit.${n}`) : ''
+}
+
+/**
+ * Human readable, localized message for any error thrown by invoke()
  */
-export function decodeThunderUrl(url: string): string {
-  if (!url.toLowerCase().startsWith('thunder://')) return url
-  try {
-    const encoded = url.slice(10) // Skip "thunder://"
-    const decoded = atob(encoded)
-    // thunder wraps URL with "AA" prefix and "ZZ" suffix
-    const trimmed = decoded.replace(/^AA/, '').replace(/ZZ$/, '')
-    return trimmed || url
-  } catch {
-    return url
+export function formatError(error: unknown, t: (key: string) => string): string {
+  if (!isAppError(error)) return String(error)
+  switch (error.kind) {
+    case 'timeout':
+      return t('errors.timeout')
+    case 'disconnected':
+      return t('errors.disconnected')
+    case 'download':
+      return exitCodeMessage(error.code, t) || error.message
+    default:
+      return error.message
   }
 }